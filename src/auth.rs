@@ -0,0 +1,612 @@
+use crate::crypto;
+use crate::middleware::{Middleware, Next};
+use crate::header;
+use crate::request::{Request, RequestPath};
+use crate::response::Response;
+use crate::status::Status;
+use sha2::Digest;
+use std::collections::{HashMap, VecDeque};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type BasicVerifier<P> = dyn Fn(&str, &str) -> Option<P> + Send + Sync;
+type BearerVerifier<P> = dyn Fn(&str) -> Option<P> + Send + Sync;
+type DigestLookup<P> = dyn Fn(&str) -> Option<(String, P)> + Send + Sync;
+
+/// Middleware authenticating the clients with HTTP Basic authentication (see [RFC 7617](https://tools.ietf.org/html/rfc7617)).
+///
+/// The verifier receives the user name and the password and returns the authenticated principal (your user type),
+/// which is added to the request extensions, or None to reject the request with 401 Unauthorized.
+///
+/// Basic authentication sends the password in clear text, use it only behind HTTPS.
+///
+/// ## Example:
+/// ```
+/// use rest_server::auth::BasicAuth;
+/// use rest_server::Server;
+///
+/// #[derive(Clone)]
+/// struct User(String);
+///
+/// let mut app = Server::new();
+/// app.middleware_at(String::from("/admin"), Box::new(BasicAuth::new(String::from("admin"), Box::new(|user: &str, password: &str| {
+///     if user == "admin" && password == "secret" { Some(User(user.to_string())) } else { None }
+/// }))));
+/// // in the handlers: request.extensions().get::<User>()
+/// ```
+pub struct BasicAuth<P> {
+    realm: String,
+    verifier: Box<BasicVerifier<P>>,
+}
+
+impl<P: Send + Sync + 'static> BasicAuth<P> {
+
+    /// Create a new BasicAuth middleware, realm is shown by the browsers in the login prompt.
+    pub fn new(realm: String, verifier: Box<BasicVerifier<P>>) -> Self {
+        Self {
+            realm,
+            verifier,
+        }
+    }
+
+    /// Return the user name and the password sent in the Authorization header.
+    fn credentials(request: &Request) -> Option<(String, String)> {
+        let encoded = authorization(request, "Basic")?;
+        let decoded = String::from_utf8(crypto::base64_decode_standard(encoded)?).ok()?;
+        let (user, password) = decoded.split_once(':')?;
+        Some((user.to_string(), password.to_string()))
+    }
+}
+
+impl<P: Send + Sync + 'static> Middleware for BasicAuth<P> {
+    fn handle(&self, mut request: Request, response: Response, next: Next) {
+        let principal = Self::credentials(&request).and_then(|(user, password)| (self.verifier)(&user, &password));
+        match principal {
+            Some(principal) => {
+                request.extensions_mut().insert(principal);
+                next.run(request, response);
+            },
+            None => challenge(response, Status::Unauthorized, format!("Basic realm={}, charset=\"UTF-8\"", quote(&self.realm))),
+        }
+    }
+}
+
+/// Middleware authenticating the clients with a bearer token (see [RFC 6750](https://tools.ietf.org/html/rfc6750)).
+///
+/// The verifier receives the token and returns the authenticated principal, which is added to the request extensions,
+/// or None to reject the request with 401 Unauthorized.
+pub struct BearerAuth<P> {
+    realm: String,
+    verifier: Box<BearerVerifier<P>>,
+}
+
+impl<P: Send + Sync + 'static> BearerAuth<P> {
+
+    /// Create a new BearerAuth middleware.
+    pub fn new(realm: String, verifier: Box<BearerVerifier<P>>) -> Self {
+        Self {
+            realm,
+            verifier,
+        }
+    }
+}
+
+impl<P: Send + Sync + 'static> Middleware for BearerAuth<P> {
+    fn handle(&self, mut request: Request, response: Response, next: Next) {
+        match bearer_token(&request) {
+            Ok(Some(token)) => match (self.verifier)(token) {
+                Some(principal) => {
+                    request.extensions_mut().insert(principal);
+                    next.run(request, response);
+                },
+                None => bearer_challenge(response, &self.realm, Some(("invalid_token", "The access token is invalid"))),
+            },
+            Ok(None) => bearer_challenge(response, &self.realm, None),
+            Err(()) => bearer_challenge(response, &self.realm, Some(("invalid_request", "Malformed Authorization header"))),
+        }
+    }
+}
+
+/// Hash algorithms of HTTP Digest authentication.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DigestAlgorithm {
+    /// MD5, weak but the only algorithm supported by many old clients.
+    Md5,
+    /// SHA-256.
+    Sha256,
+}
+
+impl DigestAlgorithm {
+
+    /// Return the name of the algorithm as written in the `algorithm` parameter.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            DigestAlgorithm::Md5 => "MD5",
+            DigestAlgorithm::Sha256 => "SHA-256",
+        }
+    }
+
+    /// Return the algorithm from its name, return None if the algorithm isn't supported.
+    pub fn from_name(name: &str) -> Option<DigestAlgorithm> {
+        if name.eq_ignore_ascii_case("MD5") {
+            Some(DigestAlgorithm::Md5)
+        } else if name.eq_ignore_ascii_case("SHA-256") {
+            Some(DigestAlgorithm::Sha256)
+        } else {
+            None
+        }
+    }
+
+    /// Return the lowercase hexadecimal hash of data.
+    fn hash(&self, data: &str) -> String {
+        let hash = match self {
+            DigestAlgorithm::Md5 => md5::Md5::digest(data.as_bytes()).to_vec(),
+            DigestAlgorithm::Sha256 => sha2::Sha256::digest(data.as_bytes()).to_vec(),
+        };
+        hash.iter().map(|byte| format!("{:02x}", byte)).collect()
+    }
+}
+
+/// State of a nonce sent in a challenge.
+struct Nonce {
+    created: Instant,
+    /// Highest nonce count used by the client.
+    count: u32,
+}
+
+/// Nonces sent in the challenges, with their creation order so the oldest are expired first without scanning the table.
+#[derive(Default)]
+struct Nonces {
+    states: HashMap<String, Nonce>,
+    /// Nonces from the oldest to the newest, may still contain nonces already removed from states.
+    order: VecDeque<(Instant, String)>,
+}
+
+/// Middleware authenticating the clients with HTTP Digest authentication (see [RFC 7616](https://tools.ietf.org/html/rfc7616)).
+///
+/// Only `qop=auth` is supported. The lookup receives the user name and returns the password of the user and the authenticated principal,
+/// which is added to the request extensions, or None to reject the request with 401 Unauthorized.
+///
+/// The nonces are kept in memory: a nonce expires after the nonce lifetime (5 minutes by default),
+/// the client is then asked to retry with a new nonce (`stale=true`), and each request must use a nonce count
+/// higher than the previous one, so a captured request can't be replayed.
+/// Each challenge creates one nonce (shared by the offered algorithms), when the maximum number of nonces is reached
+/// the oldest ones are forgotten.
+///
+/// ## Example:
+/// ```
+/// use rest_server::auth::{DigestAlgorithm, DigestAuth};
+/// use rest_server::Server;
+///
+/// #[derive(Clone)]
+/// struct User(String);
+///
+/// let mut app = Server::new();
+/// let digest = DigestAuth::new(String::from("device"), Box::new(|user: &str| {
+///     if user == "admin" { Some((String::from("secret"), User(user.to_string()))) } else { None }
+/// })).algorithms(vec![DigestAlgorithm::Sha256, DigestAlgorithm::Md5]);
+/// app.middleware_at(String::from("/device"), Box::new(digest));
+/// ```
+pub struct DigestAuth<P> {
+    realm: String,
+    lookup: Box<DigestLookup<P>>,
+    algorithms: Vec<DigestAlgorithm>,
+    nonce_lifetime: Duration,
+    max_nonces: usize,
+    opaque: String,
+    nonces: Mutex<Nonces>,
+}
+
+impl<P: Send + Sync + 'static> DigestAuth<P> {
+
+    /// Create a new DigestAuth middleware offering SHA-256 and MD5.
+    pub fn new(realm: String, lookup: Box<DigestLookup<P>>) -> Self {
+        Self {
+            realm,
+            lookup,
+            algorithms: vec![DigestAlgorithm::Sha256, DigestAlgorithm::Md5],
+            nonce_lifetime: Duration::from_secs(300),
+            max_nonces: 10_000,
+            opaque: crypto::random_token(16),
+            nonces: Mutex::new(Nonces::default()),
+        }
+    }
+
+    /// Set the algorithms offered to the clients, in order of preference, a challenge is sent for each of them.
+    pub fn algorithms(mut self, algorithms: Vec<DigestAlgorithm>) -> Self {
+        self.algorithms = algorithms;
+        self
+    }
+
+    /// Set how long a nonce can be used.
+    pub fn nonce_lifetime(mut self, nonce_lifetime: Duration) -> Self {
+        self.nonce_lifetime = nonce_lifetime;
+        self
+    }
+
+    /// Set the maximum number of nonces kept in memory, the oldest nonces are forgotten when the limit is reached.
+    pub fn max_nonces(mut self, max_nonces: usize) -> Self {
+        self.max_nonces = max_nonces.max(1);
+        self
+    }
+
+    /// Create a new nonce and remember it.
+    fn new_nonce(&self) -> String {
+        let nonce = crypto::random_token(24);
+        let now = Instant::now();
+        let mut nonces = self.nonces.lock().unwrap();
+        while let Some((created, _)) = nonces.order.front() {
+            if nonces.order.len() < self.max_nonces && now.duration_since(*created) < self.nonce_lifetime {
+                break;
+            }
+            let (_, oldest) = nonces.order.pop_front().unwrap();
+            nonces.states.remove(&oldest);
+        }
+        nonces.order.push_back((now, nonce.clone()));
+        nonces.states.insert(nonce.clone(), Nonce {
+            created: now,
+            count: 0,
+        });
+        nonce
+    }
+
+    /// Check the nonce and its count, return Err(true) if the nonce has expired and Err(false) if it's unknown or replayed.
+    fn use_nonce(&self, nonce: &str, count: u32) -> Result<(), bool> {
+        let mut nonces = self.nonces.lock().unwrap();
+        let state = nonces.states.get_mut(nonce).ok_or(false)?;
+        if state.created.elapsed() >= self.nonce_lifetime {
+            nonces.states.remove(nonce);
+            return Err(true);
+        }
+        if count <= state.count {
+            return Err(false);
+        }
+        state.count = count;
+        Ok(())
+    }
+
+    /// Send a challenge for each algorithm with the same new nonce, with stale=true if the client used an expired nonce.
+    fn challenge(&self, mut response: Response, stale: bool) {
+        let nonce = self.new_nonce();
+        for algorithm in &self.algorithms {
+            response.append_header(String::from("WWW-Authenticate"), format!(
+                "Digest realm={}, qop=\"auth\", algorithm={}, nonce={}, opaque={}, charset=UTF-8, userhash=false{}",
+                quote(&self.realm), algorithm.as_str(), quote(&nonce), quote(&self.opaque), if stale { ", stale=true" } else { "" },
+            ));
+        }
+        response.set_status(Status::Unauthorized);
+        response.set_header(String::from("Content-Type"), String::from("text/plain; charset=utf-8"));
+        response.set_body(Status::Unauthorized.as_str());
+        response.send();
+    }
+
+    /// Verify the Digest credentials, return the principal, Err(true) if the nonce has expired and Err(false) if the credentials are invalid.
+    fn authenticate(&self, request: &Request, params: &HashMap<String, String>) -> Result<P, bool> {
+        let param = |name: &str| params.get(name).map(String::as_str).ok_or(false);
+        let algorithm = match params.get("algorithm") {
+            Some(name) => DigestAlgorithm::from_name(name).ok_or(false)?,
+            None => DigestAlgorithm::Md5,
+        };
+        if !self.algorithms.contains(&algorithm) || param("realm")? != self.realm || params.get("opaque").is_some_and(|opaque| *opaque != self.opaque) {
+            return Err(false);
+        }
+        if !param("qop")?.eq_ignore_ascii_case("auth") {
+            return Err(false);
+        }
+        let uri = param("uri")?;
+        let uri_path = uri.split_once("://").and_then(|(_, rest)| rest.find('/').map(|start| &rest[start..])).unwrap_or(uri);
+        if RequestPath::new(uri_path.to_string()) != request.path {
+            return Err(false);
+        }
+        let username = match params.get("username*") {
+            Some(extended) => header::decode_ext_value(extended).ok_or(false)?,
+            None => param("username")?.to_string(),
+        };
+        let nc = param("nc")?;
+        let count = u32::from_str_radix(nc, 16).map_err(|_| false)?;
+        let (nonce, cnonce, expected) = (param("nonce")?, param("cnonce")?, param("response")?);
+        let (password, principal) = (self.lookup)(&username).ok_or(false)?;
+
+        let ha1 = algorithm.hash(&format!("{}:{}:{}", username, self.realm, password));
+        let ha2 = algorithm.hash(&format!("{}:{}", request.method.as_str(), uri));
+        let computed = algorithm.hash(&format!("{}:{}:{}:{}:auth:{}", ha1, nonce, nc, cnonce, ha2));
+        if !crypto::constant_time_eq(computed.as_bytes(), expected.to_ascii_lowercase().as_bytes()) {
+            return Err(false);
+        }
+        // The nonce is only consumed once the client has proved it knows the password
+        self.use_nonce(nonce, count)?;
+        Ok(principal)
+    }
+}
+
+impl<P: Send + Sync + 'static> Middleware for DigestAuth<P> {
+    fn handle(&self, mut request: Request, response: Response, next: Next) {
+        if request.get_header("Authorization").is_none() {
+            return self.challenge(response, false);
+        }
+        let params = match authorization(&request, "Digest").and_then(parse_auth_params) {
+            Some(params) => params,
+            None => return self.challenge(response, false),
+        };
+        match self.authenticate(&request, &params) {
+            Ok(principal) => {
+                request.extensions_mut().insert(principal);
+                next.run(request, response);
+            },
+            Err(stale) => self.challenge(response, stale),
+        }
+    }
+}
+
+/// Parse the comma separated name=value parameters of an Authorization header, the values can be tokens or quoted-strings.
+///
+/// The names are converted to lowercase, return None if the parameters are malformed.
+fn parse_auth_params(input: &str) -> Option<HashMap<String, String>> {
+    let mut params = HashMap::new();
+    let mut chars = input.chars().peekable();
+    loop {
+        while chars.next_if(|c| c.is_whitespace() || *c == ',').is_some() {}
+        if chars.peek().is_none() {
+            return Some(params);
+        }
+        let mut name = String::new();
+        while let Some(c) = chars.next_if(|c| *c != '=' && *c != ',' && !c.is_whitespace()) {
+            name.push(c.to_ascii_lowercase());
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        if chars.next() != Some('=') {
+            return None;
+        }
+        while chars.next_if(|c| c.is_whitespace()).is_some() {}
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' => break,
+                    '\\' => value.push(chars.next()?),
+                    c => value.push(c),
+                }
+            }
+        } else {
+            while let Some(c) = chars.next_if(|c| *c != ',' && !c.is_whitespace()) {
+                value.push(c);
+            }
+        }
+        params.insert(name, value);
+    }
+}
+
+/// Return the credentials of the Authorization header if it uses the given scheme (case insensitive).
+pub(crate) fn authorization<'a>(request: &'a Request, scheme: &str) -> Option<&'a str> {
+    let (request_scheme, credentials) = request.get_header("Authorization")?.trim().split_once(' ')?;
+    if request_scheme.eq_ignore_ascii_case(scheme) {
+        Some(credentials.trim())
+    } else {
+        None
+    }
+}
+
+/// Return the bearer token of the request, Ok(None) if the client didn't send one and Err if the Authorization header is malformed.
+pub(crate) fn bearer_token(request: &Request) -> Result<Option<&str>, ()> {
+    if request.get_header("Authorization").is_none() {
+        return Ok(None);
+    }
+    match authorization(request, "Bearer") {
+        Some(token) if !token.is_empty() && !token.contains(' ') => Ok(Some(token)),
+        _ => Err(()),
+    }
+}
+
+/// Reject the request with a Bearer challenge, error is the error code and its description as defined by RFC 6750.
+///
+/// A malformed request is answered with 400 Bad Request, other errors with 401 Unauthorized.
+pub(crate) fn bearer_challenge(response: Response, realm: &str, error: Option<(&str, &str)>) {
+    match error {
+        Some((code, description)) => {
+            let status = if code == "invalid_request" { Status::BadRequest } else { Status::Unauthorized };
+            challenge(response, status, format!("Bearer realm={}, error=\"{}\", error_description={}", quote(realm), code, quote(description)));
+        },
+        None => challenge(response, Status::Unauthorized, format!("Bearer realm={}", quote(realm))),
+    }
+}
+
+/// Send the response with the given status and WWW-Authenticate header.
+pub(crate) fn challenge(mut response: Response, status: Status, www_authenticate: String) {
+    response.set_status(status);
+    response.set_header(String::from("WWW-Authenticate"), www_authenticate);
+    response.set_header(String::from("Content-Type"), String::from("text/plain; charset=utf-8"));
+    response.set_body(status.as_str());
+    response.send();
+}
+
+/// Return the value as a quoted-string, escaping the double quotes and backslashes.
+pub(crate) fn quote(value: &str) -> String {
+    format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::testing;
+    use std::sync::Arc;
+
+    const REALM: &str = "http-auth@example.org";
+    const NONCE: &str = "7ypf/xlj9XXwfDPEoM4URrv/xwf94BcCAzFZH4GiTo0v";
+    const CNONCE: &str = "f2/wE4q74E6zIJEtWaHKaf5wv/H5QzzpXusqGemxURZJ";
+    const OPAQUE: &str = "FQhe/qaU925kfnzjCev0ciny7QMkPqMAFRtzCUYo5tdS";
+
+    /// DigestAuth with the user, nonce and opaque of the RFC 7616 examples.
+    fn rfc_digest() -> DigestAuth<String> {
+        let mut digest = DigestAuth::new(String::from(REALM), Box::new(|user: &str| {
+            if user == "Mufasa" || user == "Jäsøn Doe" { Some((String::from("Circle of Life"), user.to_string())) } else { None }
+        }));
+        digest.opaque = String::from(OPAQUE);
+        remember(&digest, NONCE);
+        digest
+    }
+
+    fn remember(digest: &DigestAuth<String>, nonce: &str) {
+        let mut nonces = digest.nonces.lock().unwrap();
+        nonces.order.push_back((Instant::now(), nonce.to_string()));
+        nonces.states.insert(nonce.to_string(), Nonce {
+            created: Instant::now(),
+            count: 0,
+        });
+    }
+
+    fn authorization(algorithm: &str, uri: &str, nc: &str, response: &str) -> String {
+        format!(
+            "Digest username=\"Mufasa\", realm=\"{}\", uri=\"{}\", algorithm={}, nonce=\"{}\", nc={}, cnonce=\"{}\", qop=auth, response=\"{}\", opaque=\"{}\"",
+            REALM, uri, algorithm, NONCE, nc, CNONCE, response, OPAQUE,
+        )
+    }
+
+    fn authenticate(digest: &DigestAuth<String>, authorization: &str) -> Result<String, bool> {
+        let request = testing::request(Method::GET, "/dir/index.html", &[("Authorization", authorization)]);
+        let params = super::authorization(&request, "Digest").and_then(parse_auth_params).unwrap();
+        digest.authenticate(&request, &params)
+    }
+
+    /// Compute the response parameter like a client would.
+    fn response(algorithm: DigestAlgorithm, username: &str, nc: &str, uri: &str) -> String {
+        let ha1 = algorithm.hash(&format!("{}:{}:Circle of Life", username, REALM));
+        let ha2 = algorithm.hash(&format!("GET:{}", uri));
+        algorithm.hash(&format!("{}:{}:{}:{}:auth:{}", ha1, NONCE, nc, CNONCE, ha2))
+    }
+
+    #[test]
+    fn rfc_7616_examples() {
+        // section 3.9.1
+        let md5 = authorization("MD5", "/dir/index.html", "00000001", "8ca523f5e9506fed4657c9700eebdbec");
+        assert_eq!(authenticate(&rfc_digest(), &md5), Ok(String::from("Mufasa")));
+        let sha256 = authorization("SHA-256", "/dir/index.html", "00000001", "753927fa0e85d155564e2e272a28d1802ca10daf4496794697cf8db5856cb6c1");
+        assert_eq!(authenticate(&rfc_digest(), &sha256), Ok(String::from("Mufasa")));
+        assert_eq!(response(DigestAlgorithm::Md5, "Mufasa", "00000001", "/dir/index.html"), "8ca523f5e9506fed4657c9700eebdbec");
+    }
+
+    #[test]
+    fn reject_replayed_nonce_count() {
+        let digest = rfc_digest();
+        let first = authorization("MD5", "/dir/index.html", "00000001", "8ca523f5e9506fed4657c9700eebdbec");
+        assert!(authenticate(&digest, &first).is_ok());
+        assert_eq!(authenticate(&digest, &first), Err(false));
+        let second = authorization("MD5", "/dir/index.html", "00000002", &response(DigestAlgorithm::Md5, "Mufasa", "00000002", "/dir/index.html"));
+        assert!(authenticate(&digest, &second).is_ok());
+        assert_eq!(authenticate(&digest, &first), Err(false));
+    }
+
+    #[test]
+    fn reject_invalid_credentials() {
+        let digest = rfc_digest();
+        let valid = response(DigestAlgorithm::Sha256, "Mufasa", "00000001", "/dir/index.html");
+        // wrong response
+        assert_eq!(authenticate(&digest, &authorization("SHA-256", "/dir/index.html", "00000001", &"0".repeat(64))), Err(false));
+        // the uri must be the path of the request
+        let other = response(DigestAlgorithm::Sha256, "Mufasa", "00000001", "/dir/other.html");
+        assert_eq!(authenticate(&digest, &authorization("SHA-256", "/dir/other.html", "00000001", &other)), Err(false));
+        // unknown nonce
+        assert_eq!(authenticate(&digest, &authorization("SHA-256", "/dir/index.html", "00000001", &valid).replace(NONCE, "unknown")), Err(false));
+        // wrong opaque
+        assert_eq!(authenticate(&digest, &authorization("SHA-256", "/dir/index.html", "00000001", &valid).replace(OPAQUE, "other")), Err(false));
+        // algorithm not offered
+        let sha256_only = rfc_digest().algorithms(vec![DigestAlgorithm::Sha256]);
+        assert_eq!(authenticate(&sha256_only, &authorization("MD5", "/dir/index.html", "00000001", "8ca523f5e9506fed4657c9700eebdbec")), Err(false));
+        // the failed attempts didn't consume the nonce
+        assert!(authenticate(&digest, &authorization("SHA-256", "/dir/index.html", "00000001", &valid)).is_ok());
+    }
+
+    #[test]
+    fn accept_extended_username() {
+        let digest = rfc_digest();
+        let header = authorization("SHA-256", "/dir/index.html", "00000001", &response(DigestAlgorithm::Sha256, "Jäsøn Doe", "00000001", "/dir/index.html"))
+            .replace("username=\"Mufasa\"", "username*=UTF-8''J%C3%A4s%C3%B8n%20Doe");
+        assert_eq!(authenticate(&digest, &header), Ok(String::from("Jäsøn Doe")));
+    }
+
+    #[test]
+    fn expired_nonce_is_stale() {
+        let digest = rfc_digest().nonce_lifetime(Duration::ZERO);
+        let md5 = authorization("MD5", "/dir/index.html", "00000001", "8ca523f5e9506fed4657c9700eebdbec");
+        assert_eq!(authenticate(&digest, &md5), Err(true));
+
+        let digest = rfc_digest().nonce_lifetime(Duration::ZERO);
+        let response = testing::run(Arc::new(digest), testing::request(Method::GET, "/dir/index.html", &[("Authorization", &md5)]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 401 Unauthorized");
+        assert!(testing::headers(&response, "WWW-Authenticate").iter().all(|challenge| challenge.ends_with(", stale=true")));
+    }
+
+    #[test]
+    fn challenge_shares_one_nonce() {
+        let digest = Arc::new(DigestAuth::new(String::from(REALM), Box::new(|_: &str| None::<(String, ())>)));
+        let response = testing::run(Arc::clone(&digest) as Arc<dyn Middleware>, testing::request(Method::GET, "/", &[]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 401 Unauthorized");
+        let challenges = testing::headers(&response, "WWW-Authenticate");
+        assert_eq!(challenges.len(), 2);
+        assert!(challenges[0].contains("algorithm=SHA-256"));
+        assert!(challenges[1].contains("algorithm=MD5"));
+        let nonces = challenges.iter().map(|challenge| parse_auth_params(&challenge["Digest ".len()..]).unwrap()["nonce"].clone()).collect::<Vec<String>>();
+        assert_eq!(nonces[0], nonces[1]);
+        assert_eq!(digest.nonces.lock().unwrap().states.len(), 1);
+    }
+
+    #[test]
+    fn forget_oldest_nonces() {
+        let digest = DigestAuth::new(String::from(REALM), Box::new(|_: &str| None::<(String, ())>)).max_nonces(2);
+        let nonces = (0..3).map(|_| digest.new_nonce()).collect::<Vec<String>>();
+        let table = digest.nonces.lock().unwrap();
+        assert_eq!(table.order.len(), 2);
+        assert!(!table.states.contains_key(&nonces[0]));
+        assert!(table.states.contains_key(&nonces[1]) && table.states.contains_key(&nonces[2]));
+        drop(table);
+
+        let digest = digest.max_nonces(100).nonce_lifetime(Duration::ZERO);
+        let last = (0..3).map(|_| digest.new_nonce()).last().unwrap();
+        let table = digest.nonces.lock().unwrap();
+        assert_eq!(table.order.len(), 1);
+        assert!(table.states.contains_key(&last));
+    }
+
+    #[test]
+    fn middleware_round_trip() {
+        let digest = rfc_digest();
+        let nonce = digest.new_nonce();
+        let uri = "/dir/index.html";
+        let ha1 = DigestAlgorithm::Sha256.hash(&format!("Mufasa:{}:Circle of Life", REALM));
+        let ha2 = DigestAlgorithm::Sha256.hash(&format!("GET:{}", uri));
+        let response = DigestAlgorithm::Sha256.hash(&format!("{}:{}:00000001:{}:auth:{}", ha1, nonce, CNONCE, ha2));
+        let header = authorization("SHA-256", uri, "00000001", &response).replace(NONCE, &nonce);
+        let output = testing::run(Arc::new(digest), testing::request(Method::GET, uri, &[("Authorization", &header)]));
+        assert!(output.ends_with("handled"));
+    }
+
+    #[test]
+    fn parse_params() {
+        let params = parse_auth_params("a=1, B = \"x, \\\"y\\\"\",c=\"\"").unwrap();
+        assert_eq!(params["a"], "1");
+        assert_eq!(params["b"], "x, \"y\"");
+        assert_eq!(params["c"], "");
+        assert!(parse_auth_params("a").is_none());
+        assert!(parse_auth_params("a=\"unterminated").is_none());
+    }
+
+    #[test]
+    fn basic_and_bearer() {
+        let basic: Arc<dyn Middleware> = Arc::new(BasicAuth::new(String::from("admin"), Box::new(|user: &str, password: &str| {
+            if user == "admin" && password == "secret" { Some(()) } else { None }
+        })));
+        // admin:secret
+        let response = testing::run(Arc::clone(&basic), testing::request(Method::GET, "/", &[("Authorization", "Basic YWRtaW46c2VjcmV0")]));
+        assert!(response.ends_with("handled"));
+        let response = testing::run(basic, testing::request(Method::GET, "/", &[("Authorization", "Basic YWRtaW46b3RoZXI=")]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 401 Unauthorized");
+
+        let bearer: Arc<dyn Middleware> = Arc::new(BearerAuth::new(String::from("api"), Box::new(|token: &str| {
+            if token == "valid" { Some(()) } else { None }
+        })));
+        let response = testing::run(Arc::clone(&bearer), testing::request(Method::GET, "/", &[("Authorization", "Bearer valid")]));
+        assert!(response.ends_with("handled"));
+        let response = testing::run(bearer, testing::request(Method::GET, "/", &[("Authorization", "Bearer two tokens")]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 400 Bad Request");
+    }
+}
@@ -1,3 +1,5 @@
+use rest_server::cookie::{Key, Keyring};
+use rest_server::csrf::{Csrf, CsrfToken};
 use rest_server::response::Response;
 use rest_server::status::Status;
 use rest_server::request::Request;
@@ -9,6 +11,9 @@ use std::fs;
 fn main() {
     let mut app = Server::new();
     app.set_number_of_worker(8);
+    app.set_signal_handling(true);
+    app.set_cookie_keyring(Keyring::new(Key::generate()));
+    app.middleware_at(String::from("/form"), Box::new(Csrf::new()));
     app.get(String::from("/"), Box::new(index));
     app.post(String::from("/"), Box::new(index_post));
     app.get(String::from("/form"), Box::new(form));
@@ -16,7 +21,9 @@ fn main() {
     app.patch(String::from("/"), Box::new(index_patch));
     app.get(String::from("/sleep"), Box::new(sleep));
     app.delete(String::from("/sleep"), Box::new(sleep_delete));
-    app.listen(7878);
+    if let Err(e) = app.listen(7878) {
+        eprintln!("Cannot start the server: {}", e);
+    }
 }
 
 fn index(request: Request, mut response: Response) {
@@ -62,8 +69,9 @@ fn sleep_delete(_request: Request, mut response: Response){
     response.send();
 }
 
-fn form(_request: Request, mut response: Response) {
-    let content = fs::read_to_string("resources/form.html").unwrap();
+fn form(request: Request, mut response: Response) {
+    let token = request.extensions().get::<CsrfToken>().unwrap();
+    let content = fs::read_to_string("resources/form.html").unwrap().replace("{csrf_input}", &token.hidden_input());
     response.set_status(Status::Ok);
     response.set_header(String::from("Content-Type"), String::from("text/html"));
     response.set_body(content.as_str());
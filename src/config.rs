@@ -0,0 +1,560 @@
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+use std::env;
+use std::fmt::{Display, Formatter};
+use std::fs;
+use std::net::SocketAddr;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+type SourceFn<T> = dyn Fn() -> Result<T, String> + Send + Sync;
+type ParseFn<T> = dyn Fn(&str) -> Result<T, String> + Send + Sync;
+
+/// Reloadable struct, a configuration which can be reloaded while the server is running, without dropping the connections.
+///
+/// The source reads the configuration and validates it, a reload only replaces the configuration if the source returns Ok,
+/// otherwise the current configuration is kept.
+///
+/// Once added with Server::set_config(), each request gets a snapshot of the configuration (an `Arc<T>` in its extensions):
+/// a reload only changes the configuration of the next requests, the running ones keep the configuration they started with.
+/// The configuration is reloaded on SIGHUP (see Server::set_signal_handling()) or by calling reload() on a clone.
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::config::Reloadable;
+/// use rest_server::Server;
+/// use std::path::PathBuf;
+/// use std::sync::Arc;
+///
+/// struct Limits {
+///     max_items: usize,
+/// }
+///
+/// let limits = Reloadable::from_file(PathBuf::from("limits.conf"), Box::new(|content| {
+///     let max_items = content.trim().parse::<usize>().map_err(|e| format!("Invalid limit: {}", e))?;
+///     if max_items == 0 {
+///         return Err(String::from("The limit must be positive"));
+///     }
+///     Ok(Limits { max_items })
+/// })).unwrap();
+///
+/// let mut app = Server::new();
+/// app.set_signal_handling(true);
+/// app.set_config(limits.clone());
+/// app.get(String::from("/items"), Box::new(|request, mut response| {
+///     let limits = request.extensions().get::<Arc<Limits>>().unwrap();
+///     response.set_body(&format!("Up to {} items", limits.max_items));
+///     response.send();
+/// }));
+/// // reloaded by `kill -HUP <pid>` or limits.reload()
+/// app.listen(7878).unwrap();
+/// ```
+pub struct Reloadable<T> {
+    current: Arc<RwLock<Arc<T>>>,
+    source: Arc<SourceFn<T>>,
+}
+
+impl<T: Send + Sync + 'static> Reloadable<T> {
+
+    /// Create a new Reloadable and load the configuration from the source, return Err if the source fails.
+    pub fn new(source: Box<SourceFn<T>>) -> Result<Self, String> {
+        let config = source()?;
+        Ok(Self {
+            current: Arc::new(RwLock::new(Arc::new(config))),
+            source: Arc::from(source),
+        })
+    }
+
+    /// Create a new Reloadable reading the configuration from a file, parse converts the content of the file to the configuration
+    /// and validates it.
+    pub fn from_file(path: PathBuf, parse: Box<ParseFn<T>>) -> Result<Self, String> {
+        Self::new(Box::new(move || {
+            let content = fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+            parse(&content).map_err(|e| format!("Invalid configuration in {}: {}", path.display(), e))
+        }))
+    }
+
+    /// Return the current configuration, it isn't changed by the next reloads.
+    pub fn get(&self) -> Arc<T> {
+        Arc::clone(&self.current.read().unwrap())
+    }
+
+    /// Read the configuration from the source and replace the current one, the next requests get the new configuration.
+    ///
+    /// Return Err and keep the current configuration if the source fails.
+    pub fn reload(&self) -> Result<(), String> {
+        // the source runs without the lock, so the requests aren't blocked while it reads the configuration
+        let config = (self.source)()?;
+        *self.current.write().unwrap() = Arc::new(config);
+        Ok(())
+    }
+}
+
+impl<T> Clone for Reloadable<T> {
+    fn clone(&self) -> Self {
+        Self {
+            current: Arc::clone(&self.current),
+            source: Arc::clone(&self.source),
+        }
+    }
+}
+
+impl<T: Send + Sync + 'static> Middleware for Reloadable<T> {
+    fn handle(&self, mut request: Request, response: Response, next: Next) {
+        request.extensions_mut().insert(self.get());
+        next.run(request, response);
+    }
+}
+
+/// Prefix of the environment variables overriding the ServerConfig.
+const ENV_PREFIX: &str = "REST_SERVER_";
+
+/// Format of the request log written on the standard output.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum LogFormat {
+    /// `[REQUEST] GET /path, params: {}`
+    #[default]
+    Text,
+    /// One JSON object per line: `{"method":"GET","path":"/path"}`
+    Json,
+}
+
+impl FromStr for LogFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "text" => Ok(LogFormat::Text),
+            "json" => Ok(LogFormat::Json),
+            _ => Err(String::from("expected \"text\" or \"json\"")),
+        }
+    }
+}
+
+impl LogFormat {
+
+    /// Return the log line of a request.
+    pub(crate) fn request_line(&self, request: &Request) -> String {
+        match self {
+            LogFormat::Text => format!("[REQUEST] {} {}", request.method, request.path),
+            LogFormat::Json => {
+                let path = format!("/{}", request.path.get_path());
+                format!("{{\"method\":\"{}\",\"path\":\"{}\"}}", request.method, json_escape(&path))
+            },
+        }
+    }
+}
+
+/// Escape a string to be written in a JSON string.
+fn json_escape(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if c.is_control() => escaped.push_str(&format!("\\u{:04x}", c as u32)),
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Error returned when a ServerConfig can't be loaded or isn't valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConfigError {
+    /// The file can't be read or isn't valid TOML.
+    Read(String),
+    /// A key (or an environment variable) isn't a setting of the server, probably a typo.
+    UnknownKey(String),
+    /// The value of a key can't be used.
+    InvalidValue {
+        key: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl ConfigError {
+
+    /// Return the key (or the environment variable) causing the error, None if the file can't be read.
+    pub fn key(&self) -> Option<&str> {
+        match self {
+            ConfigError::Read(_) => None,
+            ConfigError::UnknownKey(key) | ConfigError::InvalidValue { key, .. } => Some(key),
+        }
+    }
+
+    fn invalid(key: &str, value: &str, reason: impl ToString) -> Self {
+        ConfigError::InvalidValue {
+            key: String::from(key),
+            value: String::from(value),
+            reason: reason.to_string(),
+        }
+    }
+}
+
+impl Display for ConfigError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ConfigError::Read(message) => write!(f, "{}", message),
+            ConfigError::UnknownKey(key) => write!(f, "Unknown key: {}", key),
+            ConfigError::InvalidValue { key, value, reason } => write!(f, "Invalid value {:?} for {}: {}", value, key, reason),
+        }
+    }
+}
+
+impl std::error::Error for ConfigError {}
+
+/// ServerConfig struct, the settings of the server usually chosen at deployment, see Server::apply_config().
+///
+/// It can be loaded from a TOML file (with the `toml` feature), then overridden by the `REST_SERVER_*` environment variables:
+///
+/// | TOML key | Environment variable | Default |
+/// |---|---|---|
+/// | `bind` (a list of addresses) | `REST_SERVER_BIND` (comma separated) | `["127.0.0.1:7878"]` |
+/// | `workers` | `REST_SERVER_WORKERS` | 4 |
+/// | `ipv6_only` | `REST_SERVER_IPV6_ONLY` | false |
+/// | `read_timeout` (seconds, 0 to disable) | `REST_SERVER_READ_TIMEOUT` | 30 |
+/// | `shutdown_timeout` (seconds) | `REST_SERVER_SHUTDOWN_TIMEOUT` | 30 |
+/// | `max_body_size` (bytes) | `REST_SERVER_MAX_BODY_SIZE` | 8388608 |
+/// | `log_format` (`"text"` or `"json"`) | `REST_SERVER_LOG_FORMAT` | `"text"` |
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::config::ServerConfig;
+/// use rest_server::Server;
+///
+/// // REST_SERVER_WORKERS=16 ./my-api
+/// let config = match ServerConfig::from_env() {
+///     Ok(config) => config,
+///     Err(e) => {
+///         eprintln!("Invalid configuration: {}", e);
+///         std::process::exit(1);
+///     },
+/// };
+/// let mut app = Server::new();
+/// app.apply_config(&config);
+/// app.listen_on(&config.bind).unwrap();
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ServerConfig {
+    /// Addresses on which the server listens.
+    pub bind: Vec<SocketAddr>,
+    /// Number of workers handling the requests.
+    pub workers: usize,
+    /// Whether the IPv6 sockets only accept IPv6 connections.
+    pub ipv6_only: bool,
+    /// Maximum time to receive the request headers and to wait for each read of the body, None to wait forever.
+    pub read_timeout: Option<Duration>,
+    /// Time given to the running requests to finish once the shutdown starts.
+    pub shutdown_timeout: Duration,
+    /// Maximum size of a request body (except multipart bodies), in bytes.
+    pub max_body_size: u64,
+    /// Format of the request log.
+    pub log_format: LogFormat,
+}
+
+impl Default for ServerConfig {
+    fn default() -> Self {
+        Self {
+            bind: vec![SocketAddr::from(([127, 0, 0, 1], 7878))],
+            workers: 4,
+            ipv6_only: false,
+            read_timeout: Some(Duration::from_secs(30)),
+            shutdown_timeout: Duration::from_secs(30),
+            max_body_size: 8 * 1024 * 1024,
+            log_format: LogFormat::Text,
+        }
+    }
+}
+
+impl ServerConfig {
+
+    /// Load the default configuration overridden by the `REST_SERVER_*` environment variables, then validate it.
+    pub fn from_env() -> Result<Self, ConfigError> {
+        Self::default().with_env()
+    }
+
+    /// Load a TOML file, override it with the `REST_SERVER_*` environment variables, then validate it.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_file(path: PathBuf) -> Result<Self, ConfigError> {
+        let content = fs::read_to_string(&path).map_err(|e| ConfigError::Read(format!("Cannot read {}: {}", path.display(), e)))?;
+        Self::parse_toml(&content)
+            .map_err(|e| match e {
+                ConfigError::Read(message) => ConfigError::Read(format!("Invalid TOML in {}: {}", path.display(), message)),
+                e => e,
+            })?
+            .with_env()
+    }
+
+    /// Parse a TOML document and validate it, the environment variables aren't used.
+    #[cfg(feature = "toml")]
+    pub fn from_toml_str(content: &str) -> Result<Self, ConfigError> {
+        let config = Self::parse_toml(content)?;
+        config.validate()?;
+        Ok(config)
+    }
+
+    #[cfg(feature = "toml")]
+    fn parse_toml(content: &str) -> Result<Self, ConfigError> {
+        let table = content.parse::<toml::Table>().map_err(|e| ConfigError::Read(e.to_string().trim_end().to_string()))?;
+        let mut config = Self::default();
+        config.set_toml_table(&table)?;
+        Ok(config)
+    }
+
+    /// Set the keys of a TOML table.
+    #[cfg(feature = "toml")]
+    fn set_toml_table(&mut self, table: &toml::Table) -> Result<(), ConfigError> {
+        for (key, value) in table {
+            let value = match value {
+                toml::Value::Table(_) => return Err(ConfigError::UnknownKey(key.clone())),
+                toml::Value::String(value) => value.clone(),
+                toml::Value::Integer(value) => value.to_string(),
+                toml::Value::Float(value) => value.to_string(),
+                toml::Value::Boolean(value) => value.to_string(),
+                toml::Value::Array(values) if key == "bind" => values.iter()
+                    .map(|value| value.as_str().ok_or_else(|| ConfigError::invalid(key, &value.to_string(), "expected a string")))
+                    .collect::<Result<Vec<&str>, ConfigError>>()?
+                    .join(","),
+                value => return Err(ConfigError::invalid(key, &value.to_string(), "unexpected type")),
+            };
+            self.set(key, key, &value)?;
+        }
+        Ok(())
+    }
+
+    /// Override the configuration with the `REST_SERVER_*` environment variables, then validate it.
+    ///
+    /// Return Err if a variable can't be parsed or isn't a setting of the server, the other variables are ignored
+    /// (even if they aren't valid Unicode).
+    pub fn with_env(mut self) -> Result<Self, ConfigError> {
+        for (variable, value) in env::vars_os() {
+            let Some(variable) = variable.to_str() else {
+                continue;
+            };
+            let Some(name) = variable.strip_prefix(ENV_PREFIX) else {
+                continue;
+            };
+            let value = value.to_str().ok_or_else(|| {
+                ConfigError::invalid(variable, &value.to_string_lossy(), "expected valid Unicode")
+            })?;
+            self.set(&name.to_ascii_lowercase(), variable, value)?;
+        }
+        self.validate()?;
+        Ok(self)
+    }
+
+    /// Set a setting from its text value, key is the name reported in the errors (the TOML key or the environment variable).
+    fn set(&mut self, name: &str, key: &str, value: &str) -> Result<(), ConfigError> {
+        let invalid = |reason: &dyn Display| ConfigError::invalid(key, value, reason);
+        match name {
+            "bind" => {
+                self.bind = value.split(',')
+                    .map(|addr| addr.trim().parse::<SocketAddr>().map_err(|e| invalid(&format!("{} ({})", e, addr.trim()))))
+                    .collect::<Result<Vec<SocketAddr>, ConfigError>>()?;
+            },
+            "workers" => self.workers = value.parse().map_err(|e| invalid(&e))?,
+            "ipv6_only" => self.ipv6_only = value.parse().map_err(|e| invalid(&e))?,
+            "read_timeout" => {
+                let timeout = parse_seconds(value).map_err(|e| invalid(&e))?;
+                self.read_timeout = Some(timeout).filter(|timeout| !timeout.is_zero());
+            },
+            "shutdown_timeout" => self.shutdown_timeout = parse_seconds(value).map_err(|e| invalid(&e))?,
+            "max_body_size" => self.max_body_size = value.parse().map_err(|e| invalid(&e))?,
+            "log_format" => self.log_format = value.parse().map_err(|e| invalid(&e))?,
+            _ => return Err(ConfigError::UnknownKey(String::from(key))),
+        }
+        Ok(())
+    }
+
+    /// Check that the settings can be used together, the errors use the TOML keys.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.bind.is_empty() {
+            return Err(ConfigError::invalid("bind", "", "at least one address is required"));
+        }
+        if self.workers == 0 {
+            return Err(ConfigError::invalid("workers", "0", "at least one worker is required"));
+        }
+        Ok(())
+    }
+}
+
+/// Parse a duration in seconds, with an optional fractional part ("2.5").
+fn parse_seconds(value: &str) -> Result<Duration, String> {
+    let seconds = value.parse::<f64>().map_err(|e| e.to_string())?;
+    Duration::try_from_secs_f64(seconds).map_err(|_| String::from("expected a positive number of seconds"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::testing;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Return a Reloadable whose source returns the value of the counter, or fails if it's 0.
+    fn counter() -> (Reloadable<usize>, Arc<AtomicUsize>) {
+        let value = Arc::new(AtomicUsize::new(1));
+        let source = Arc::clone(&value);
+        let config = Reloadable::new(Box::new(move || match source.load(Ordering::SeqCst) {
+            0 => Err(String::from("invalid configuration")),
+            value => Ok(value),
+        })).unwrap();
+        (config, value)
+    }
+
+    #[test]
+    fn reload_replaces_the_configuration() {
+        let (config, value) = counter();
+        assert_eq!(*config.get(), 1);
+        value.store(2, Ordering::SeqCst);
+        assert_eq!(*config.get(), 1);
+        config.reload().unwrap();
+        assert_eq!(*config.get(), 2);
+        // the clones share the configuration
+        value.store(3, Ordering::SeqCst);
+        config.clone().reload().unwrap();
+        assert_eq!(*config.get(), 3);
+    }
+
+    #[test]
+    fn failed_reload_keeps_the_configuration() {
+        let (config, value) = counter();
+        value.store(0, Ordering::SeqCst);
+        assert_eq!(config.reload(), Err(String::from("invalid configuration")));
+        assert_eq!(*config.get(), 1);
+        assert!(Reloadable::<usize>::new(Box::new(|| Err(String::from("missing")))).is_err());
+    }
+
+    #[test]
+    fn snapshot_isnt_changed_by_a_reload() {
+        let (config, value) = counter();
+        let snapshot = config.get();
+        value.store(2, Ordering::SeqCst);
+        config.reload().unwrap();
+        assert_eq!(*snapshot, 1);
+        assert_eq!(*config.get(), 2);
+    }
+
+    #[test]
+    fn middleware_adds_the_snapshot() {
+        let (config, value) = counter();
+        let middleware: Arc<dyn Middleware> = Arc::new(config.clone());
+        let handler = |request: Request, mut response: Response| {
+            response.set_body(&request.extensions().get::<Arc<usize>>().unwrap().to_string());
+            response.send();
+        };
+        let (response, client) = testing::response();
+        Next::new(std::slice::from_ref(&middleware), &handler).run(testing::request(Method::GET, "/", &[]), response);
+        assert!(testing::read_response(client).ends_with("\r\n\r\n1"));
+
+        value.store(2, Ordering::SeqCst);
+        config.reload().unwrap();
+        let (response, client) = testing::response();
+        Next::new(std::slice::from_ref(&middleware), &handler).run(testing::request(Method::GET, "/", &[]), response);
+        assert!(testing::read_response(client).ends_with("\r\n\r\n2"));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_with_every_key() {
+        let config = ServerConfig::from_toml_str(r#"
+            bind = ["127.0.0.1:8080", "[::1]:8080"]
+            workers = 8
+            ipv6_only = true
+            read_timeout = 2.5
+            shutdown_timeout = 10
+            max_body_size = 1024
+            log_format = "json"
+        "#).unwrap();
+        assert_eq!(config, ServerConfig {
+            bind: vec!["127.0.0.1:8080".parse().unwrap(), "[::1]:8080".parse().unwrap()],
+            workers: 8,
+            ipv6_only: true,
+            read_timeout: Some(Duration::from_millis(2500)),
+            shutdown_timeout: Duration::from_secs(10),
+            max_body_size: 1024,
+            log_format: LogFormat::Json,
+        });
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_unknown_key() {
+        let error = ServerConfig::from_toml_str("worker = 8").unwrap_err();
+        assert_eq!(error, ConfigError::UnknownKey(String::from("worker")));
+        let error = ServerConfig::from_toml_str("[tls]\ncertificate = \"cert.pem\"").unwrap_err();
+        assert_eq!(error, ConfigError::UnknownKey(String::from("tls")));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_invalid_value() {
+        let error = ServerConfig::from_toml_str("workers = \"many\"").unwrap_err();
+        assert!(matches!(error, ConfigError::InvalidValue { .. }));
+        assert_eq!(error.key(), Some("workers"));
+        let error = ServerConfig::from_toml_str("bind = [\"127.0.0.1:8080\", 8081]").unwrap_err();
+        assert_eq!(error.key(), Some("bind"));
+        let error = ServerConfig::from_toml_str("bind = [\"localhost\"]").unwrap_err();
+        assert_eq!(error.key(), Some("bind"));
+    }
+
+    #[cfg(feature = "toml")]
+    #[test]
+    fn toml_invalid_syntax() {
+        let error = ServerConfig::from_toml_str("workers = ").unwrap_err();
+        assert!(matches!(error, ConfigError::Read(_)));
+        assert_eq!(error.key(), None);
+    }
+
+    #[test]
+    fn zero_read_timeout_disables_it() {
+        let mut config = ServerConfig::default();
+        config.set("read_timeout", "read_timeout", "0").unwrap();
+        assert_eq!(config.read_timeout, None);
+        config.set("read_timeout", "read_timeout", "0.5").unwrap();
+        assert_eq!(config.read_timeout, Some(Duration::from_millis(500)));
+    }
+
+    #[test]
+    fn invalid_shutdown_timeout() {
+        let mut config = ServerConfig::default();
+        for value in ["-1", "soon", ""] {
+            let error = config.set("shutdown_timeout", "shutdown_timeout", value).unwrap_err();
+            assert_eq!(error.key(), Some("shutdown_timeout"), "{}", value);
+        }
+        assert_eq!(config.shutdown_timeout, Duration::from_secs(30));
+    }
+
+    #[test]
+    fn validate() {
+        assert_eq!(ServerConfig::default().validate(), Ok(()));
+        let config = ServerConfig { workers: 0, ..ServerConfig::default() };
+        assert_eq!(config.validate().unwrap_err().key(), Some("workers"));
+        let config = ServerConfig { bind: Vec::new(), ..ServerConfig::default() };
+        assert_eq!(config.validate().unwrap_err().key(), Some("bind"));
+    }
+
+    #[test]
+    fn environment_variable_name_in_errors() {
+        let mut config = ServerConfig::default();
+        config.set("bind", "REST_SERVER_BIND", "127.0.0.1:80, [::]:80").unwrap();
+        assert_eq!(config.bind, vec!["127.0.0.1:80".parse().unwrap(), "[::]:80".parse().unwrap()]);
+        let error = config.set("workers", "REST_SERVER_WORKERS", "-2").unwrap_err();
+        assert_eq!(error.key(), Some("REST_SERVER_WORKERS"));
+        assert!(error.to_string().contains("REST_SERVER_WORKERS"));
+        let error = config.set("worker", "REST_SERVER_WORKER", "2").unwrap_err();
+        assert_eq!(error, ConfigError::UnknownKey(String::from("REST_SERVER_WORKER")));
+    }
+
+    #[test]
+    fn log_format() {
+        assert_eq!("JSON".parse::<LogFormat>(), Ok(LogFormat::Json));
+        assert_eq!("text".parse::<LogFormat>(), Ok(LogFormat::Text));
+        assert!("xml".parse::<LogFormat>().is_err());
+    }
+}
@@ -0,0 +1,243 @@
+use std::fmt::{Display, Formatter};
+use std::io::{self, Read, Write};
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr, TcpListener, TcpStream};
+#[cfg(unix)]
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+/// Identifier of the next connection accepted by the server.
+static NEXT_CONNECTION_ID: AtomicU64 = AtomicU64::new(1);
+
+/// ConnectionInfo struct, describes the connection on which a request has been received, see Request::connection().
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ConnectionInfo {
+    /// Identifier of the connection, unique for the lifetime of the process (0 if the request wasn't received from the network).
+    pub id: u64,
+    /// Address of the client, if the server is behind a reverse proxy, this is the address of the proxy.
+    pub peer_addr: Option<SocketAddr>,
+    /// Address of the server socket which accepted the connection.
+    pub local_addr: Option<SocketAddr>,
+    /// Path of the Unix socket which accepted the connection, None for TCP connections.
+    pub socket_path: Option<PathBuf>,
+    /// Credentials of the process which opened the connection, only available for Unix socket connections.
+    pub peer_credentials: Option<PeerCredentials>,
+    /// Position of the request in the connection, starting at 1.
+    ///
+    /// Each connection currently carries a single request, as the connection is closed once the response is sent.
+    pub request_number: u64,
+}
+
+impl ConnectionInfo {
+
+    /// Create the ConnectionInfo of a new connection, with a new identifier.
+    pub(crate) fn from_stream(stream: &Stream) -> Self {
+        let mut info = Self {
+            id: NEXT_CONNECTION_ID.fetch_add(1, Ordering::Relaxed),
+            request_number: 1,
+            ..Self::default()
+        };
+        match stream {
+            Stream::Tcp(stream) => {
+                info.peer_addr = stream.peer_addr().ok();
+                info.local_addr = stream.local_addr().ok();
+            },
+            #[cfg(unix)]
+            Stream::Unix(stream) => {
+                info.socket_path = stream.local_addr().ok().and_then(|addr| addr.as_pathname().map(PathBuf::from));
+                info.peer_credentials = crate::unix::peer_credentials(stream);
+            },
+        }
+        info
+    }
+}
+
+/// PeerCredentials struct, identity of the process at the other end of a Unix socket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PeerCredentials {
+    /// Process ID, only available on Linux.
+    pub pid: Option<i32>,
+    /// User ID.
+    pub uid: u32,
+    /// Group ID.
+    pub gid: u32,
+}
+
+/// Address on which the server listens, given to the startup hooks (see Server::on_start()).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    /// Path of a Unix socket, None for an unnamed or abstract socket.
+    Unix(Option<PathBuf>),
+}
+
+impl Display for ListenAddr {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "{}", addr),
+            ListenAddr::Unix(Some(path)) => write!(f, "unix:{}", path.display()),
+            ListenAddr::Unix(None) => write!(f, "unix:(unnamed)"),
+        }
+    }
+}
+
+/// Socket accepting the connections of the server.
+pub(crate) enum Listener {
+    Tcp(TcpListener),
+    #[cfg(unix)]
+    Unix(UnixListener),
+}
+
+impl Listener {
+
+    /// Wait for a new connection.
+    pub(crate) fn accept(&self) -> io::Result<Stream> {
+        match self {
+            Listener::Tcp(listener) => listener.accept().map(|(stream, _)| Stream::Tcp(stream)),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.accept().map(|(stream, _)| Stream::Unix(stream)),
+        }
+    }
+
+    /// Return the address of the listener.
+    pub(crate) fn local_addr(&self) -> io::Result<ListenAddr> {
+        match self {
+            Listener::Tcp(listener) => listener.local_addr().map(ListenAddr::Tcp),
+            #[cfg(unix)]
+            Listener::Unix(listener) => listener.local_addr().map(|addr| ListenAddr::Unix(addr.as_pathname().map(PathBuf::from))),
+        }
+    }
+
+    /// Return a Waker able to unblock a thread waiting in accept().
+    pub(crate) fn waker(&self) -> Waker {
+        match self {
+            Listener::Tcp(listener) => match listener.local_addr() {
+                // the unspecified address can't be reached on every system, connect to the loopback address instead
+                Ok(addr) if addr.ip().is_unspecified() => {
+                    let loopback = match addr.ip() {
+                        IpAddr::V4(_) => IpAddr::V4(Ipv4Addr::LOCALHOST),
+                        IpAddr::V6(_) => IpAddr::V6(Ipv6Addr::LOCALHOST),
+                    };
+                    Waker::Tcp(SocketAddr::new(loopback, addr.port()))
+                },
+                Ok(addr) => Waker::Tcp(addr),
+                Err(_) => Waker::None,
+            },
+            #[cfg(unix)]
+            Listener::Unix(listener) => match listener.local_addr().ok().and_then(|addr| addr.as_pathname().map(PathBuf::from)) {
+                Some(path) => Waker::Unix(path),
+                None => Waker::None,
+            },
+        }
+    }
+}
+
+/// Address of a listener, a connection to it wakes up the thread waiting for a new connection, so it can see that the server stops.
+#[derive(Debug, Clone)]
+pub(crate) enum Waker {
+    Tcp(SocketAddr),
+    #[cfg(unix)]
+    Unix(PathBuf),
+    /// The listener can't be reached (abstract Unix socket, etc.), it stops at its next connection.
+    None,
+}
+
+impl Waker {
+
+    /// Open and close a connection to the listener, the errors are ignored as the listener may already be closed.
+    pub(crate) fn wake(&self) {
+        match self {
+            Waker::Tcp(addr) => {
+                let _ = TcpStream::connect(addr);
+            },
+            #[cfg(unix)]
+            Waker::Unix(path) => {
+                let _ = UnixStream::connect(path);
+            },
+            Waker::None => {},
+        }
+    }
+}
+
+/// Connection with a client, a TCP or a Unix socket.
+#[derive(Debug)]
+pub(crate) enum Stream {
+    Tcp(TcpStream),
+    #[cfg(unix)]
+    Unix(UnixStream),
+}
+
+impl Stream {
+
+    /// Return a new handle to the same connection.
+    pub(crate) fn try_clone(&self) -> io::Result<Stream> {
+        match self {
+            Stream::Tcp(stream) => stream.try_clone().map(Stream::Tcp),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.try_clone().map(Stream::Unix),
+        }
+    }
+
+    /// Set the timeout of the reads, None to wait forever.
+    pub(crate) fn set_read_timeout(&self, timeout: Option<Duration>) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.set_read_timeout(timeout),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.set_read_timeout(timeout),
+        }
+    }
+
+    /// Shut down the reading, writing or both halves of the connection, for all its handles.
+    pub(crate) fn shutdown(&self, how: std::net::Shutdown) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => stream.shutdown(how),
+            #[cfg(unix)]
+            Stream::Unix(stream) => stream.shutdown(how),
+        }
+    }
+}
+
+impl Read for &Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).read(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).read(buf),
+        }
+    }
+}
+
+impl Read for Stream {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        (&*self).read(buf)
+    }
+}
+
+impl Write for &Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).write(buf),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Stream::Tcp(stream) => (&*stream).flush(),
+            #[cfg(unix)]
+            Stream::Unix(stream) => (&*stream).flush(),
+        }
+    }
+}
+
+impl Write for Stream {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        (&*self).write(buf)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        (&*self).flush()
+    }
+}
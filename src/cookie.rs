@@ -0,0 +1,719 @@
+use crate::crypto;
+use aes_gcm::{Aes256Gcm, KeyInit, Nonce};
+use aes_gcm::aead::{Aead, Payload};
+use std::fmt::{Debug, Display, Formatter};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Size of the AES-GCM nonce put before the encrypted value of a private cookie.
+const NONCE_SIZE: usize = 12;
+
+/// SameSite attribute of a cookie, see [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Headers/Set-Cookie#samesitesamesite-value).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SameSite {
+    /// The cookie is only sent for same-site requests.
+    Strict,
+    /// The cookie is also sent when the user navigates to the site from another one.
+    Lax,
+    /// The cookie is sent for every requests, browsers require the Secure attribute in this case.
+    None,
+}
+
+impl SameSite {
+
+    /// Return the string representation of the attribute value.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SameSite::Strict => "Strict",
+            SameSite::Lax => "Lax",
+            SameSite::None => "None",
+        }
+    }
+}
+
+/// Cookie struct, used to build a cookie sent to the client with Response::add_cookie().
+///
+/// Attributes are set with the builder methods, a cookie without Max-Age and Expires is a session cookie.
+///
+/// ## Example:
+/// ```
+/// use rest_server::cookie::{Cookie, SameSite};
+/// use std::time::Duration;
+///
+/// let cookie = Cookie::new(String::from("theme"), String::from("dark"))
+///     .path(String::from("/"))
+///     .max_age(Duration::from_secs(3600))
+///     .http_only(true)
+///     .same_site(SameSite::Lax);
+/// assert_eq!(cookie.to_string(), "theme=dark; Path=/; Max-Age=3600; HttpOnly; SameSite=Lax");
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cookie {
+    name: String,
+    value: String,
+    path: Option<String>,
+    domain: Option<String>,
+    max_age: Option<Duration>,
+    expires: Option<SystemTime>,
+    secure: bool,
+    http_only: bool,
+    same_site: Option<SameSite>,
+    partitioned: bool,
+}
+
+impl Cookie {
+
+    /// Create a new session cookie with the given name and value.
+    ///
+    /// Panic if the name or the value contains characters which aren't allowed in a cookie, see try_new() for values
+    /// which aren't known in advance.
+    pub fn new(name: String, value: String) -> Self {
+        match Self::try_new(name, value) {
+            Ok(cookie) => cookie,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Create a new session cookie with the given name and value.
+    ///
+    /// The name must be a token and the value can only contain printable ASCII characters except spaces, `"`, `,`, `;` and `\`
+    /// (see [RFC 6265](https://tools.ietf.org/html/rfc6265#section-4.1.1)), quoting the value doesn't allow more characters.
+    /// Encode arbitrary data (user input, JSON, etc.) with form::percent_encode() and decode it with form::percent_decode().
+    ///
+    /// Return Err if the name or the value contains a character which isn't allowed, nothing is removed silently.
+    ///
+    /// ## Example:
+    /// ```
+    /// use rest_server::cookie::{Cookie, CookieError};
+    /// use rest_server::form::{percent_decode, percent_encode};
+    ///
+    /// assert!(matches!(Cookie::try_new(String::from("city"), String::from("São Paulo")), Err(CookieError::InvalidValue(_))));
+    ///
+    /// let cookie = Cookie::try_new(String::from("city"), percent_encode("São Paulo")).unwrap();
+    /// assert_eq!(cookie.value(), "S%C3%A3o+Paulo");
+    /// assert_eq!(percent_decode(cookie.value().as_bytes()), "São Paulo".as_bytes());
+    /// ```
+    pub fn try_new(name: String, value: String) -> Result<Self, CookieError> {
+        if name.is_empty() || !name.chars().all(is_token_char) {
+            return Err(CookieError::InvalidName(name));
+        }
+        if !value.chars().all(is_value_char) {
+            return Err(CookieError::InvalidValue(value));
+        }
+        Ok(Self {
+            name,
+            value,
+            path: None,
+            domain: None,
+            max_age: None,
+            expires: None,
+            secure: false,
+            http_only: false,
+            same_site: None,
+            partitioned: false,
+        })
+    }
+
+    /// Create a cookie which ask the client to remove the cookie with the given name.
+    ///
+    /// The path and the domain must be the same than the ones of the cookie to remove.
+    ///
+    /// Panic if the name isn't a valid cookie name.
+    pub fn removal(name: String) -> Self {
+        Self::new(name, String::new())
+            .max_age(Duration::ZERO)
+            .expires(UNIX_EPOCH)
+    }
+
+    /// Set the Path attribute, the cookie is only sent for the requests under this path.
+    ///
+    /// Panic if the path contains characters which aren't allowed, see try_path().
+    pub fn path(self, path: String) -> Self {
+        match self.try_path(path) {
+            Ok(cookie) => cookie,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Set the Path attribute, the cookie is only sent for the requests under this path.
+    ///
+    /// Return Err if the path contains a control character, a `;` or a non ASCII character (percent-encode it),
+    /// nothing is removed silently.
+    pub fn try_path(mut self, path: String) -> Result<Self, CookieError> {
+        check_path(&path)?;
+        self.path = Some(path);
+        Ok(self)
+    }
+
+    /// Set the Domain attribute, the cookie is also sent to the subdomains of this domain.
+    ///
+    /// Panic if the domain is empty or contains characters which aren't allowed, see try_domain().
+    pub fn domain(self, domain: String) -> Self {
+        match self.try_domain(domain) {
+            Ok(cookie) => cookie,
+            Err(e) => panic!("{}", e),
+        }
+    }
+
+    /// Set the Domain attribute, the cookie is also sent to the subdomains of this domain, a leading `.` is ignored.
+    ///
+    /// Return Err if the domain is empty or contains a control character, a `;` or a non ASCII character
+    /// (use the punycode form of internationalized domains), nothing is removed silently.
+    pub fn try_domain(mut self, domain: String) -> Result<Self, CookieError> {
+        check_domain(&domain)?;
+        self.domain = Some(domain.trim_start_matches('.').to_string());
+        Ok(self)
+    }
+
+    /// Set the Max-Age attribute, the number of seconds until the cookie expires, it takes precedence over Expires.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Set the Expires attribute, the date when the cookie expires.
+    pub fn expires(mut self, expires: SystemTime) -> Self {
+        self.expires = Some(expires);
+        self
+    }
+
+    /// Set the Secure attribute, the cookie is only sent over HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set the HttpOnly attribute, the cookie can't be read from javascript.
+    pub fn http_only(mut self, http_only: bool) -> Self {
+        self.http_only = http_only;
+        self
+    }
+
+    /// Set the SameSite attribute.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = Some(same_site);
+        self
+    }
+
+    /// Set the Partitioned attribute (CHIPS), the cookie is stored separately for each top-level site, it requires Secure.
+    pub fn partitioned(mut self, partitioned: bool) -> Self {
+        self.partitioned = partitioned;
+        self
+    }
+
+    /// Return the name of the cookie.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Return the value of the cookie.
+    pub fn value(&self) -> &str {
+        &self.value
+    }
+
+    /// Return the Path attribute of the cookie.
+    pub fn get_path(&self) -> Option<&str> {
+        self.path.as_deref()
+    }
+
+    /// Return the Domain attribute of the cookie.
+    pub fn get_domain(&self) -> Option<&str> {
+        self.domain.as_deref()
+    }
+}
+
+impl Display for Cookie {
+
+    /// Format the cookie as the value of a Set-Cookie header.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}={}", self.name, self.value)?;
+        if let Some(path) = &self.path {
+            write!(f, "; Path={}", path)?;
+        }
+        if let Some(domain) = &self.domain {
+            write!(f, "; Domain={}", domain)?;
+        }
+        if let Some(max_age) = self.max_age {
+            write!(f, "; Max-Age={}", max_age.as_secs())?;
+        }
+        if let Some(expires) = self.expires {
+            write!(f, "; Expires={}", format_http_date(expires))?;
+        }
+        if self.secure {
+            write!(f, "; Secure")?;
+        }
+        if self.http_only {
+            write!(f, "; HttpOnly")?;
+        }
+        if let Some(same_site) = self.same_site {
+            write!(f, "; SameSite={}", same_site.as_str())?;
+        }
+        if self.partitioned {
+            write!(f, "; Partitioned")?;
+        }
+        Ok(())
+    }
+}
+
+/// Error returned when a cookie can't be created.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum CookieError {
+    /// The name is empty or contains a character which isn't allowed.
+    InvalidName(String),
+    /// The value contains a character which isn't allowed.
+    InvalidValue(String),
+    /// The Path attribute contains a character which isn't allowed.
+    InvalidPath(String),
+    /// The Domain attribute is empty or contains a character which isn't allowed.
+    InvalidDomain(String),
+}
+
+impl Display for CookieError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            CookieError::InvalidName(name) => write!(f, "Invalid cookie name: {:?}", name),
+            CookieError::InvalidValue(value) => write!(f, "Invalid cookie value: {:?}", value),
+            CookieError::InvalidPath(path) => write!(f, "Invalid cookie path: {:?}", path),
+            CookieError::InvalidDomain(domain) => write!(f, "Invalid cookie domain: {:?}", domain),
+        }
+    }
+}
+
+impl std::error::Error for CookieError {}
+
+/// CookieJar struct, the cookies sent by the client in the Cookie header, see Request::cookies().
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CookieJar {
+    cookies: Vec<(String, String)>,
+}
+
+impl CookieJar {
+
+    /// Parse the value of a Cookie header, values between double quotes are unquoted.
+    pub fn parse(header: &str) -> Self {
+        let cookies = header.split(';')
+            .filter_map(|pair| pair.split_once('='))
+            .map(|(name, value)| {
+                let value = value.trim();
+                let value = value.strip_prefix('"').and_then(|v| v.strip_suffix('"')).unwrap_or(value);
+                (name.trim().to_string(), value.to_string())
+            })
+            .filter(|(name, _)| !name.is_empty())
+            .collect();
+        Self {
+            cookies,
+        }
+    }
+
+    /// Return the value of the cookie, if the client sent several cookies with this name, the first one is returned.
+    ///
+    /// Return None if the cookie is not found.
+    pub fn get(&self, name: &str) -> Option<&str> {
+        self.cookies.iter().find(|(n, _)| n == name).map(|(_, value)| value.as_str())
+    }
+
+    /// Return every values of the cookie, clients send several cookies with the same name when they have different paths or domains.
+    pub fn get_all(&self, name: &str) -> Vec<&str> {
+        self.cookies.iter().filter(|(n, _)| n == name).map(|(_, value)| value.as_str()).collect()
+    }
+
+    /// Return an iterator over the (name, value) pairs of the cookies.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.cookies.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Return the number of cookies.
+    pub fn len(&self) -> usize {
+        self.cookies.len()
+    }
+
+    /// Return true if the client didn't send any cookie.
+    pub fn is_empty(&self) -> bool {
+        self.cookies.is_empty()
+    }
+}
+
+/// Key used to sign and encrypt cookies.
+/// 
+/// A key is made of 64 bytes, the first 32 bytes are used to sign cookies with HMAC-SHA256
+/// and the last 32 bytes to encrypt cookies with AES-256-GCM.
+#[derive(Clone, PartialEq, Eq)]
+pub struct Key {
+    signing: [u8; 32],
+    encryption: [u8; 32],
+}
+
+impl Key {
+
+    /// Create a key from 64 bytes of secret material, the bytes must be random (see generate()) and kept secret.
+    /// 
+    /// Return Err if bytes isn't 64 bytes long.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, String> {
+        if bytes.len() != 64 {
+            return Err(format!("A cookie key must be 64 bytes long, got {} bytes", bytes.len()));
+        }
+        let mut signing = [0; 32];
+        let mut encryption = [0; 32];
+        signing.copy_from_slice(&bytes[..32]);
+        encryption.copy_from_slice(&bytes[32..]);
+        Ok(Self {
+            signing,
+            encryption,
+        })
+    }
+
+    /// Generate a new random key.
+    /// 
+    /// Cookies signed with a generated key can't be read after a restart of the server, use from_bytes() with a stored key to keep them.
+    pub fn generate() -> Self {
+        Self::from_bytes(&crypto::random_bytes(64)).unwrap()
+    }
+
+    /// Return the 64 bytes of the key, to store it.
+    pub fn to_bytes(&self) -> Vec<u8> {
+        [self.signing, self.encryption].concat()
+    }
+
+    fn sign(&self, name: &str, value: &str) -> String {
+        let tag = crypto::hmac_sha256(&self.signing, format!("{}={}", name, value).as_bytes());
+        format!("{}.{}", crypto::base64_encode(&tag), value)
+    }
+
+    fn verify(&self, name: &str, signed: &str) -> Option<String> {
+        let (tag, value) = signed.split_once('.')?;
+        let tag = crypto::base64_decode(tag)?;
+        if crypto::hmac_sha256_verify(&self.signing, format!("{}={}", name, value).as_bytes(), &tag) {
+            Some(value.to_string())
+        } else {
+            None
+        }
+    }
+
+    fn encrypt(&self, name: &str, value: &str) -> String {
+        let cipher = Aes256Gcm::new((&self.encryption).into());
+        let nonce = crypto::random_bytes(NONCE_SIZE);
+        // The name is authenticated so a value can't be moved to another cookie
+        let encrypted = cipher.encrypt(Nonce::from_slice(&nonce), Payload { msg: value.as_bytes(), aad: name.as_bytes() })
+            .expect("AES-GCM encryption can't fail for a cookie sized value");
+        crypto::base64_encode(&[nonce, encrypted].concat())
+    }
+
+    fn decrypt(&self, name: &str, encrypted: &str) -> Option<String> {
+        let data = crypto::base64_decode(encrypted)?;
+        if data.len() <= NONCE_SIZE {
+            return None;
+        }
+        let cipher = Aes256Gcm::new((&self.encryption).into());
+        let (nonce, encrypted) = data.split_at(NONCE_SIZE);
+        let value = cipher.decrypt(Nonce::from_slice(nonce), Payload { msg: encrypted, aad: name.as_bytes() }).ok()?;
+        String::from_utf8(value).ok()
+    }
+}
+
+impl Debug for Key {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Never print the secret
+        write!(f, "Key(..)")
+    }
+}
+
+/// Keyring struct, the keys used by the server to sign and encrypt cookies, see Server::set_cookie_keyring().
+/// 
+/// New cookies are always signed or encrypted with the primary key, previous keys are only used to read cookies,
+/// this allows to rotate the key: add the current primary key as previous key and set a new primary key,
+/// cookies sent by the clients are still valid until they expire.
+#[derive(Debug, Clone)]
+pub struct Keyring {
+    primary: Key,
+    previous: Vec<Key>,
+}
+
+impl Keyring {
+
+    /// Create a new Keyring with the given primary key.
+    pub fn new(primary: Key) -> Self {
+        Self {
+            primary,
+            previous: Vec::new(),
+        }
+    }
+
+    /// Add a previous key still accepted to read cookies.
+    pub fn with_previous(mut self, key: Key) -> Self {
+        self.previous.push(key);
+        self
+    }
+
+    /// Return the keys in the order they are tried, the primary key first.
+    fn keys(&self) -> impl Iterator<Item = &Key> {
+        std::iter::once(&self.primary).chain(self.previous.iter())
+    }
+
+    /// Return a copy of the cookie with its value signed, the value can be read by the client but not modified.
+    pub fn sign(&self, cookie: &Cookie) -> Cookie {
+        let mut signed = cookie.clone();
+        signed.value = self.primary.sign(&cookie.name, &cookie.value);
+        signed
+    }
+
+    /// Verify the signature of a signed cookie value and return the original value.
+    /// 
+    /// Return None if the signature isn't valid with any of the keys.
+    pub fn verify(&self, name: &str, value: &str) -> Option<String> {
+        self.keys().find_map(|key| key.verify(name, value))
+    }
+
+    /// Return a copy of the cookie with its value encrypted, the value can't be read nor modified by the client.
+    pub fn encrypt(&self, cookie: &Cookie) -> Cookie {
+        let mut encrypted = cookie.clone();
+        encrypted.value = self.primary.encrypt(&cookie.name, &cookie.value);
+        encrypted
+    }
+
+    /// Decrypt an encrypted cookie value and return the original value.
+    /// 
+    /// Return None if the value can't be decrypted with any of the keys or has been modified.
+    pub fn decrypt(&self, name: &str, value: &str) -> Option<String> {
+        self.keys().find_map(|key| key.decrypt(name, value))
+    }
+}
+
+/// Format a date as defined by [RFC 7231](https://tools.ietf.org/html/rfc7231#section-7.1.1.1) (e.g. "Sun, 06 Nov 1994 08:49:37 GMT").
+pub fn format_http_date(date: SystemTime) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+    let seconds = date.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = seconds / 86400;
+    let (year, month, day) = civil_from_days(days as i64);
+    format!(
+        "{}, {:02} {} {} {:02}:{:02}:{:02} GMT",
+        DAYS[(days % 7) as usize],
+        day,
+        MONTHS[(month - 1) as usize],
+        year,
+        seconds % 86400 / 3600,
+        seconds % 3600 / 60,
+        seconds % 60
+    )
+}
+
+/// Convert a number of days since 1970-01-01 to a (year, month, day) date,
+/// see [Howard Hinnant's algorithm](https://howardhinnant.github.io/date_algorithms.html#civil_from_days).
+fn civil_from_days(days: i64) -> (i64, u32, u32) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = (doy - (153 * mp + 2) / 5 + 1) as u32;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 } as u32;
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Return true if the character is allowed in a cookie name (a token as defined by RFC 7230).
+fn is_token_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"()<>@,;:\\\"/[]?={}".contains(c)
+}
+
+/// Return true if the character is allowed in a cookie value (cookie-octet as defined by RFC 6265).
+fn is_value_char(c: char) -> bool {
+    c.is_ascii_graphic() && !"\",;\\".contains(c)
+}
+
+/// Return true if the character is allowed in the Path and Domain attributes (any CHAR except CTLs or ";" in RFC 6265).
+fn is_attribute_char(c: char) -> bool {
+    c.is_ascii() && !c.is_ascii_control() && c != ';'
+}
+
+/// Check that the path can be used as the Path attribute of a cookie.
+pub(crate) fn check_path(path: &str) -> Result<(), CookieError> {
+    if !path.chars().all(is_attribute_char) {
+        return Err(CookieError::InvalidPath(String::from(path)));
+    }
+    Ok(())
+}
+
+/// Check that the domain can be used as the Domain attribute of a cookie.
+pub(crate) fn check_domain(domain: &str) -> Result<(), CookieError> {
+    if domain.trim_start_matches('.').is_empty() || !domain.chars().all(is_attribute_char) {
+        return Err(CookieError::InvalidDomain(String::from(domain)));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn serialize_cookie() {
+        let cookie = Cookie::new(String::from("id"), String::from("a3fWa"))
+            .path(String::from("/docs"))
+            .domain(String::from(".example.com"))
+            .max_age(Duration::from_secs(60))
+            .expires(UNIX_EPOCH + Duration::from_secs(784111777))
+            .secure(true)
+            .http_only(true)
+            .same_site(SameSite::Strict)
+            .partitioned(true);
+        assert_eq!(
+            cookie.to_string(),
+            "id=a3fWa; Path=/docs; Domain=example.com; Max-Age=60; Expires=Sun, 06 Nov 1994 08:49:37 GMT; Secure; HttpOnly; SameSite=Strict; Partitioned"
+        );
+    }
+
+    #[test]
+    fn serialize_removal() {
+        assert_eq!(Cookie::removal(String::from("id")).to_string(), "id=; Max-Age=0; Expires=Thu, 01 Jan 1970 00:00:00 GMT");
+    }
+
+    #[test]
+    fn reject_invalid_names_and_values() {
+        for name in ["", "a b", "a;b", "a=b", "na\u{e9}me", "a,b"] {
+            assert_eq!(Cookie::try_new(String::from(name), String::from("v")), Err(CookieError::InvalidName(String::from(name))));
+        }
+        for value in ["h\u{e9}llo", "a;b", "a b", "a,b", "\"quoted\"", "a\\b", "a\nb"] {
+            assert_eq!(Cookie::try_new(String::from("n"), String::from(value)), Err(CookieError::InvalidValue(String::from(value))));
+        }
+        assert!(Cookie::try_new(String::from("n"), String::new()).is_ok());
+        assert!(Cookie::try_new(String::from("__Host-id"), String::from("abc.DEF-_~!#$%&'()*+/:<=>?@[]^`{|}")).is_ok());
+    }
+
+    #[test]
+    fn reject_invalid_paths_and_domains() {
+        let cookie = || Cookie::new(String::from("n"), String::from("v"));
+        for path in ["/a;b", "/a\nb", "/a\rb", "/caf\u{e9}", "/\u{7f}"] {
+            assert_eq!(cookie().try_path(String::from(path)), Err(CookieError::InvalidPath(String::from(path))));
+        }
+        for domain in ["", ".", "example.com; Secure", "exa\tmple.com", "b\u{fc}cher.example"] {
+            assert_eq!(cookie().try_domain(String::from(domain)), Err(CookieError::InvalidDomain(String::from(domain))));
+        }
+        let cookie = cookie().try_path(String::from("/docs/a b")).unwrap().try_domain(String::from(".example.com")).unwrap();
+        assert_eq!(cookie.get_path(), Some("/docs/a b"));
+        assert_eq!(cookie.get_domain(), Some("example.com"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid cookie path")]
+    fn path_panics_on_invalid_path() {
+        let _ = Cookie::new(String::from("n"), String::from("v")).path(String::from("/; Domain=evil.example"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid cookie value")]
+    fn new_panics_on_invalid_value() {
+        Cookie::new(String::from("n"), String::from("a;b"));
+    }
+
+    #[test]
+    fn parse_cookie_header() {
+        let jar = CookieJar::parse("theme=dark; id=\"abc\";  empty=; =nameless; novalue; lang=en; lang=fr");
+        assert_eq!(jar.get("theme"), Some("dark"));
+        assert_eq!(jar.get("id"), Some("abc"));
+        assert_eq!(jar.get("empty"), Some(""));
+        assert_eq!(jar.get("novalue"), None);
+        assert_eq!(jar.get("lang"), Some("en"));
+        assert_eq!(jar.get_all("lang"), vec!["en", "fr"]);
+        assert_eq!(jar.len(), 5);
+        assert!(CookieJar::parse("").is_empty());
+    }
+
+    #[test]
+    fn format_dates() {
+        assert_eq!(format_http_date(UNIX_EPOCH), "Thu, 01 Jan 1970 00:00:00 GMT");
+        assert_eq!(format_http_date(UNIX_EPOCH + Duration::from_secs(951782400)), "Tue, 29 Feb 2000 00:00:00 GMT");
+    }
+
+    fn keyring() -> Keyring {
+        Keyring::new(Key::from_bytes(&[7; 64]).unwrap())
+    }
+
+    fn cookie(value: &str) -> Cookie {
+        Cookie::new(String::from("user"), String::from(value)).path(String::from("/"))
+    }
+
+    #[test]
+    fn key_from_bytes() {
+        let bytes = crypto::random_bytes(64);
+        assert_eq!(Key::from_bytes(&bytes).unwrap().to_bytes(), bytes);
+        assert!(Key::from_bytes(&[0; 32]).is_err());
+        assert_eq!(format!("{:?}", Key::from_bytes(&bytes).unwrap()), "Key(..)");
+    }
+
+    #[test]
+    fn signed_round_trip() {
+        let keyring = keyring();
+        let signed = keyring.sign(&cookie("alice"));
+        assert_eq!(signed.name(), "user");
+        assert_eq!(signed.get_path(), Some("/"));
+        assert!(signed.value().ends_with(".alice"));
+        assert_eq!(keyring.verify("user", signed.value()).as_deref(), Some("alice"));
+    }
+
+    #[test]
+    fn encrypted_round_trip() {
+        let keyring = keyring();
+        let encrypted = keyring.encrypt(&cookie("alice"));
+        assert!(!encrypted.value().contains("alice"));
+        assert!(Cookie::try_new(String::from("user"), encrypted.value().to_string()).is_ok());
+        assert_eq!(keyring.decrypt("user", encrypted.value()).as_deref(), Some("alice"));
+        // a new nonce is used every time
+        assert_ne!(keyring.encrypt(&cookie("alice")).value(), encrypted.value());
+    }
+
+    #[test]
+    fn verify_with_previous_key_after_rotation() {
+        let old = keyring();
+        let signed = old.sign(&cookie("alice"));
+        let encrypted = old.encrypt(&cookie("alice"));
+
+        let rotated = Keyring::new(Key::generate()).with_previous(Key::from_bytes(&[7; 64]).unwrap());
+        assert_eq!(rotated.verify("user", signed.value()).as_deref(), Some("alice"));
+        assert_eq!(rotated.decrypt("user", encrypted.value()).as_deref(), Some("alice"));
+        // new cookies use the primary key only
+        assert_eq!(old.verify("user", rotated.sign(&cookie("bob")).value()), None);
+        assert_eq!(old.decrypt("user", rotated.encrypt(&cookie("bob")).value()), None);
+
+        let unrelated = Keyring::new(Key::generate());
+        assert_eq!(unrelated.verify("user", signed.value()), None);
+        assert_eq!(unrelated.decrypt("user", encrypted.value()), None);
+    }
+
+    #[test]
+    fn reject_value_moved_to_another_cookie() {
+        let keyring = keyring();
+        let signed = keyring.sign(&cookie("alice"));
+        let encrypted = keyring.encrypt(&cookie("alice"));
+        assert_eq!(keyring.verify("admin", signed.value()), None);
+        assert_eq!(keyring.decrypt("admin", encrypted.value()), None);
+    }
+
+    #[test]
+    fn reject_modified_signature_or_value() {
+        let keyring = keyring();
+        let signed = keyring.sign(&cookie("alice")).value().to_string();
+        let (tag, _) = signed.split_once('.').unwrap();
+        assert_eq!(keyring.verify("user", &format!("{}.admin", tag)), None);
+        let mut tag = crypto::base64_decode(tag).unwrap();
+        tag[0] ^= 1;
+        assert_eq!(keyring.verify("user", &format!("{}.alice", crypto::base64_encode(&tag))), None);
+        assert_eq!(keyring.verify("user", "alice"), None);
+        assert_eq!(keyring.verify("user", "not base64!.alice"), None);
+    }
+
+    #[test]
+    fn reject_modified_ciphertext() {
+        let keyring = keyring();
+        let encrypted = crypto::base64_decode(keyring.encrypt(&cookie("alice")).value()).unwrap();
+        // nonce, ciphertext and tag
+        for index in [0, NONCE_SIZE, encrypted.len() - 1] {
+            let mut modified = encrypted.clone();
+            modified[index] ^= 1;
+            assert_eq!(keyring.decrypt("user", &crypto::base64_encode(&modified)), None);
+        }
+        assert_eq!(keyring.decrypt("user", &crypto::base64_encode(&encrypted[..NONCE_SIZE])), None);
+        assert_eq!(keyring.decrypt("user", &crypto::base64_encode(&encrypted[..encrypted.len() - 1])), None);
+        assert_eq!(keyring.decrypt("user", "not base64!"), None);
+    }
+}
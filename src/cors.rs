@@ -0,0 +1,313 @@
+use crate::method::Method;
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::Status;
+use std::time::Duration;
+
+type OriginPredicate = dyn Fn(&str) -> bool + Send + Sync;
+
+/// Origins allowed to make cross-origin requests.
+enum AllowedOrigins {
+    Any,
+    List(Vec<String>),
+    Predicate(Box<OriginPredicate>),
+}
+
+/// Middleware implementing Cross-Origin Resource Sharing (see the [Fetch standard](https://fetch.spec.whatwg.org/#http-cors-protocol)).
+///
+/// The CORS headers are added to the responses of the requests sent from an allowed origin,
+/// and the preflight requests (OPTIONS with an Access-Control-Request-Method header) are answered
+/// with 204 No Content without calling the route handler, so no OPTIONS route needs to be registered.
+/// A preflight from an origin which isn't allowed, or asking for a method or a header which isn't allowed, is answered with 403 Forbidden.
+///
+/// No origin is allowed by default.
+///
+/// ## Example:
+/// ```
+/// use rest_server::cors::Cors;
+/// use rest_server::method::Method;
+/// use rest_server::Server;
+/// use std::time::Duration;
+///
+/// let cors = Cors::new()
+///     .allow_origin(String::from("https://app.example.com"))
+///     .allow_origin(String::from("https://*.preview.example.com"))
+///     .allow_methods(vec![Method::GET, Method::POST, Method::DELETE])
+///     .allow_headers(vec![String::from("Content-Type"), String::from("Authorization")])
+///     .allow_credentials(true)
+///     .max_age(Duration::from_secs(600));
+/// let mut app = Server::new();
+/// app.middleware_at(String::from("/api"), Box::new(cors));
+/// ```
+pub struct Cors {
+    origins: AllowedOrigins,
+    methods: Vec<Method>,
+    headers: Vec<String>,
+    any_header: bool,
+    exposed_headers: Vec<String>,
+    credentials: bool,
+    max_age: Option<Duration>,
+}
+
+impl Cors {
+
+    /// Create a new Cors middleware allowing no origin, with the methods GET, HEAD and POST and no request header.
+    pub fn new() -> Self {
+        Self {
+            origins: AllowedOrigins::List(Vec::new()),
+            methods: vec![Method::GET, Method::HEAD, Method::POST],
+            headers: Vec::new(),
+            any_header: false,
+            exposed_headers: Vec::new(),
+            credentials: false,
+            max_age: None,
+        }
+    }
+
+    /// Allow an origin (`https://app.example.com`), a `*` in the origin matches any sequence of characters
+    /// except a dot, `https://*.example.com` allows the direct subdomains of example.com.
+    pub fn allow_origin(mut self, origin: String) -> Self {
+        match &mut self.origins {
+            AllowedOrigins::List(origins) => origins.push(origin.to_ascii_lowercase()),
+            origins => *origins = AllowedOrigins::List(vec![origin.to_ascii_lowercase()]),
+        }
+        self
+    }
+
+    /// Allow every origin, `Access-Control-Allow-Origin: *` is sent.
+    ///
+    /// Panic if credentials are allowed, any web site could then make authenticated requests with the cookies of the user,
+    /// list the trusted origins with allow_origin() or allow_origin_fn() instead.
+    pub fn allow_any_origin(mut self) -> Self {
+        assert!(!self.credentials, "Cors: any origin can't be allowed with credentials");
+        self.origins = AllowedOrigins::Any;
+        self
+    }
+
+    /// Allow the origins for which the predicate returns true, replace the origins previously allowed.
+    pub fn allow_origin_fn(mut self, predicate: Box<OriginPredicate>) -> Self {
+        self.origins = AllowedOrigins::Predicate(predicate);
+        self
+    }
+
+    /// Set the methods allowed in cross-origin requests.
+    pub fn allow_methods(mut self, methods: Vec<Method>) -> Self {
+        self.methods = methods;
+        self
+    }
+
+    /// Set the request headers allowed in cross-origin requests (Content-Type, Authorization, etc.).
+    pub fn allow_headers(mut self, headers: Vec<String>) -> Self {
+        self.headers = headers;
+        self
+    }
+
+    /// Allow every request header, the headers asked by the preflight request are sent back.
+    pub fn allow_any_header(mut self) -> Self {
+        self.any_header = true;
+        self
+    }
+
+    /// Set the response headers the browser lets the page read, in addition to the CORS-safelisted ones.
+    pub fn expose_headers(mut self, headers: Vec<String>) -> Self {
+        self.exposed_headers = headers;
+        self
+    }
+
+    /// Set whether the browser can send the cookies and the Authorization header with the requests, false by default.
+    ///
+    /// Panic if credentials are allowed after allow_any_origin().
+    pub fn allow_credentials(mut self, credentials: bool) -> Self {
+        assert!(!credentials || !matches!(self.origins, AllowedOrigins::Any), "Cors: any origin can't be allowed with credentials");
+        self.credentials = credentials;
+        self
+    }
+
+    /// Set how long the browser can cache the result of a preflight request.
+    pub fn max_age(mut self, max_age: Duration) -> Self {
+        self.max_age = Some(max_age);
+        self
+    }
+
+    /// Return true if the origin is allowed.
+    fn is_allowed(&self, origin: &str) -> bool {
+        match &self.origins {
+            AllowedOrigins::Any => true,
+            AllowedOrigins::List(origins) => {
+                let origin = origin.to_ascii_lowercase();
+                origins.iter().any(|pattern| wildcard_match(pattern, &origin))
+            },
+            AllowedOrigins::Predicate(predicate) => predicate(origin),
+        }
+    }
+
+    /// Add the headers common to the preflight and the actual responses.
+    fn add_origin_headers(&self, response: &mut Response, origin: &str) {
+        if matches!(self.origins, AllowedOrigins::Any) {
+            response.set_header(String::from("Access-Control-Allow-Origin"), String::from("*"));
+        } else {
+            response.set_header(String::from("Access-Control-Allow-Origin"), origin.to_string());
+            response.append_header(String::from("Vary"), String::from("Origin"));
+        }
+        if self.credentials {
+            response.set_header(String::from("Access-Control-Allow-Credentials"), String::from("true"));
+        }
+    }
+
+    /// Answer a preflight request.
+    fn preflight(&self, request: &Request, mut response: Response, origin: &str, requested_method: &str) {
+        response.append_header(String::from("Vary"), String::from("Access-Control-Request-Method, Access-Control-Request-Headers"));
+        let requested_headers: Vec<&str> = request.get_header("Access-Control-Request-Headers")
+            .map(|headers| headers.split(',').map(str::trim).filter(|header| !header.is_empty()).collect())
+            .unwrap_or_default();
+        let method_allowed = Method::from_str(requested_method).is_some_and(|method| self.methods.contains(&method));
+        let headers_allowed = self.any_header
+            || requested_headers.iter().all(|header| self.headers.iter().any(|allowed| allowed.eq_ignore_ascii_case(header)));
+        if !self.is_allowed(origin) || !method_allowed || !headers_allowed {
+            response.set_status(Status::Forbidden);
+            response.send();
+            return;
+        }
+        self.add_origin_headers(&mut response, origin);
+        let methods = self.methods.iter().map(Method::as_str).collect::<Vec<&str>>().join(", ");
+        response.set_header(String::from("Access-Control-Allow-Methods"), methods);
+        let headers = if self.any_header { requested_headers.join(", ") } else { self.headers.join(", ") };
+        if !headers.is_empty() {
+            response.set_header(String::from("Access-Control-Allow-Headers"), headers);
+        }
+        if let Some(max_age) = self.max_age {
+            response.set_header(String::from("Access-Control-Max-Age"), max_age.as_secs().to_string());
+        }
+        response.set_status(Status::NoContent);
+        response.send();
+    }
+}
+
+impl Default for Cors {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Cors {
+    fn handle(&self, request: Request, mut response: Response, next: Next) {
+        let origin = match request.get_header("Origin") {
+            Some(origin) => origin.to_string(),
+            None => return next.run(request, response),
+        };
+        if request.method == Method::OPTIONS {
+            if let Some(requested_method) = request.get_header("Access-Control-Request-Method") {
+                let requested_method = requested_method.trim().to_string();
+                return self.preflight(&request, response, &origin, &requested_method);
+            }
+        }
+        if self.is_allowed(&origin) {
+            self.add_origin_headers(&mut response, &origin);
+            if !self.exposed_headers.is_empty() {
+                response.set_header(String::from("Access-Control-Expose-Headers"), self.exposed_headers.join(", "));
+            }
+        } else if !matches!(self.origins, AllowedOrigins::Any) {
+            // The response depends on the origin even when it isn't allowed
+            response.append_header(String::from("Vary"), String::from("Origin"));
+        }
+        next.run(request, response);
+    }
+}
+
+/// Return true if the value matches the pattern, `*` matches any sequence of characters except a dot.
+fn wildcard_match(pattern: &str, value: &str) -> bool {
+    match pattern.split_once('*') {
+        None => pattern == value,
+        Some((prefix, rest)) => {
+            let Some(value) = value.strip_prefix(prefix) else {
+                return false;
+            };
+            // try every possible length for the part matched by the wildcard
+            value.char_indices().map(|(i, _)| i).chain(std::iter::once(value.len()))
+                .take_while(|&i| !value[..i].contains('.'))
+                .any(|i| i > 0 && wildcard_match(rest, &value[i..]))
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use std::sync::Arc;
+
+    fn run(cors: Cors, method: Method, headers: &[(&str, &str)]) -> String {
+        testing::run(Arc::new(cors), testing::request(method, "/api", headers))
+    }
+
+    #[test]
+    #[should_panic(expected = "any origin can't be allowed with credentials")]
+    fn reject_any_origin_with_credentials() {
+        let _ = Cors::new().allow_any_origin().allow_credentials(true);
+    }
+
+    #[test]
+    #[should_panic(expected = "any origin can't be allowed with credentials")]
+    fn reject_credentials_with_any_origin() {
+        let _ = Cors::new().allow_credentials(true).allow_any_origin();
+    }
+
+    #[test]
+    fn any_origin_sends_wildcard() {
+        let response = run(Cors::new().allow_any_origin().allow_credentials(false), Method::GET, &[("Origin", "null")]);
+        assert_eq!(testing::header(&response, "Access-Control-Allow-Origin"), Some("*"));
+        assert_eq!(testing::header(&response, "Access-Control-Allow-Credentials"), None);
+        assert!(response.ends_with("handled"));
+    }
+
+    #[test]
+    fn echo_allowed_origin_with_credentials() {
+        let cors = || Cors::new().allow_origin(String::from("https://*.example.com")).allow_credentials(true);
+        let response = run(cors(), Method::GET, &[("Origin", "https://app.example.com")]);
+        assert_eq!(testing::header(&response, "Access-Control-Allow-Origin"), Some("https://app.example.com"));
+        assert_eq!(testing::header(&response, "Access-Control-Allow-Credentials"), Some("true"));
+        assert_eq!(testing::header(&response, "Vary"), Some("Origin"));
+
+        for origin in ["https://evil.com", "https://a.b.example.com", "null"] {
+            let response = run(cors(), Method::GET, &[("Origin", origin)]);
+            assert_eq!(testing::header(&response, "Access-Control-Allow-Origin"), None);
+            assert_eq!(testing::header(&response, "Vary"), Some("Origin"));
+            assert!(response.ends_with("handled"));
+        }
+    }
+
+    #[test]
+    fn answer_preflight() {
+        let cors = || Cors::new()
+            .allow_origin(String::from("https://app.example.com"))
+            .allow_methods(vec![Method::GET, Method::DELETE])
+            .allow_headers(vec![String::from("Content-Type")])
+            .max_age(Duration::from_secs(600));
+        let response = run(cors(), Method::OPTIONS, &[
+            ("Origin", "https://app.example.com"), ("Access-Control-Request-Method", "DELETE"), ("Access-Control-Request-Headers", "content-type"),
+        ]);
+        assert_eq!(testing::status(&response), "HTTP/1.1 204 No Content");
+        assert_eq!(testing::header(&response, "Access-Control-Allow-Methods"), Some("GET, DELETE"));
+        assert_eq!(testing::header(&response, "Access-Control-Allow-Headers"), Some("Content-Type"));
+        assert_eq!(testing::header(&response, "Access-Control-Max-Age"), Some("600"));
+
+        let response = run(cors(), Method::OPTIONS, &[("Origin", "https://app.example.com"), ("Access-Control-Request-Method", "PUT")]);
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+        let response = run(cors(), Method::OPTIONS, &[
+            ("Origin", "https://app.example.com"), ("Access-Control-Request-Method", "GET"), ("Access-Control-Request-Headers", "X-Secret"),
+        ]);
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+        let response = run(cors(), Method::OPTIONS, &[("Origin", "https://evil.com"), ("Access-Control-Request-Method", "GET")]);
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+    }
+
+    #[test]
+    fn match_wildcards() {
+        assert!(wildcard_match("https://*.example.com", "https://app.example.com"));
+        assert!(!wildcard_match("https://*.example.com", "https://example.com"));
+        assert!(!wildcard_match("https://*.example.com", "https://a.b.example.com"));
+        assert!(!wildcard_match("https://*.example.com", "https://evil.com/.example.com"));
+        assert!(wildcard_match("https://example.com", "https://example.com"));
+    }
+}
@@ -0,0 +1,98 @@
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, URL_SAFE_NO_PAD};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+
+pub(crate) type HmacSha256 = Hmac<Sha256>;
+
+/// Return size random bytes from the operating system.
+/// 
+/// Panic if the operating system can't provide random bytes, as the server can't work securely without them.
+pub(crate) fn random_bytes(size: usize) -> Vec<u8> {
+    let mut bytes = vec![0; size];
+    getrandom::getrandom(&mut bytes).expect("Cannot get random bytes from the operating system");
+    bytes
+}
+
+/// Return a random url-safe token encoding size random bytes.
+pub(crate) fn random_token(size: usize) -> String {
+    base64_encode(&random_bytes(size))
+}
+
+/// Encode bytes using url-safe base64 without padding.
+pub(crate) fn base64_encode(bytes: &[u8]) -> String {
+    URL_SAFE_NO_PAD.encode(bytes)
+}
+
+/// Decode url-safe base64 without padding, return None if the input isn't valid.
+pub(crate) fn base64_decode(input: &str) -> Option<Vec<u8>> {
+    URL_SAFE_NO_PAD.decode(input).ok()
+}
+
+/// Decode standard base64 with padding (as used by HTTP Basic authentication), return None if the input isn't valid.
+pub(crate) fn base64_decode_standard(input: &str) -> Option<Vec<u8>> {
+    STANDARD.decode(input).ok()
+}
+
+/// Return the HMAC-SHA256 of data.
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Check in constant time that tag is the HMAC-SHA256 of data.
+pub(crate) fn hmac_sha256_verify(key: &[u8], data: &[u8], tag: &[u8]) -> bool {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.verify_slice(tag).is_ok()
+}
+
+/// Compare two byte strings in constant time.
+pub(crate) fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn compare_in_constant_time() {
+        assert!(constant_time_eq(b"", b""));
+        assert!(constant_time_eq(b"secret", b"secret"));
+        assert!(!constant_time_eq(b"secret", b"secreT"));
+        assert!(!constant_time_eq(b"secret", b"Secret"));
+        assert!(!constant_time_eq(b"secret", b"secret!"));
+        assert!(!constant_time_eq(b"secret", b""));
+    }
+
+    #[test]
+    fn hmac_sha256_rfc_4231() {
+        // test case 2
+        let tag = hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        assert_eq!(
+            tag.iter().map(|b| format!("{:02x}", b)).collect::<String>(),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        assert!(hmac_sha256_verify(b"Jefe", b"what do ya want for nothing?", &tag));
+        assert!(!hmac_sha256_verify(b"Jefe", b"what do ya want for nothing!", &tag));
+        assert!(!hmac_sha256_verify(b"Jefe", b"what do ya want for nothing?", &tag[..31]));
+    }
+
+    #[test]
+    fn base64_round_trip() {
+        let bytes = random_bytes(33);
+        assert_eq!(base64_decode(&base64_encode(&bytes)), Some(bytes));
+        assert_eq!(base64_encode(&[0xfb, 0xff]), "-_8");
+        assert_eq!(base64_decode("+/8="), None);
+        assert_eq!(base64_decode_standard("+/8="), Some(vec![0xfb, 0xff]));
+    }
+
+    #[test]
+    fn random_tokens_are_unique() {
+        let token = random_token(32);
+        assert_eq!(token.len(), 43);
+        assert_ne!(token, random_token(32));
+    }
+}
@@ -0,0 +1,344 @@
+use crate::cookie::{self, Cookie, SameSite};
+use crate::crypto;
+use crate::method::Method;
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::Status;
+
+/// Size in bytes of the secret stored in the CSRF cookie.
+const SECRET_SIZE: usize = 32;
+
+/// CsrfToken struct, added to the request extensions by the Csrf middleware, to be embedded in the forms
+/// or sent by the JavaScript code in the X-CSRF-Token header.
+///
+/// A new token is generated for each request (the secret of the cookie masked with random bytes),
+/// all of them stay valid as long as the cookie doesn't change.
+#[derive(Debug, Clone)]
+pub struct CsrfToken {
+    token: String,
+    field_name: String,
+}
+
+impl CsrfToken {
+
+    /// Return the token.
+    pub fn value(&self) -> &str {
+        &self.token
+    }
+
+    /// Return the name of the form field read by the middleware.
+    pub fn field_name(&self) -> &str {
+        &self.field_name
+    }
+
+    /// Return a hidden input to add in HTML forms: `<input type="hidden" name="_csrf" value="...">`.
+    pub fn hidden_input(&self) -> String {
+        // the token is url-safe base64 and the field name is escaped, so the input can't break the HTML
+        format!("<input type=\"hidden\" name=\"{}\" value=\"{}\">", escape_html(&self.field_name), self.token)
+    }
+}
+
+/// Middleware protecting the forms against Cross-Site Request Forgery, using double-submit cookies.
+///
+/// A random secret is stored in a cookie and each request gets a CsrfToken in its extensions.
+/// The requests using an unsafe method (POST, PUT, PATCH and DELETE) must send the token back,
+/// either in the X-CSRF-Token header or in the `_csrf` field of an urlencoded form, otherwise they are rejected with 403 Forbidden.
+/// Multipart forms must send the token in the header (or use an urlencoded form).
+///
+/// The cookie is signed with the server keyring, so a cookie planted by a subdomain or an attacker on the network is rejected:
+/// a keyring must be set with Server::set_cookie_keyring(), otherwise no cookie is created and every unsafe request is rejected.
+/// The cookie is named `__Host-csrf` and has the Secure attribute, which browsers only accept over HTTPS (and on localhost).
+///
+/// ## Example:
+/// ```
+/// use rest_server::cookie::{Key, Keyring};
+/// use rest_server::csrf::{Csrf, CsrfToken};
+/// use rest_server::request::Request;
+/// use rest_server::response::Response;
+/// use rest_server::Server;
+///
+/// fn form(request: Request, mut response: Response) {
+///     let token = request.extensions().get::<CsrfToken>().unwrap();
+///     response.set_body(&format!("<form method=\"post\">{}<input type=\"submit\"></form>", token.hidden_input()));
+///     response.send();
+/// }
+///
+/// let mut app = Server::new();
+/// app.set_cookie_keyring(Keyring::new(Key::generate()));
+/// app.middleware(Box::new(Csrf::new()));
+/// app.get(String::from("/form"), Box::new(form));
+/// ```
+pub struct Csrf {
+    cookie_name: String,
+    field_name: String,
+    header_name: String,
+    path: String,
+    secure: bool,
+    same_site: SameSite,
+}
+
+impl Csrf {
+
+    /// Create a new Csrf middleware.
+    ///
+    /// By default, the cookie is named "__Host-csrf" and sent for every path with the Secure, HttpOnly and SameSite=Lax attributes,
+    /// the token is read from the X-CSRF-Token header or the `_csrf` form field.
+    pub fn new() -> Self {
+        Self {
+            cookie_name: String::from("__Host-csrf"),
+            field_name: String::from("_csrf"),
+            header_name: String::from("X-CSRF-Token"),
+            path: String::from("/"),
+            secure: true,
+            same_site: SameSite::Lax,
+        }
+    }
+
+    /// Set the name of the cookie storing the secret, a name starting with `__Host-` requires the Secure attribute and the path `/`.
+    pub fn cookie_name(mut self, cookie_name: String) -> Self {
+        self.cookie_name = cookie_name;
+        self
+    }
+
+    /// Set the name of the form field containing the token.
+    pub fn field_name(mut self, field_name: String) -> Self {
+        self.field_name = field_name;
+        self
+    }
+
+    /// Set the name of the header containing the token.
+    pub fn header_name(mut self, header_name: String) -> Self {
+        self.header_name = header_name;
+        self
+    }
+
+    /// Set the Path attribute of the cookie.
+    ///
+    /// Panic if the path isn't a valid cookie path, see Cookie::try_path().
+    pub fn path(mut self, path: String) -> Self {
+        if let Err(e) = cookie::check_path(&path) {
+            panic!("{}", e);
+        }
+        self.path = path;
+        self
+    }
+
+    /// Set the Secure attribute of the cookie, enabled by default.
+    ///
+    /// Disable it only for a server reached over plain HTTP on another host than localhost, with a cookie name without the `__Host-` prefix.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.secure = secure;
+        self
+    }
+
+    /// Set the SameSite attribute of the cookie.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.same_site = same_site;
+        self
+    }
+
+    /// Return the token sent by the client, from the header or the form field.
+    fn submitted_token(&self, request: &Request) -> Option<String> {
+        if let Some(token) = request.get_header(&self.header_name) {
+            return Some(token.trim().to_string());
+        }
+        let is_form = request.content_type().is_some_and(|content_type| content_type == "application/x-www-form-urlencoded");
+        if !is_form {
+            return None;
+        }
+        request.form().ok()?.get(&self.field_name).map(String::from)
+    }
+}
+
+impl Default for Csrf {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for Csrf {
+    fn handle(&self, mut request: Request, mut response: Response, next: Next) {
+        let secret = request.get_signed_cookie(&self.cookie_name)
+            .and_then(|secret| crypto::base64_decode(&secret))
+            .filter(|secret| secret.len() == SECRET_SIZE);
+        let unsafe_method = matches!(request.method, Method::POST | Method::PUT | Method::PATCH | Method::DELETE);
+        if unsafe_method {
+            let valid = match (&secret, self.submitted_token(&request)) {
+                (Some(secret), Some(token)) => unmask(&token).is_some_and(|submitted| crypto::constant_time_eq(&submitted, secret)),
+                _ => false,
+            };
+            if !valid {
+                response.set_status(Status::Forbidden);
+                response.set_header(String::from("Content-Type"), String::from("text/plain; charset=utf-8"));
+                response.set_body("Invalid CSRF token");
+                response.send();
+                return;
+            }
+        }
+        let secret = match secret {
+            Some(secret) => secret,
+            None => {
+                let secret = crypto::random_bytes(SECRET_SIZE);
+                let cookie = Cookie::new(self.cookie_name.clone(), crypto::base64_encode(&secret))
+                    .path(self.path.clone())
+                    .secure(self.secure)
+                    .http_only(true)
+                    .same_site(self.same_site);
+                response.add_signed_cookie(cookie);
+                secret
+            },
+        };
+        request.extensions_mut().insert(CsrfToken {
+            token: mask(&secret),
+            field_name: self.field_name.clone(),
+        });
+        next.run(request, response);
+    }
+}
+
+/// Return the secret xored with random bytes, prefixed by these bytes, so the token changes on each request
+/// (a page compressed with a secret which never changes leaks it, see the BREACH attack).
+fn mask(secret: &[u8]) -> String {
+    let mut token = crypto::random_bytes(secret.len());
+    let masked = token.iter().zip(secret).map(|(mask, byte)| mask ^ byte).collect::<Vec<u8>>();
+    token.extend(masked);
+    crypto::base64_encode(&token)
+}
+
+/// Return the secret of a masked token, return None if the token is invalid.
+fn unmask(token: &str) -> Option<Vec<u8>> {
+    let bytes = crypto::base64_decode(token)?;
+    if bytes.len() != 2 * SECRET_SIZE {
+        return None;
+    }
+    let (mask, masked) = bytes.split_at(SECRET_SIZE);
+    Some(mask.iter().zip(masked).map(|(mask, byte)| mask ^ byte).collect())
+}
+
+/// Escape the characters which have a meaning in HTML.
+fn escape_html(value: &str) -> String {
+    value.replace('&', "&amp;").replace('"', "&quot;").replace('<', "&lt;").replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::cookie::{Key, Keyring};
+    use crate::testing;
+    use std::io::Cursor;
+    use std::sync::Arc;
+
+    fn keyring() -> Arc<Keyring> {
+        Arc::new(Keyring::new(Key::from_bytes(&[7; 64]).unwrap()))
+    }
+
+    /// Return the signed cookie header storing the secret.
+    fn cookie(keyring: &Keyring, secret: &[u8]) -> String {
+        let cookie = keyring.sign(&Cookie::new(String::from("__Host-csrf"), crypto::base64_encode(secret)));
+        format!("{}={}", cookie.name(), cookie.value())
+    }
+
+    /// Run the middleware with the keyring set, the handler answers with the token it received.
+    fn run(keyring: Option<Arc<Keyring>>, mut request: Request) -> String {
+        let middleware: Arc<dyn Middleware> = Arc::new(Csrf::new());
+        let (mut response, client) = testing::response();
+        request.set_keyring(keyring.clone());
+        response.set_keyring(keyring);
+        let handler = |request: Request, mut response: Response| {
+            response.set_body(request.extensions().get::<CsrfToken>().unwrap().value());
+            response.send();
+        };
+        Next::new(&[middleware], &handler).run(request, response);
+        testing::read_response(client)
+    }
+
+    fn post(headers: &[(&str, &str)]) -> Request {
+        testing::request(Method::POST, "/form", headers)
+    }
+
+    #[test]
+    fn create_signed_cookie() {
+        let keyring = keyring();
+        let response = run(Some(Arc::clone(&keyring)), testing::request(Method::GET, "/form", &[]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 200 OK");
+        let set_cookie = testing::header(&response, "Set-Cookie").unwrap();
+        assert!(set_cookie.starts_with("__Host-csrf="));
+        assert!(set_cookie.contains("; Path=/") && set_cookie.contains("; Secure") && set_cookie.contains("; HttpOnly"));
+
+        // the cookie and the token sent to the handler work together
+        let value = set_cookie.split(';').next().unwrap();
+        let token = response.split("\r\n\r\n").nth(1).unwrap();
+        let response = run(Some(keyring), post(&[("Cookie", value), ("X-CSRF-Token", token)]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 200 OK");
+        assert_eq!(testing::header(&response, "Set-Cookie"), None);
+    }
+
+    #[test]
+    fn accept_valid_token() {
+        let keyring = keyring();
+        let secret = crypto::random_bytes(SECRET_SIZE);
+        let cookie = cookie(&keyring, &secret);
+        let response = run(Some(Arc::clone(&keyring)), post(&[("Cookie", &cookie), ("X-CSRF-Token", &mask(&secret))]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 200 OK");
+
+        let body = format!("name=value&_csrf={}", mask(&secret));
+        let mut request = post(&[("Cookie", &cookie), ("Content-Type", "application/x-www-form-urlencoded"), ("Content-Length", &body.len().to_string())]);
+        request.set_body_reader(Box::new(Cursor::new(body.into_bytes())));
+        let response = run(Some(keyring), request);
+        assert_eq!(testing::status(&response), "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn reject_missing_token() {
+        let keyring = keyring();
+        let cookie = cookie(&keyring, &crypto::random_bytes(SECRET_SIZE));
+        let response = run(Some(Arc::clone(&keyring)), post(&[("Cookie", &cookie)]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+        let response = run(Some(keyring), post(&[]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+    }
+
+    #[test]
+    fn reject_mismatched_token() {
+        let keyring = keyring();
+        let cookie = cookie(&keyring, &crypto::random_bytes(SECRET_SIZE));
+        let response = run(Some(Arc::clone(&keyring)), post(&[("Cookie", &cookie), ("X-CSRF-Token", &mask(&crypto::random_bytes(SECRET_SIZE)))]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+        let response = run(Some(keyring), post(&[("Cookie", &cookie), ("X-CSRF-Token", "not a token")]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+    }
+
+    #[test]
+    fn reject_forged_cookie() {
+        // an attacker able to set cookies (a subdomain or the network) knows the secret of its own cookie
+        let secret = crypto::random_bytes(SECRET_SIZE);
+        let token = mask(&secret);
+        let unsigned = format!("__Host-csrf={}", crypto::base64_encode(&secret));
+        let response = run(Some(keyring()), post(&[("Cookie", &unsigned), ("X-CSRF-Token", &token)]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+
+        let other_keyring = Keyring::new(Key::generate());
+        let response = run(Some(keyring()), post(&[("Cookie", &cookie(&other_keyring, &secret)), ("X-CSRF-Token", &token)]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+    }
+
+    #[test]
+    fn reject_without_keyring() {
+        let response = run(None, testing::request(Method::GET, "/form", &[]));
+        assert_eq!(testing::header(&response, "Set-Cookie"), None);
+        let secret = crypto::random_bytes(SECRET_SIZE);
+        let response = run(None, post(&[("Cookie", &cookie(&keyring(), &secret)), ("X-CSRF-Token", &mask(&secret))]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+    }
+
+    #[test]
+    fn tokens_are_masked() {
+        let secret = crypto::random_bytes(SECRET_SIZE);
+        let (first, second) = (mask(&secret), mask(&secret));
+        assert_ne!(first, second);
+        assert_eq!(unmask(&first), Some(secret.clone()));
+        assert_eq!(unmask(&second), Some(secret));
+        assert_eq!(unmask("short"), None);
+    }
+}
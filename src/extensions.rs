@@ -0,0 +1,81 @@
+use std::any::{Any, TypeId};
+use std::collections::HashMap;
+use std::fmt::{Debug, Formatter};
+
+/// Extensions struct, a map storing one value per type, used by the middlewares to attach data to a request
+/// (the session, the authenticated user, etc.) which can be read by the handlers.
+#[derive(Default)]
+pub struct Extensions {
+    map: HashMap<TypeId, Box<dyn Any + Send + Sync>>,
+}
+
+impl Extensions {
+
+    /// Create a new empty Extensions.
+    pub fn new() -> Self {
+        Self {
+            map: HashMap::new(),
+        }
+    }
+
+    /// Insert a value, return the previous value of the same type if there was one.
+    pub fn insert<T: Any + Send + Sync>(&mut self, value: T) -> Option<T> {
+        self.map.insert(TypeId::of::<T>(), Box::new(value))
+            .and_then(|previous| previous.downcast::<T>().ok())
+            .map(|previous| *previous)
+    }
+
+    /// Return a reference to the value of type T, if there is no value of this type, return None.
+    pub fn get<T: Any + Send + Sync>(&self) -> Option<&T> {
+        self.map.get(&TypeId::of::<T>()).and_then(|value| value.downcast_ref::<T>())
+    }
+
+    /// Return a mutable reference to the value of type T, if there is no value of this type, return None.
+    pub fn get_mut<T: Any + Send + Sync>(&mut self) -> Option<&mut T> {
+        self.map.get_mut(&TypeId::of::<T>()).and_then(|value| value.downcast_mut::<T>())
+    }
+
+    /// Remove and return the value of type T.
+    pub fn remove<T: Any + Send + Sync>(&mut self) -> Option<T> {
+        self.map.remove(&TypeId::of::<T>())
+            .and_then(|value| value.downcast::<T>().ok())
+            .map(|value| *value)
+    }
+
+    /// Return true if there is a value of type T.
+    pub fn contains<T: Any + Send + Sync>(&self) -> bool {
+        self.map.contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl Debug for Extensions {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Extensions({} values)", self.map.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[derive(Debug, PartialEq)]
+    struct User(String);
+
+    #[test]
+    fn store_one_value_per_type() {
+        let mut extensions = Extensions::new();
+        assert!(!extensions.contains::<User>());
+        assert_eq!(extensions.insert(User(String::from("alice"))), None);
+        assert_eq!(extensions.insert(42u32), None);
+        assert_eq!(extensions.get::<User>(), Some(&User(String::from("alice"))));
+        assert_eq!(extensions.get::<u32>(), Some(&42));
+        assert_eq!(extensions.get::<u64>(), None);
+
+        assert_eq!(extensions.insert(User(String::from("bob"))), Some(User(String::from("alice"))));
+        extensions.get_mut::<User>().unwrap().0.push('!');
+        assert_eq!(extensions.remove::<User>(), Some(User(String::from("bob!"))));
+        assert!(!extensions.contains::<User>());
+        assert!(extensions.contains::<u32>());
+        assert_eq!(format!("{:?}", extensions), "Extensions(1 values)");
+    }
+}
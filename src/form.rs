@@ -0,0 +1,349 @@
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+use std::str::FromStr;
+
+/// Form struct, used to represent a decoded `application/x-www-form-urlencoded` body.
+///
+/// Fields are kept in the order they were sent by the client and a same field name can appear several times
+/// (checkboxes, multiple select, etc.), use get_all() to retrieve every value of a field.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Form {
+    fields: Vec<(String, String)>,
+}
+
+impl Form {
+
+    /// Create a new empty Form.
+    pub fn new() -> Self {
+        Self {
+            fields: Vec::new(),
+        }
+    }
+
+    /// Parse an urlencoded body using the given charset.
+    ///
+    /// `+` are decoded as spaces and `%XX` sequences as the byte they represent, invalid sequences are kept as is.
+    /// Only the first `=` of a pair separate the name from the value, so values can contain `=`.
+    pub fn parse(body: &[u8], charset: Charset) -> Self {
+        let fields = body.split(|b| *b == b'&')
+            .filter(|pair| !pair.is_empty())
+            .map(|pair| {
+                let (name, value) = match pair.iter().position(|b| *b == b'=') {
+                    Some(index) => (&pair[..index], &pair[index + 1..]),
+                    None => (pair, &[][..]),
+                };
+                (charset.decode(&percent_decode(name)), charset.decode(&percent_decode(value)))
+            })
+            .collect::<Vec<(String, String)>>();
+        Self {
+            fields,
+        }
+    }
+
+    /// Parse an urlencoded body, the charset is given by the `_charset_` field if the client sent one, otherwise UTF-8 is used.
+    ///
+    /// Return Err if the `_charset_` field contains a charset which isn't supported.
+    pub fn parse_detect_charset(body: &[u8]) -> Result<Self, FormError> {
+        let charset = match Self::parse(body, Charset::Utf8).get("_charset_") {
+            Some(label) => Charset::from_label(label).ok_or_else(|| FormError::UnsupportedCharset(label.to_string()))?,
+            None => Charset::Utf8,
+        };
+        Ok(Self::parse(body, charset))
+    }
+
+    /// Return the first value of the field, if the field is not found, return None.
+    pub fn get(&self, key: &str) -> Option<&str> {
+        self.fields.iter().find(|(name, _)| name == key).map(|(_, value)| value.as_str())
+    }
+
+    /// Return every value of the field in the order they were sent, the Vec is empty if the field is not found.
+    pub fn get_all(&self, key: &str) -> Vec<&str> {
+        self.fields.iter().filter(|(name, _)| name == key).map(|(_, value)| value.as_str()).collect()
+    }
+
+    /// Return the first value of the field converted to T.
+    ///
+    /// Return Err if the field is missing or if its value can't be parsed.
+    pub fn get_parsed<T>(&self, key: &str) -> Result<T, FormError>
+    where
+        T: FromStr,
+        T::Err: Display, {
+            let value = self.get(key).ok_or_else(|| FormError::MissingField(key.to_string()))?;
+            value.parse::<T>().map_err(|e| FormError::InvalidField {
+                name: key.to_string(),
+                value: value.to_string(),
+                reason: e.to_string(),
+            })
+        }
+
+    /// Return true if the form contains at least one value for the field.
+    pub fn contains_key(&self, key: &str) -> bool {
+        self.fields.iter().any(|(name, _)| name == key)
+    }
+
+    /// Add a value to the form, existing values of the field are kept.
+    pub fn append(&mut self, key: String, value: String) {
+        self.fields.push((key, value));
+    }
+
+    /// Return an iterator over the (name, value) pairs of the form, in the order they were sent.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &str)> {
+        self.fields.iter().map(|(name, value)| (name.as_str(), value.as_str()))
+    }
+
+    /// Return the number of (name, value) pairs in the form.
+    pub fn len(&self) -> usize {
+        self.fields.len()
+    }
+
+    /// Return true if the form doesn't contain any field.
+    pub fn is_empty(&self) -> bool {
+        self.fields.is_empty()
+    }
+
+    /// Convert the form to a HashMap, if a field is repeated, the last value is kept.
+    pub fn to_map(&self) -> HashMap<String, String> {
+        self.fields.iter().cloned().collect()
+    }
+
+    /// Convert the form to T using its FromForm implementation.
+    pub fn deserialize<T: FromForm>(&self) -> Result<T, FormError> {
+        T::from_form(self)
+    }
+
+    /// Encode the form as an UTF-8 `application/x-www-form-urlencoded` string.
+    pub fn encode(&self) -> String {
+        self.fields.iter()
+            .map(|(name, value)| format!("{}={}", percent_encode(name), percent_encode(value)))
+            .collect::<Vec<String>>()
+            .join("&")
+    }
+}
+
+impl FromIterator<(String, String)> for Form {
+    fn from_iter<I: IntoIterator<Item = (String, String)>>(iter: I) -> Self {
+        Self {
+            fields: iter.into_iter().collect(),
+        }
+    }
+}
+
+/// Charset used to decode the bytes of an urlencoded body once percent-decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    /// UTF-8, the default charset, invalid sequences are replaced by U+FFFD.
+    Utf8,
+    /// ISO-8859-1 (latin1), every byte is mapped to the unicode code point of the same value.
+    Latin1,
+}
+
+impl Charset {
+
+    /// Return a Option<Charset> from a charset label (case insensitive, e.g. "utf-8", "ISO-8859-1").
+    /// If the charset is not supported, return None.
+    pub fn from_label(label: &str) -> Option<Charset> {
+        match label.trim().trim_matches('"').to_ascii_lowercase().as_str() {
+            "utf-8" | "utf8" | "unicode-1-1-utf-8" => Some(Charset::Utf8),
+            "iso-8859-1" | "iso8859-1" | "iso_8859-1" | "latin1" | "l1" | "us-ascii" | "ascii" => Some(Charset::Latin1),
+            _ => None,
+        }
+    }
+
+    /// Decode the bytes to a String using the charset.
+    pub fn decode(&self, bytes: &[u8]) -> String {
+        match self {
+            Charset::Utf8 => String::from_utf8_lossy(bytes).into_owned(),
+            Charset::Latin1 => bytes.iter().map(|b| *b as char).collect(),
+        }
+    }
+}
+
+/// Trait implemented by the types which can be built from a Form, see Request::form_as().
+///
+/// ## Example:
+/// ```
+/// use rest_server::form::{Form, FormError, FromForm};
+///
+/// struct Contact {
+///     name: String,
+///     age: u8,
+/// }
+///
+/// impl FromForm for Contact {
+///     fn from_form(form: &Form) -> Result<Self, FormError> {
+///         Ok(Self {
+///             name: form.get_parsed("name")?,
+///             age: form.get_parsed("age")?,
+///         })
+///     }
+/// }
+/// ```
+pub trait FromForm: Sized {
+    fn from_form(form: &Form) -> Result<Self, FormError>;
+}
+
+impl FromForm for Form {
+    fn from_form(form: &Form) -> Result<Self, FormError> {
+        Ok(form.clone())
+    }
+}
+
+impl FromForm for HashMap<String, String> {
+    fn from_form(form: &Form) -> Result<Self, FormError> {
+        Ok(form.to_map())
+    }
+}
+
+/// Error returned when a form can't be decoded or converted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FormError {
+    /// The request content-type isn't `application/x-www-form-urlencoded`.
+    UnsupportedContentType(String),
+    /// The charset given by the client isn't supported.
+    UnsupportedCharset(String),
+    /// A required field is missing.
+    MissingField(String),
+    /// A field value can't be converted to the expected type.
+    InvalidField {
+        name: String,
+        value: String,
+        reason: String,
+    },
+}
+
+impl Display for FormError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FormError::UnsupportedContentType(content_type) => write!(f, "Unsupported content-type: {}", content_type),
+            FormError::UnsupportedCharset(charset) => write!(f, "Unsupported charset: {}", charset),
+            FormError::MissingField(name) => write!(f, "Missing field: {}", name),
+            FormError::InvalidField { name, value, reason } => write!(f, "Invalid value {:?} for field {}: {}", value, name, reason),
+        }
+    }
+}
+
+impl std::error::Error for FormError {}
+
+/// Decode `+` as spaces and `%XX` as the byte they represent, invalid sequences are kept as is.
+pub fn percent_decode(input: &[u8]) -> Vec<u8> {
+    let mut output = Vec::with_capacity(input.len());
+    let mut i = 0;
+    while i < input.len() {
+        match input[i] {
+            b'+' => output.push(b' '),
+            b'%' if i + 2 < input.len() => {
+                match (hex_value(input[i + 1]), hex_value(input[i + 2])) {
+                    (Some(high), Some(low)) => {
+                        output.push(high << 4 | low);
+                        i += 2;
+                    },
+                    _ => output.push(b'%'),
+                }
+            },
+            b => output.push(b),
+        }
+        i += 1;
+    }
+    output
+}
+
+/// Encode a string to be used in an urlencoded body, spaces are encoded as `+`.
+pub fn percent_encode(input: &str) -> String {
+    let mut output = String::with_capacity(input.len());
+    for b in input.bytes() {
+        match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'*' => output.push(b as char),
+            b' ' => output.push('+'),
+            _ => output.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    output
+}
+
+fn hex_value(b: u8) -> Option<u8> {
+    match b {
+        b'0'..=b'9' => Some(b - b'0'),
+        b'a'..=b'f' => Some(b - b'a' + 10),
+        b'A'..=b'F' => Some(b - b'A' + 10),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_plus_and_percent() {
+        let form = Form::parse(b"name=John+Doe&city=S%C3%A3o%20Paulo&sum=1%2B1%3D2", Charset::Utf8);
+        assert_eq!(form.get("name"), Some("John Doe"));
+        assert_eq!(form.get("city"), Some("São Paulo"));
+        assert_eq!(form.get("sum"), Some("1+1=2"));
+    }
+
+    #[test]
+    fn keep_malformed_escapes() {
+        let form = Form::parse(b"a=100%&b=%zz&c=%4&d=%%41", Charset::Utf8);
+        assert_eq!(form.get("a"), Some("100%"));
+        assert_eq!(form.get("b"), Some("%zz"));
+        assert_eq!(form.get("c"), Some("%4"));
+        assert_eq!(form.get("d"), Some("%A"));
+    }
+
+    #[test]
+    fn split_on_first_equal_and_skip_empty_pairs() {
+        let form = Form::parse(b"token=abc==&&flag&empty=", Charset::Utf8);
+        assert_eq!(form.get("token"), Some("abc=="));
+        assert_eq!(form.get("flag"), Some(""));
+        assert_eq!(form.get("empty"), Some(""));
+        assert_eq!(form.len(), 3);
+    }
+
+    #[test]
+    fn keep_repeated_keys_in_order() {
+        let form = Form::parse(b"tag=a&other=x&tag=b&tag=c", Charset::Utf8);
+        assert_eq!(form.get("tag"), Some("a"));
+        assert_eq!(form.get_all("tag"), vec!["a", "b", "c"]);
+        assert!(form.get_all("missing").is_empty());
+        assert_eq!(form.to_map().get("tag").map(String::as_str), Some("c"));
+        assert_eq!(form.iter().map(|(name, _)| name).collect::<Vec<&str>>(), vec!["tag", "other", "tag", "tag"]);
+    }
+
+    #[test]
+    fn detect_charset() {
+        let form = Form::parse_detect_charset(b"_charset_=ISO-8859-1&name=Jos%E9").unwrap();
+        assert_eq!(form.get("name"), Some("José"));
+
+        let form = Form::parse_detect_charset(b"name=Jos%C3%A9").unwrap();
+        assert_eq!(form.get("name"), Some("José"));
+
+        assert_eq!(
+            Form::parse_detect_charset(b"_charset_=koi8-r&name=x"),
+            Err(FormError::UnsupportedCharset(String::from("koi8-r")))
+        );
+    }
+
+    #[test]
+    fn invalid_utf8_is_replaced() {
+        let form = Form::parse(b"name=%FF", Charset::Utf8);
+        assert_eq!(form.get("name"), Some("\u{FFFD}"));
+    }
+
+    #[test]
+    fn encode_round_trip() {
+        let mut form = Form::new();
+        form.append(String::from("full name"), String::from("Zoë & co=1"));
+        form.append(String::from("full name"), String::from("+"));
+        let encoded = form.encode();
+        assert_eq!(encoded, "full+name=Zo%C3%AB+%26+co%3D1&full+name=%2B");
+        assert_eq!(Form::parse(encoded.as_bytes(), Charset::Utf8), form);
+    }
+
+    #[test]
+    fn get_parsed() {
+        let form = Form::parse(b"age=42&name=x", Charset::Utf8);
+        assert_eq!(form.get_parsed::<u8>("age"), Ok(42));
+        assert_eq!(form.get_parsed::<u8>("missing"), Err(FormError::MissingField(String::from("missing"))));
+        assert!(matches!(form.get_parsed::<u8>("name"), Err(FormError::InvalidField { .. })));
+    }
+}
@@ -0,0 +1,91 @@
+use crate::shutdown::Shutdown;
+use std::io;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::thread::JoinHandle;
+
+/// ServerHandle struct, a server running in a background thread, returned by Server::bind().
+///
+/// The server is stopped and joined when the handle is dropped.
+///
+/// ## Example:
+/// ```
+/// use rest_server::Server;
+/// use std::io::{Read, Write};
+/// use std::net::TcpStream;
+///
+/// let mut app = Server::new();
+/// app.get(String::from("/"), Box::new(|_request, mut response| {
+///     response.set_body("Hello");
+///     response.send();
+/// }));
+/// // port 0: the system picks a free port
+/// let handle = app.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+///
+/// let mut stream = TcpStream::connect(handle.local_addr()).unwrap();
+/// stream.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+/// let mut content = String::new();
+/// stream.read_to_string(&mut content).unwrap();
+/// assert!(content.ends_with("Hello"));
+///
+/// handle.shutdown();
+/// handle.join().unwrap();
+/// ```
+pub struct ServerHandle {
+    local_addr: SocketAddr,
+    shutdown: Arc<Shutdown>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl ServerHandle {
+
+    pub(crate) fn new(local_addr: SocketAddr, shutdown: Arc<Shutdown>, thread: JoinHandle<io::Result<()>>) -> Self {
+        Self {
+            local_addr,
+            shutdown,
+            thread: Some(thread),
+        }
+    }
+
+    /// Return the address on which the server listens, with the port chosen by the system if the server was bound to port 0.
+    pub fn local_addr(&self) -> SocketAddr {
+        self.local_addr
+    }
+
+    /// Start the shutdown of the server: it stops accepting connections and the running requests are handled,
+    /// up to the shutdown timeout (see Server::set_shutdown_timeout()).
+    /// Return immediately, use join() to wait for the server to stop.
+    pub fn shutdown(&self) {
+        self.shutdown.request();
+    }
+
+    /// Return true if the server thread has stopped.
+    pub fn is_finished(&self) -> bool {
+        self.thread.as_ref().is_none_or(|thread| thread.is_finished())
+    }
+
+    /// Wait until the server stops and its workers have handled the running requests.
+    ///
+    /// Return Err if the server stopped with an error or if its thread panicked.
+    pub fn join(mut self) -> io::Result<()> {
+        self.wait()
+    }
+
+    fn wait(&mut self) -> io::Result<()> {
+        match self.thread.take() {
+            Some(thread) => thread.join().unwrap_or_else(|_| Err(io::Error::other("The server thread panicked"))),
+            None => Ok(()),
+        }
+    }
+}
+
+impl Drop for ServerHandle {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            self.shutdown();
+            if let Err(e) = self.wait() {
+                eprintln!("{}", e);
+            }
+        }
+    }
+}
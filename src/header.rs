@@ -0,0 +1,42 @@
+//! Helpers to parse the parameters of HTTP headers, shared by the modules.
+
+/// Decode an extended parameter value (`charset'language'percent-encoded-value`, see [RFC 8187](https://tools.ietf.org/html/rfc8187)),
+/// only UTF-8 and ISO-8859-1 are supported.
+///
+/// Return None if the charset is unsupported or the value has no language part.
+pub(crate) fn decode_ext_value(value: &str) -> Option<String> {
+    let mut parts = value.splitn(3, '\'');
+    let charset = crate::form::Charset::from_label(parts.next()?)?;
+    let encoded = parts.nth(1)?;
+    let bytes = encoded.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        if bytes[i] == b'%' && i + 2 < bytes.len() {
+            if let Some(b) = std::str::from_utf8(&bytes[i + 1..i + 3]).ok().and_then(|hex| u8::from_str_radix(hex, 16).ok()) {
+                decoded.push(b);
+                i += 3;
+                continue;
+            }
+        }
+        decoded.push(bytes[i]);
+        i += 1;
+    }
+    Some(charset.decode(&decoded))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_extended_values() {
+        assert_eq!(decode_ext_value("UTF-8''na%C3%AFve.txt").as_deref(), Some("naïve.txt"));
+        assert_eq!(decode_ext_value("utf-8'en'%E2%82%AC%20rates").as_deref(), Some("€ rates"));
+        assert_eq!(decode_ext_value("iso-8859-1'en'%A3%20rates").as_deref(), Some("£ rates"));
+        assert_eq!(decode_ext_value("UTF-8''100%").as_deref(), Some("100%"));
+        assert_eq!(decode_ext_value("UTF-8''%zz").as_deref(), Some("%zz"));
+        assert_eq!(decode_ext_value("UTF-8'na%C3%AFve.txt"), None);
+        assert_eq!(decode_ext_value("KOI8-R''%C1"), None);
+    }
+}
@@ -0,0 +1,325 @@
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::Status;
+use std::fmt::{Display, Formatter};
+use std::net::IpAddr;
+
+/// IpNet struct, a range of IPv4 or IPv6 addresses in CIDR notation (`10.0.0.0/8`, `fd00::/8`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNet {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNet {
+
+    /// Create a new IpNet, return Err if the prefix is longer than the address.
+    pub fn new(addr: IpAddr, prefix: u8) -> Result<Self, String> {
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        if prefix > max {
+            return Err(format!("Invalid prefix length /{} for {}", prefix, addr));
+        }
+        if let (IpAddr::V6(v6), 96..) = (addr, prefix) {
+            if let Some(v4) = v6.to_ipv4_mapped() {
+                return Ok(Self {
+                    addr: IpAddr::V4(v4),
+                    prefix: prefix - 96,
+                });
+            }
+        }
+        Ok(Self {
+            addr,
+            prefix,
+        })
+    }
+
+    /// Parse a range in CIDR notation, an address without prefix is a range containing only this address.
+    pub fn parse(input: &str) -> Result<Self, String> {
+        let (addr, prefix) = match input.trim().split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (input.trim(), None),
+        };
+        let addr: IpAddr = addr.parse().map_err(|_| format!("Invalid IP address: {}", addr))?;
+        let prefix = match prefix {
+            Some(prefix) => prefix.parse::<u8>().map_err(|_| format!("Invalid prefix length: {}", prefix))?,
+            None if addr.is_ipv4() => 32,
+            None => 128,
+        };
+        Self::new(addr, prefix)
+    }
+
+    /// Return true if the address is in the range, IPv4-mapped IPv6 addresses (`::ffff:10.0.0.1`) are handled as IPv4 addresses.
+    pub fn contains(&self, addr: IpAddr) -> bool {
+        match (self.addr, normalize(addr)) {
+            (IpAddr::V4(net), IpAddr::V4(addr)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(addr) & mask
+            },
+            (IpAddr::V6(net), IpAddr::V6(addr)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(addr) & mask
+            },
+            _ => false,
+        }
+    }
+}
+
+impl Display for IpNet {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// Convert the IPv4-mapped IPv6 addresses to IPv4 addresses.
+fn normalize(addr: IpAddr) -> IpAddr {
+    match addr {
+        IpAddr::V6(v6) => v6.to_ipv4_mapped().map(IpAddr::V4).unwrap_or(addr),
+        addr => addr,
+    }
+}
+
+/// ClientIp struct, the address of the client, added to the request extensions by the IpFilter middleware.
+///
+/// Behind a trusted reverse proxy, this is the address forwarded by the proxy, otherwise it's the peer address of the connection (see Request::connection()).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClientIp {
+    ip: IpAddr,
+}
+
+impl ClientIp {
+
+    /// Return the address of the client.
+    pub fn ip(&self) -> IpAddr {
+        self.ip
+    }
+}
+
+/// Middleware allowing or denying the requests depending on the IP address of the client, the denied requests get 403 Forbidden.
+///
+/// A request is denied if its address is in a denied range or, when at least one range is allowed, if it isn't in an allowed range.
+///
+/// When the server is behind reverse proxies, their addresses must be trusted: if the request comes from a trusted proxy,
+/// the address of the client is read from the `Forwarded` header (or `X-Forwarded-For` if there is no `Forwarded` header),
+/// from right to left, skipping the addresses of trusted proxies. The repeated header lines are combined in order, whatever the case of their name.
+/// The headers are ignored if the request doesn't come from a trusted proxy, so a client can't spoof its address.
+///
+/// ## Example:
+/// ```
+/// use rest_server::ipfilter::{IpFilter, IpNet};
+/// use rest_server::Server;
+///
+/// let filter = IpFilter::new()
+///     .allow(IpNet::parse("10.0.0.0/8").unwrap())
+///     .allow(IpNet::parse("fd00::/8").unwrap())
+///     .deny(IpNet::parse("10.66.0.0/16").unwrap())
+///     .trust_proxy(IpNet::parse("127.0.0.1").unwrap());
+/// let mut app = Server::new();
+/// app.middleware_at(String::from("/admin"), Box::new(filter));
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct IpFilter {
+    allowed: Vec<IpNet>,
+    denied: Vec<IpNet>,
+    trusted_proxies: Vec<IpNet>,
+}
+
+impl IpFilter {
+
+    /// Create a new IpFilter allowing every address.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Allow a range, once a range is allowed, the addresses in no allowed range are denied.
+    pub fn allow(mut self, net: IpNet) -> Self {
+        self.allowed.push(net);
+        self
+    }
+
+    /// Deny a range, the denied ranges take precedence over the allowed ones.
+    pub fn deny(mut self, net: IpNet) -> Self {
+        self.denied.push(net);
+        self
+    }
+
+    /// Trust the reverse proxies in this range to forward the address of the client.
+    pub fn trust_proxy(mut self, net: IpNet) -> Self {
+        self.trusted_proxies.push(net);
+        self
+    }
+
+    /// Return true if the address is allowed.
+    pub fn is_allowed(&self, ip: IpAddr) -> bool {
+        !self.denied.iter().any(|net| net.contains(ip)) && (self.allowed.is_empty() || self.allowed.iter().any(|net| net.contains(ip)))
+    }
+
+    /// Return the address of the client, None if the request has no peer address or if a forwarded address is unknown or invalid.
+    pub fn client_ip(&self, request: &Request) -> Option<IpAddr> {
+        let peer = normalize(request.connection().peer_addr?.ip());
+        if !self.is_trusted(peer) {
+            return Some(peer);
+        }
+        // a repeated header is the same as a single header with the values separated by commas (RFC 9110),
+        // every line must be read, in order, or a client could hide its address behind the one added by the proxy
+        let forwarded = request.get_headers("Forwarded");
+        let forwarded = if !forwarded.is_empty() {
+            parse_forwarded(&forwarded.join(","))
+        } else {
+            request.get_headers("X-Forwarded-For").join(",").split(',')
+                .map(str::trim)
+                .filter(|addr| !addr.is_empty())
+                .map(parse_addr)
+                .collect()
+        };
+        let mut client = peer;
+        for addr in forwarded.into_iter().rev() {
+            match addr {
+                // an unknown or obfuscated hop, the address of the client can't be known
+                None => return None,
+                Some(addr) => {
+                    client = normalize(addr);
+                    if !self.is_trusted(client) {
+                        break;
+                    }
+                },
+            }
+        }
+        Some(client)
+    }
+
+    fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.trusted_proxies.iter().any(|net| net.contains(ip))
+    }
+}
+
+impl Middleware for IpFilter {
+    fn handle(&self, mut request: Request, mut response: Response, next: Next) {
+        match self.client_ip(&request) {
+            Some(ip) if self.is_allowed(ip) => {
+                request.extensions_mut().insert(ClientIp {
+                    ip,
+                });
+                next.run(request, response);
+            },
+            _ => {
+                response.set_status(Status::Forbidden);
+                response.set_header(String::from("Content-Type"), String::from("text/plain; charset=utf-8"));
+                response.set_body(Status::Forbidden.as_str());
+                response.send();
+            },
+        }
+    }
+}
+
+/// Return the `for` addresses of a Forwarded header (see [RFC 7239](https://tools.ietf.org/html/rfc7239)),
+/// None for the unknown and obfuscated ones.
+fn parse_forwarded(header: &str) -> Vec<Option<IpAddr>> {
+    header.split(',')
+        .filter_map(|element| {
+            element.split(';')
+                .filter_map(|pair| pair.split_once('='))
+                .find(|(name, _)| name.trim().eq_ignore_ascii_case("for"))
+                .map(|(_, value)| parse_addr(value.trim().trim_matches('"')))
+        })
+        .collect()
+}
+
+/// Parse an address which can have a port (`192.0.2.1:8080`, `[2001:db8::1]:8080`).
+fn parse_addr(value: &str) -> Option<IpAddr> {
+    if let Ok(addr) = value.parse::<IpAddr>() {
+        return Some(addr);
+    }
+    if let Some(rest) = value.strip_prefix('[') {
+        return rest.split_once(']').and_then(|(addr, _)| addr.parse().ok());
+    }
+    value.rsplit_once(':').and_then(|(addr, _)| addr.parse().ok())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::connection::ConnectionInfo;
+    use crate::method::Method;
+    use crate::testing;
+    use std::net::SocketAddr;
+    use std::sync::Arc;
+
+    fn filter() -> IpFilter {
+        IpFilter::new().trust_proxy(IpNet::parse("10.0.0.0/8").unwrap())
+    }
+
+    fn request(peer: &str, headers: &[(&str, &str)]) -> Request {
+        let mut request = testing::request(Method::GET, "/", headers);
+        request.set_connection(ConnectionInfo {
+            peer_addr: Some(peer.parse::<SocketAddr>().unwrap()),
+            ..ConnectionInfo::default()
+        });
+        request
+    }
+
+    /// Return the client address found by a filter trusting 10.0.0.0/8.
+    fn client_ip(peer: &str, headers: &[(&str, &str)]) -> Option<IpAddr> {
+        filter().client_ip(&request(peer, headers))
+    }
+
+    fn ip(addr: &str) -> Option<IpAddr> {
+        Some(addr.parse().unwrap())
+    }
+
+    #[test]
+    fn parse_and_match_ranges() {
+        let net = IpNet::parse("192.168.1.0/24").unwrap();
+        assert!(net.contains("192.168.1.77".parse().unwrap()));
+        assert!(!net.contains("192.168.2.1".parse().unwrap()));
+        assert!(IpNet::parse("::ffff:10.1.2.3").unwrap().contains("10.1.2.3".parse().unwrap()));
+        assert!(IpNet::parse("fd00::/8").unwrap().contains("fd12::1".parse().unwrap()));
+        assert!(IpNet::parse("0.0.0.0/0").unwrap().contains("8.8.8.8".parse().unwrap()));
+        assert!(IpNet::parse("10.0.0.0/33").is_err());
+        assert!(IpNet::parse("not an address").is_err());
+    }
+
+    #[test]
+    fn ignore_headers_from_untrusted_peers() {
+        assert_eq!(client_ip("203.0.113.7:5000", &[("X-Forwarded-For", "10.1.1.1"), ("Forwarded", "for=10.1.1.1")]), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn read_forwarded_addresses() {
+        assert_eq!(client_ip("10.0.0.1:5000", &[("X-Forwarded-For", "198.51.100.1, 203.0.113.7, 10.0.0.2")]), ip("203.0.113.7"));
+        assert_eq!(client_ip("10.0.0.1:5000", &[("Forwarded", "for=198.51.100.1, for=\"[2001:db8::1]:4711\";proto=https")]), ip("2001:db8::1"));
+        assert_eq!(client_ip("10.0.0.1:5000", &[("Forwarded", "for=unknown")]), None);
+    }
+
+    #[test]
+    fn combine_duplicate_headers() {
+        // the client sends its own header, the proxy appends a line with the real address
+        assert_eq!(client_ip("10.0.0.1:5000", &[("X-Forwarded-For", "192.0.2.1"), ("X-Forwarded-For", "203.0.113.7")]), ip("203.0.113.7"));
+        assert_eq!(client_ip("10.0.0.1:5000", &[("Forwarded", "for=192.0.2.1"), ("Forwarded", "for=203.0.113.7")]), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn combine_headers_with_different_cases() {
+        // the proxy appends a lowercase line, the exact name sent by the client must not win
+        assert_eq!(client_ip("10.0.0.1:5000", &[("X-Forwarded-For", "192.0.2.1"), ("x-forwarded-for", "203.0.113.7")]), ip("203.0.113.7"));
+        assert_eq!(client_ip("10.0.0.1:5000", &[("x-forwarded-for", "192.0.2.1"), ("X-Forwarded-For", "203.0.113.7")]), ip("203.0.113.7"));
+        assert_eq!(client_ip("10.0.0.1:5000", &[("X-FORWARDED-FOR", "203.0.113.7"), ("x-forwarded-for", "192.0.2.1")]), ip("192.0.2.1"));
+        assert_eq!(client_ip("10.0.0.1:5000", &[("forwarded", "for=192.0.2.1"), ("FORWARDED", "for=203.0.113.7")]), ip("203.0.113.7"));
+    }
+
+    #[test]
+    fn allow_and_deny() {
+        let filter = IpFilter::new()
+            .allow(IpNet::parse("10.0.0.0/8").unwrap())
+            .deny(IpNet::parse("10.66.0.0/16").unwrap());
+        assert!(filter.is_allowed("10.1.2.3".parse().unwrap()));
+        assert!(!filter.is_allowed("10.66.2.3".parse().unwrap()));
+        assert!(!filter.is_allowed("192.0.2.1".parse().unwrap()));
+
+        let filter: Arc<dyn Middleware> = Arc::new(filter);
+        let response = testing::run(Arc::clone(&filter), request("[::ffff:10.1.2.3]:5000", &[]));
+        assert!(response.ends_with("handled"));
+        let response = testing::run(filter, request("192.0.2.1:5000", &[]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+    }
+}
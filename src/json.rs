@@ -0,0 +1,60 @@
+use crate::status::Status;
+use std::fmt::{Display, Formatter};
+
+/// Error returned by Request::json() and Response::json().
+#[derive(Debug)]
+pub enum JsonError {
+    /// The request content-type isn't `application/json` (or a `+json` media type).
+    UnsupportedContentType(String),
+    /// The body isn't valid JSON or doesn't match the expected type, line and column give the location of the error in the body.
+    Parse {
+        message: String,
+        line: usize,
+        column: usize,
+    },
+    /// The value can't be serialized to JSON.
+    Serialize(String),
+}
+
+impl JsonError {
+
+    /// Return the status which should be sent to the client for this error.
+    /// 
+    /// 415 Unsupported Media Type for a wrong content-type, 400 Bad Request if the body can't be parsed and 500 Internal Server Error if a response can't be serialized.
+    pub fn status(&self) -> Status {
+        match self {
+            JsonError::UnsupportedContentType(_) => Status::UnsupportedMediaType,
+            JsonError::Parse { .. } => Status::BadRequest,
+            JsonError::Serialize(_) => Status::InternalServerError,
+        }
+    }
+
+    /// Create a Parse error from a deserialization error of serde_json.
+    pub(crate) fn parse(e: serde_json::Error) -> Self {
+        // serde_json adds the location at the end of the message, it's already in line and column
+        let message = e.to_string();
+        let suffix = format!(" at line {} column {}", e.line(), e.column());
+        JsonError::Parse {
+            message: message.strip_suffix(&suffix).unwrap_or(&message).to_string(),
+            line: e.line(),
+            column: e.column(),
+        }
+    }
+}
+
+impl Display for JsonError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JsonError::UnsupportedContentType(content_type) => write!(f, "Unsupported content-type: {:?}, expected application/json", content_type),
+            JsonError::Parse { message, line, column } => write!(f, "Invalid JSON at line {} column {}: {}", line, column, message),
+            JsonError::Serialize(message) => write!(f, "Cannot serialize to JSON: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for JsonError {}
+
+/// Return true if the media type is `application/json` or ends with `+json` (e.g. `application/problem+json`).
+pub(crate) fn is_json_media_type(media_type: &str) -> bool {
+    media_type == "application/json" || (media_type.starts_with("application/") && media_type.ends_with("+json"))
+}
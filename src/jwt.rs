@@ -0,0 +1,800 @@
+use crate::auth;
+use crate::crypto;
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::Status;
+use p256::ecdsa::signature::Verifier;
+use rsa::{BigUint, RsaPublicKey};
+use serde_json::{Map, Value};
+use sha2::Sha256;
+use std::fmt::{Debug, Display, Formatter};
+use std::fs;
+use std::path::PathBuf;
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Signature algorithms supported to verify a JWT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    /// HMAC with SHA-256, the key is a shared secret.
+    HS256,
+    /// RSASSA-PKCS1-v1_5 with SHA-256, the key is a RSA public key.
+    RS256,
+    /// ECDSA with the P-256 curve and SHA-256, the key is a P-256 public key.
+    ES256,
+}
+
+impl Algorithm {
+
+    /// Return a Option<Algorithm> from the `alg` header value, return None if the algorithm isn't supported.
+    pub fn from_name(name: &str) -> Option<Algorithm> {
+        match name {
+            "HS256" => Some(Algorithm::HS256),
+            "RS256" => Some(Algorithm::RS256),
+            "ES256" => Some(Algorithm::ES256),
+            _ => None,
+        }
+    }
+}
+
+/// Key used to verify the signature of a JWT.
+#[derive(Clone)]
+pub enum JwtKey {
+    /// Shared secret for HS256.
+    Hmac(Vec<u8>),
+    /// RSA public key for RS256.
+    Rsa(RsaPublicKey),
+    /// P-256 public key for ES256.
+    Ec(p256::ecdsa::VerifyingKey),
+}
+
+impl JwtKey {
+
+    /// Return the algorithm this key can verify, a token is only verified with the keys of its algorithm.
+    pub fn algorithm(&self) -> Algorithm {
+        match self {
+            JwtKey::Hmac(_) => Algorithm::HS256,
+            JwtKey::Rsa(_) => Algorithm::RS256,
+            JwtKey::Ec(_) => Algorithm::ES256,
+        }
+    }
+
+    /// Parse a key in JWK format (see [RFC 7517](https://tools.ietf.org/html/rfc7517)), only "oct", "RSA" and "EC" (P-256) keys are supported.
+    fn from_jwk(jwk: &Map<String, Value>) -> Result<Self, String> {
+        let field = |name: &str| -> Result<Vec<u8>, String> {
+            jwk.get(name).and_then(Value::as_str).and_then(crypto::base64_decode)
+                .ok_or_else(|| format!("missing or invalid \"{}\"", name))
+        };
+        match jwk.get("kty").and_then(Value::as_str) {
+            Some("oct") => Ok(JwtKey::Hmac(field("k")?)),
+            Some("RSA") => {
+                let n = BigUint::from_bytes_be(&field("n")?);
+                let e = BigUint::from_bytes_be(&field("e")?);
+                RsaPublicKey::new(n, e).map(JwtKey::Rsa).map_err(|e| e.to_string())
+            },
+            Some("EC") => {
+                if jwk.get("crv").and_then(Value::as_str) != Some("P-256") {
+                    return Err(String::from("only the P-256 curve is supported"));
+                }
+                let (x, y) = (field("x")?, field("y")?);
+                if x.len() != 32 || y.len() != 32 {
+                    return Err(String::from("invalid P-256 coordinates"));
+                }
+                let point = p256::EncodedPoint::from_affine_coordinates(x.as_slice().into(), y.as_slice().into(), false);
+                p256::ecdsa::VerifyingKey::from_encoded_point(&point).map(JwtKey::Ec).map_err(|e| e.to_string())
+            },
+            Some(kty) => Err(format!("unsupported key type {}", kty)),
+            None => Err(String::from("missing \"kty\"")),
+        }
+    }
+
+    fn verify(&self, message: &[u8], signature: &[u8]) -> bool {
+        match self {
+            JwtKey::Hmac(secret) => crypto::hmac_sha256_verify(secret, message, signature),
+            JwtKey::Rsa(key) => {
+                let key = rsa::pkcs1v15::VerifyingKey::<Sha256>::new(key.clone());
+                rsa::pkcs1v15::Signature::try_from(signature).is_ok_and(|signature| key.verify(message, &signature).is_ok())
+            },
+            JwtKey::Ec(key) => {
+                // JWS uses the raw r || s encoding of the signature
+                p256::ecdsa::Signature::from_slice(signature).is_ok_and(|signature| key.verify(message, &signature).is_ok())
+            },
+        }
+    }
+}
+
+impl Debug for JwtKey {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // Never print the HMAC secret
+        write!(f, "JwtKey({:?})", self.algorithm())
+    }
+}
+
+/// KeySet struct, the keys used to verify the tokens, each key can have an ID (`kid`).
+///
+/// When a token header has a `kid`, only the key with this ID is used, otherwise every key of the token algorithm is tried.
+#[derive(Debug, Clone, Default)]
+pub struct KeySet {
+    keys: Vec<(Option<String>, JwtKey)>,
+}
+
+impl KeySet {
+
+    /// Create a new empty KeySet.
+    pub fn new() -> Self {
+        Self {
+            keys: Vec::new(),
+        }
+    }
+
+    /// Add a key with an optional ID.
+    pub fn with_key(mut self, kid: Option<String>, key: JwtKey) -> Self {
+        self.keys.push((kid, key));
+        self
+    }
+
+    /// Parse a JWK Set document (`{"keys": [...]}`).
+    ///
+    /// Return Err if the document isn't valid JSON or if a key can't be parsed.
+    pub fn from_jwks(jwks: &str) -> Result<Self, String> {
+        let document: Value = serde_json::from_str(jwks).map_err(|e| format!("Invalid JWKS: {}", e))?;
+        let keys = document.get("keys").and_then(Value::as_array).ok_or_else(|| String::from("Invalid JWKS: missing \"keys\" array"))?;
+        let mut set = Self::new();
+        for (index, jwk) in keys.iter().enumerate() {
+            let jwk = jwk.as_object().ok_or_else(|| format!("Invalid JWKS: key {} isn't an object", index))?;
+            let key = JwtKey::from_jwk(jwk).map_err(|e| format!("Invalid JWKS: key {}: {}", index, e))?;
+            set.keys.push((jwk.get("kid").and_then(Value::as_str).map(String::from), key));
+        }
+        Ok(set)
+    }
+
+    /// Return the keys which can verify a token with the given algorithm and key ID.
+    fn candidates<'a>(&'a self, algorithm: Algorithm, kid: Option<&'a str>) -> impl Iterator<Item = &'a JwtKey> {
+        self.keys.iter()
+            .filter(move |(key_id, key)| key.algorithm() == algorithm && (kid.is_none() || key_id.as_deref() == kid))
+            .map(|(_, key)| key)
+    }
+}
+
+/// Source of the keys of a JwtVerifier.
+enum KeySource {
+    Static(KeySet),
+    File(JwksFile),
+}
+
+/// JWK Set loaded from a local file, the file is reloaded when it's modified.
+struct JwksFile {
+    path: PathBuf,
+    check_interval: Duration,
+    keys: RwLock<KeySet>,
+    state: Mutex<(Instant, Option<SystemTime>)>,
+}
+
+impl JwksFile {
+
+    fn load(path: PathBuf, check_interval: Duration) -> Result<Self, String> {
+        let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+        let content = fs::read_to_string(&path).map_err(|e| format!("Cannot read {}: {}", path.display(), e))?;
+        Ok(Self {
+            keys: RwLock::new(KeySet::from_jwks(&content)?),
+            path,
+            check_interval,
+            state: Mutex::new((Instant::now(), modified)),
+        })
+    }
+
+    /// Reload the file, the previous keys are kept if the new file is invalid.
+    fn reload(&self) -> Result<(), String> {
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        let content = fs::read_to_string(&self.path).map_err(|e| format!("Cannot read {}: {}", self.path.display(), e))?;
+        let keys = KeySet::from_jwks(&content)?;
+        *self.keys.write().unwrap() = keys;
+        *self.state.lock().unwrap() = (Instant::now(), modified);
+        Ok(())
+    }
+
+    /// Reload the file if it has been modified, its modification time is checked at most once per check interval.
+    ///
+    /// Return Err if the modified file can't be loaded, the previous keys are kept and the file is checked again after the interval.
+    fn refresh(&self) -> Result<(), String> {
+        let mut state = self.state.lock().unwrap();
+        if state.0.elapsed() < self.check_interval {
+            return Ok(());
+        }
+        state.0 = Instant::now();
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        if modified == state.1 {
+            return Ok(());
+        }
+        drop(state);
+        self.reload()
+    }
+}
+
+/// Claims struct, the decoded payload of a valid JWT, added to the request extensions by the JwtAuth middleware.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Claims {
+    claims: Map<String, Value>,
+}
+
+impl Claims {
+
+    /// Return the value of a claim, if the claim is not found, return None.
+    pub fn get(&self, name: &str) -> Option<&Value> {
+        self.claims.get(name)
+    }
+
+    /// Return the value of a string claim, if the claim is not found or isn't a string, return None.
+    pub fn get_str(&self, name: &str) -> Option<&str> {
+        self.claims.get(name).and_then(Value::as_str)
+    }
+
+    /// Return the subject (`sub` claim) of the token, usually the ID of the user.
+    pub fn subject(&self) -> Option<&str> {
+        self.get_str("sub")
+    }
+
+    /// Return the issuer (`iss` claim) of the token.
+    pub fn issuer(&self) -> Option<&str> {
+        self.get_str("iss")
+    }
+
+    /// Return the expiration time (`exp` claim) of the token in seconds since UNIX epoch, rounded down if it's fractional.
+    pub fn expiration(&self) -> Option<u64> {
+        self.claims.get("exp").and_then(numeric_date).map(|exp| exp as u64)
+    }
+
+    /// Return all the claims.
+    pub fn as_map(&self) -> &Map<String, Value> {
+        &self.claims
+    }
+
+    /// Deserialize the claims to T.
+    pub fn deserialize<T: serde::de::DeserializeOwned>(&self) -> Result<T, serde_json::Error> {
+        serde_json::from_value(Value::Object(self.claims.clone()))
+    }
+}
+
+/// JwtVerifier struct, verify the signature and the registered claims of a JWT (see [RFC 7519](https://tools.ietf.org/html/rfc7519)).
+///
+/// The `exp` claim is required by default, `nbf` is checked when present, `iss` and `aud` are checked when an expected value is set.
+/// A clock skew of 60 seconds is tolerated by default.
+///
+/// No header extension is supported, so the tokens with a `crit` header are rejected.
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::jwt::{JwtAuth, JwtVerifier};
+/// use rest_server::Server;
+/// use std::path::PathBuf;
+/// use std::time::Duration;
+///
+/// let verifier = JwtVerifier::from_jwks_file(PathBuf::from("/etc/my-api/jwks.json"), Duration::from_secs(30))
+///     .unwrap()
+///     .issuer(String::from("https://id.example.com"))
+///     .audience(String::from("my-api"));
+/// let mut app = Server::new();
+/// app.middleware_at(String::from("/api"), Box::new(JwtAuth::new(String::from("my-api"), verifier)));
+/// ```
+pub struct JwtVerifier {
+    keys: KeySource,
+    issuer: Option<String>,
+    audiences: Vec<String>,
+    leeway: Duration,
+    require_exp: bool,
+}
+
+impl JwtVerifier {
+
+    /// Create a new JwtVerifier using the given keys.
+    pub fn new(keys: KeySet) -> Self {
+        Self::with_source(KeySource::Static(keys))
+    }
+
+    /// Create a new JwtVerifier using the keys of a local JWK Set file.
+    ///
+    /// The modification time of the file is checked at most once per check interval and the file is reloaded when it changes,
+    /// if the new file is invalid, the previous keys are kept.
+    /// Return Err if the file can't be read or parsed.
+    pub fn from_jwks_file(path: PathBuf, check_interval: Duration) -> Result<Self, String> {
+        Ok(Self::with_source(KeySource::File(JwksFile::load(path, check_interval)?)))
+    }
+
+    fn with_source(keys: KeySource) -> Self {
+        Self {
+            keys,
+            issuer: None,
+            audiences: Vec::new(),
+            leeway: Duration::from_secs(60),
+            require_exp: true,
+        }
+    }
+
+    /// Set the expected issuer, tokens with another `iss` claim are rejected.
+    pub fn issuer(mut self, issuer: String) -> Self {
+        self.issuer = Some(issuer);
+        self
+    }
+
+    /// Add an accepted audience, if at least one is set, the `aud` claim of the token must contain one of them.
+    pub fn audience(mut self, audience: String) -> Self {
+        self.audiences.push(audience);
+        self
+    }
+
+    /// Set the clock skew tolerated when checking `exp` and `nbf`.
+    pub fn leeway(mut self, leeway: Duration) -> Self {
+        self.leeway = leeway;
+        self
+    }
+
+    /// Set whether the `exp` claim is required, true by default.
+    pub fn require_exp(mut self, require_exp: bool) -> Self {
+        self.require_exp = require_exp;
+        self
+    }
+
+    /// Reload the JWK Set file now, do nothing if the verifier wasn't created with from_jwks_file().
+    pub fn reload(&self) -> Result<(), String> {
+        match &self.keys {
+            KeySource::Static(_) => Ok(()),
+            KeySource::File(file) => file.reload(),
+        }
+    }
+
+    /// Verify the token and return its claims.
+    ///
+    /// With a JWK Set file, Err(JwtError::KeySet) is returned when the modified file can't be loaded,
+    /// the next tokens are verified with the previous keys until the file is checked again.
+    pub fn verify(&self, token: &str) -> Result<Claims, JwtError> {
+        let mut parts = token.split('.');
+        let (header, payload, signature) = match (parts.next(), parts.next(), parts.next(), parts.next()) {
+            (Some(header), Some(payload), Some(signature), None) => (header, payload, signature),
+            _ => return Err(JwtError::Malformed),
+        };
+        let header = decode_json(header)?;
+        let algorithm_name = header.get("alg").and_then(Value::as_str).ok_or(JwtError::Malformed)?;
+        let algorithm = Algorithm::from_name(algorithm_name).ok_or_else(|| JwtError::UnsupportedAlgorithm(algorithm_name.to_string()))?;
+        // the header extensions listed in crit must be understood, and none is supported (RFC 7515 section 4.1.11)
+        match header.get("crit") {
+            Some(Value::Array(crit)) if !crit.is_empty() && crit.iter().all(|name| name.as_str().is_some_and(|name| !name.is_empty())) => {
+                let names = crit.iter().filter_map(Value::as_str).collect::<Vec<&str>>().join(", ");
+                return Err(JwtError::UnsupportedCritical(names));
+            },
+            Some(_) => return Err(JwtError::Malformed),
+            None => {},
+        }
+        let kid = header.get("kid").and_then(Value::as_str);
+        let signature = crypto::base64_decode(signature).ok_or(JwtError::Malformed)?;
+        let message = &token.as_bytes()[..header_payload_len(token)];
+
+        let verified = match &self.keys {
+            KeySource::Static(keys) => Self::verify_signature(keys, algorithm, kid, message, &signature)?,
+            KeySource::File(file) => {
+                file.refresh().map_err(JwtError::KeySet)?;
+                Self::verify_signature(&file.keys.read().unwrap(), algorithm, kid, message, &signature)?
+            },
+        };
+        if !verified {
+            return Err(JwtError::InvalidSignature);
+        }
+        let claims = decode_json(payload)?;
+        self.validate(&claims)?;
+        Ok(Claims {
+            claims,
+        })
+    }
+
+    fn verify_signature(keys: &KeySet, algorithm: Algorithm, kid: Option<&str>, message: &[u8], signature: &[u8]) -> Result<bool, JwtError> {
+        let mut candidates = keys.candidates(algorithm, kid).peekable();
+        if candidates.peek().is_none() {
+            return Err(JwtError::UnknownKey);
+        }
+        Ok(candidates.any(|key| key.verify(message, signature)))
+    }
+
+    /// Check the registered claims.
+    fn validate(&self, claims: &Map<String, Value>) -> Result<(), JwtError> {
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs_f64()).unwrap_or(0.0);
+        let leeway = self.leeway.as_secs_f64();
+        match claims.get("exp") {
+            Some(exp) => {
+                let exp = numeric_date(exp).ok_or_else(|| JwtError::InvalidClaim(String::from("exp")))?;
+                if now > exp + leeway {
+                    return Err(JwtError::Expired);
+                }
+            },
+            None if self.require_exp => return Err(JwtError::MissingClaim(String::from("exp"))),
+            None => {},
+        }
+        if let Some(nbf) = claims.get("nbf") {
+            let nbf = numeric_date(nbf).ok_or_else(|| JwtError::InvalidClaim(String::from("nbf")))?;
+            if now + leeway < nbf {
+                return Err(JwtError::NotYetValid);
+            }
+        }
+        if let Some(issuer) = &self.issuer {
+            match claims.get("iss").and_then(Value::as_str) {
+                Some(iss) if iss == issuer => {},
+                Some(_) => return Err(JwtError::InvalidClaim(String::from("iss"))),
+                None => return Err(JwtError::MissingClaim(String::from("iss"))),
+            }
+        }
+        if !self.audiences.is_empty() {
+            let audiences = match claims.get("aud") {
+                Some(Value::String(aud)) => vec![aud.as_str()],
+                Some(Value::Array(auds)) => auds.iter().filter_map(Value::as_str).collect(),
+                Some(_) => return Err(JwtError::InvalidClaim(String::from("aud"))),
+                None => return Err(JwtError::MissingClaim(String::from("aud"))),
+            };
+            if !audiences.iter().any(|aud| self.audiences.iter().any(|expected| expected == aud)) {
+                return Err(JwtError::InvalidClaim(String::from("aud")));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Return the length of the signed part of the token (header.payload).
+fn header_payload_len(token: &str) -> usize {
+    token.rfind('.').unwrap_or(0)
+}
+
+/// Return the seconds since UNIX epoch of a NumericDate, which can be fractional (RFC 7519 section 2).
+///
+/// Return None if the value isn't a positive number.
+fn numeric_date(value: &Value) -> Option<f64> {
+    value.as_f64().filter(|date| *date >= 0.0)
+}
+
+/// Decode a base64url encoded JSON object.
+fn decode_json(part: &str) -> Result<Map<String, Value>, JwtError> {
+    let bytes = crypto::base64_decode(part).ok_or(JwtError::Malformed)?;
+    match serde_json::from_slice(&bytes) {
+        Ok(Value::Object(map)) => Ok(map),
+        _ => Err(JwtError::Malformed),
+    }
+}
+
+/// Error returned when a JWT isn't valid.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JwtError {
+    /// The token isn't made of three base64url parts or its header or payload isn't a JSON object.
+    Malformed,
+    /// The `alg` of the token isn't supported.
+    UnsupportedAlgorithm(String),
+    /// The `crit` header lists extensions which aren't supported.
+    UnsupportedCritical(String),
+    /// No key matches the algorithm and the `kid` of the token.
+    UnknownKey,
+    /// The signature doesn't match.
+    InvalidSignature,
+    /// The token has expired (`exp`).
+    Expired,
+    /// The token can't be used yet (`nbf`).
+    NotYetValid,
+    /// A required claim is missing.
+    MissingClaim(String),
+    /// A claim has an invalid value or type.
+    InvalidClaim(String),
+    /// The JWK Set file has been modified but can't be loaded, this is an error of the server, not of the token.
+    KeySet(String),
+}
+
+impl Display for JwtError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            JwtError::Malformed => write!(f, "Malformed token"),
+            JwtError::UnsupportedAlgorithm(algorithm) => write!(f, "Unsupported algorithm: {}", algorithm),
+            JwtError::UnsupportedCritical(names) => write!(f, "Unsupported critical header: {}", names),
+            JwtError::UnknownKey => write!(f, "No key found to verify the token"),
+            JwtError::InvalidSignature => write!(f, "Invalid signature"),
+            JwtError::Expired => write!(f, "The token has expired"),
+            JwtError::NotYetValid => write!(f, "The token isn't valid yet"),
+            JwtError::MissingClaim(claim) => write!(f, "Missing claim: {}", claim),
+            JwtError::InvalidClaim(claim) => write!(f, "Invalid claim: {}", claim),
+            JwtError::KeySet(message) => write!(f, "Cannot load the keys: {}", message),
+        }
+    }
+}
+
+impl std::error::Error for JwtError {}
+
+/// Middleware authenticating the clients with a JWT sent as bearer token.
+///
+/// The Claims of a valid token are added to the request extensions,
+/// otherwise the request is rejected with 401 Unauthorized and a Bearer challenge.
+pub struct JwtAuth {
+    realm: String,
+    verifier: JwtVerifier,
+}
+
+impl JwtAuth {
+
+    /// Create a new JwtAuth middleware using the given verifier.
+    pub fn new(realm: String, verifier: JwtVerifier) -> Self {
+        Self {
+            realm,
+            verifier,
+        }
+    }
+
+    /// Return the verifier, to reload its keys for example.
+    pub fn verifier(&self) -> &JwtVerifier {
+        &self.verifier
+    }
+}
+
+impl Middleware for JwtAuth {
+    fn handle(&self, mut request: Request, mut response: Response, next: Next) {
+        match auth::bearer_token(&request) {
+            Ok(Some(token)) => match self.verifier.verify(token) {
+                Ok(claims) => {
+                    request.extensions_mut().insert(claims);
+                    next.run(request, response);
+                },
+                Err(JwtError::KeySet(e)) => {
+                    // the client isn't at fault, and the details of the server configuration aren't sent to it
+                    eprintln!("Cannot verify the token: {}", e);
+                    response.set_status(Status::InternalServerError);
+                    response.send();
+                },
+                Err(e) => auth::bearer_challenge(response, &self.realm, Some(("invalid_token", &e.to_string()))),
+            },
+            Ok(None) => auth::bearer_challenge(response, &self.realm, None),
+            Err(()) => auth::bearer_challenge(response, &self.realm, Some(("invalid_request", "Malformed Authorization header"))),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::testing;
+    use p256::ecdsa::signature::Signer;
+    use std::sync::Arc;
+
+    const SECRET: &[u8] = b"a secret of at least 32 bytes for HS256";
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    fn encode(value: &Value) -> String {
+        crypto::base64_encode(value.to_string().as_bytes())
+    }
+
+    /// Return header.payload.signature, the signature is made by sign over header.payload.
+    fn token(header: Value, claims: Value, sign: impl Fn(&[u8]) -> Vec<u8>) -> String {
+        let message = format!("{}.{}", encode(&header), encode(&claims));
+        let signature = sign(message.as_bytes());
+        format!("{}.{}", message, crypto::base64_encode(&signature))
+    }
+
+    fn hs256(claims: Value) -> String {
+        token(serde_json::json!({"alg": "HS256", "typ": "JWT"}), claims, |message| crypto::hmac_sha256(SECRET, message))
+    }
+
+    fn hs256_verifier() -> JwtVerifier {
+        JwtVerifier::new(KeySet::new().with_key(None, JwtKey::Hmac(SECRET.to_vec())))
+    }
+
+    /// Test key of the rsa crate, too small for production but fast.
+    fn rsa_key() -> rsa::RsaPrivateKey {
+        let number = |value: &str| value.parse::<BigUint>().unwrap();
+        rsa::RsaPrivateKey::from_components(
+            number("9353930466774385905609975137998169297361893554149986716853295022578535724979677252958524466350471210367835187480748268864277464700638583474144061408845077"),
+            number("65537"),
+            number("7266398431328116344057699379749222532279343923819063639497049039389899328538543087657733766554155839834519529439851673014800261285757759040931985506583861"),
+            vec![
+                number("98920366548084643601728869055592650835572950932266967461790948584315647051443"),
+                number("94560208308847015747498523884063394671606671904944666360068158221458669711639"),
+            ],
+        ).unwrap()
+    }
+
+    fn rs256(claims: Value) -> String {
+        let key = rsa::pkcs1v15::SigningKey::<Sha256>::new(rsa_key());
+        token(serde_json::json!({"alg": "RS256", "kid": "rsa-1"}), claims, |message| {
+            use rsa::signature::SignatureEncoding;
+            rsa::signature::Signer::sign(&key, message).to_vec()
+        })
+    }
+
+    fn ec_key() -> p256::ecdsa::SigningKey {
+        p256::ecdsa::SigningKey::from_slice(&[7; 32]).unwrap()
+    }
+
+    fn es256(claims: Value) -> String {
+        let key = ec_key();
+        token(serde_json::json!({"alg": "ES256"}), claims, |message| {
+            let signature: p256::ecdsa::Signature = key.sign(message);
+            signature.to_bytes().to_vec()
+        })
+    }
+
+    fn all_keys() -> KeySet {
+        KeySet::new()
+            .with_key(None, JwtKey::Hmac(SECRET.to_vec()))
+            .with_key(Some(String::from("rsa-1")), JwtKey::Rsa(rsa_key().to_public_key()))
+            .with_key(None, JwtKey::Ec(*ec_key().verifying_key()))
+    }
+
+    fn valid_claims() -> Value {
+        serde_json::json!({"sub": "alice", "exp": now() + 300})
+    }
+
+    #[test]
+    fn accept_valid_tokens() {
+        let verifier = JwtVerifier::new(all_keys());
+        for token in [hs256(valid_claims()), rs256(valid_claims()), es256(valid_claims())] {
+            let claims = verifier.verify(&token).unwrap();
+            assert_eq!(claims.subject(), Some("alice"));
+            assert_eq!(claims.expiration(), Some(now() + 300).filter(|exp| claims.expiration().unwrap().abs_diff(*exp) <= 1));
+        }
+    }
+
+    #[test]
+    fn reject_tampered_signature() {
+        let verifier = JwtVerifier::new(all_keys());
+        for token in [hs256(valid_claims()), rs256(valid_claims()), es256(valid_claims())] {
+            let (message, signature) = token.rsplit_once('.').unwrap();
+            let mut signature = crypto::base64_decode(signature).unwrap();
+            signature[0] ^= 1;
+            let tampered = format!("{}.{}", message, crypto::base64_encode(&signature));
+            assert_eq!(verifier.verify(&tampered), Err(JwtError::InvalidSignature));
+        }
+    }
+
+    #[test]
+    fn reject_tampered_payload() {
+        let token = hs256(valid_claims());
+        let parts = token.split('.').collect::<Vec<&str>>();
+        let forged = format!("{}.{}.{}", parts[0], encode(&serde_json::json!({"sub": "admin", "exp": now() + 300})), parts[2]);
+        assert_eq!(hs256_verifier().verify(&forged), Err(JwtError::InvalidSignature));
+    }
+
+    #[test]
+    fn reject_alg_none() {
+        let token = format!("{}.{}.", encode(&serde_json::json!({"alg": "none"})), encode(&valid_claims()));
+        assert_eq!(JwtVerifier::new(all_keys()).verify(&token), Err(JwtError::UnsupportedAlgorithm(String::from("none"))));
+    }
+
+    #[test]
+    fn reject_hs256_token_signed_with_rsa_public_key() {
+        // the classic confusion attack: the public key used as HMAC secret
+        let public_key = rsa_key().to_public_key();
+        let secret = rsa::pkcs1::EncodeRsaPublicKey::to_pkcs1_der(&public_key).unwrap().as_bytes().to_vec();
+        let token = token(serde_json::json!({"alg": "HS256"}), valid_claims(), |message| crypto::hmac_sha256(&secret, message));
+        let verifier = JwtVerifier::new(KeySet::new().with_key(None, JwtKey::Rsa(public_key)));
+        assert_eq!(verifier.verify(&token), Err(JwtError::UnknownKey));
+    }
+
+    #[test]
+    fn select_key_by_kid() {
+        let verifier = JwtVerifier::new(KeySet::new().with_key(Some(String::from("rsa-2")), JwtKey::Rsa(rsa_key().to_public_key())));
+        assert_eq!(verifier.verify(&rs256(valid_claims())), Err(JwtError::UnknownKey));
+    }
+
+    #[test]
+    fn reject_expired_and_not_yet_valid_tokens() {
+        let verifier = hs256_verifier().leeway(Duration::from_secs(10));
+        let now = now();
+        assert_eq!(verifier.verify(&hs256(serde_json::json!({"exp": now - 20}))), Err(JwtError::Expired));
+        assert!(verifier.verify(&hs256(serde_json::json!({"exp": now - 5}))).is_ok());
+        assert_eq!(verifier.verify(&hs256(serde_json::json!({"exp": now + 60, "nbf": now + 20}))), Err(JwtError::NotYetValid));
+        assert!(verifier.verify(&hs256(serde_json::json!({"exp": now + 60, "nbf": now + 5}))).is_ok());
+        assert_eq!(verifier.verify(&hs256(serde_json::json!({"sub": "alice"}))), Err(JwtError::MissingClaim(String::from("exp"))));
+        assert!(hs256_verifier().require_exp(false).verify(&hs256(serde_json::json!({"sub": "alice"}))).is_ok());
+        assert_eq!(verifier.verify(&hs256(serde_json::json!({"exp": "tomorrow"}))), Err(JwtError::InvalidClaim(String::from("exp"))));
+        assert_eq!(verifier.verify(&hs256(serde_json::json!({"exp": -1}))), Err(JwtError::InvalidClaim(String::from("exp"))));
+    }
+
+    #[test]
+    fn accept_fractional_dates() {
+        let verifier = hs256_verifier().leeway(Duration::ZERO);
+        let now = now() as f64;
+        let claims = verifier.verify(&hs256(serde_json::json!({"exp": now + 60.5, "nbf": now - 0.25}))).unwrap();
+        assert_eq!(claims.expiration(), Some((now + 60.5) as u64));
+        assert_eq!(verifier.verify(&hs256(serde_json::json!({"exp": now - 1.5}))), Err(JwtError::Expired));
+        assert_eq!(verifier.verify(&hs256(serde_json::json!({"exp": now + 60.0, "nbf": now + 30.5}))), Err(JwtError::NotYetValid));
+    }
+
+    #[test]
+    fn check_issuer_and_audience() {
+        let verifier = hs256_verifier().issuer(String::from("https://id.example.com")).audience(String::from("api"));
+        let exp = now() + 60;
+        assert!(verifier.verify(&hs256(serde_json::json!({"exp": exp, "iss": "https://id.example.com", "aud": "api"}))).is_ok());
+        assert!(verifier.verify(&hs256(serde_json::json!({"exp": exp, "iss": "https://id.example.com", "aud": ["web", "api"]}))).is_ok());
+        assert_eq!(
+            verifier.verify(&hs256(serde_json::json!({"exp": exp, "iss": "https://evil.example.com", "aud": "api"}))),
+            Err(JwtError::InvalidClaim(String::from("iss")))
+        );
+        assert_eq!(
+            verifier.verify(&hs256(serde_json::json!({"exp": exp, "iss": "https://id.example.com", "aud": "web"}))),
+            Err(JwtError::InvalidClaim(String::from("aud")))
+        );
+        assert_eq!(
+            verifier.verify(&hs256(serde_json::json!({"exp": exp, "iss": "https://id.example.com"}))),
+            Err(JwtError::MissingClaim(String::from("aud")))
+        );
+    }
+
+    #[test]
+    fn reject_critical_headers() {
+        let verifier = hs256_verifier();
+        let sign = |message: &[u8]| crypto::hmac_sha256(SECRET, message);
+        let critical = token(serde_json::json!({"alg": "HS256", "crit": ["exp"], "exp": 1}), valid_claims(), sign);
+        assert_eq!(verifier.verify(&critical), Err(JwtError::UnsupportedCritical(String::from("exp"))));
+        for crit in [serde_json::json!([]), serde_json::json!("exp"), serde_json::json!([""])] {
+            let invalid = token(serde_json::json!({"alg": "HS256", "crit": crit}), valid_claims(), sign);
+            assert_eq!(verifier.verify(&invalid), Err(JwtError::Malformed));
+        }
+    }
+
+    #[test]
+    fn reject_malformed_tokens() {
+        let verifier = hs256_verifier();
+        for token in ["", "a.b", "a.b.c.d", "!!.!!.!!"] {
+            assert_eq!(verifier.verify(token), Err(JwtError::Malformed));
+        }
+        let token = format!("{}.{}.", crypto::base64_encode(b"[1]"), encode(&valid_claims()));
+        assert_eq!(verifier.verify(&token), Err(JwtError::Malformed));
+    }
+
+    #[test]
+    fn parse_jwks() {
+        let point = ec_key().verifying_key().to_encoded_point(false);
+        let public_key = rsa_key().to_public_key();
+        let jwks = serde_json::json!({"keys": [
+            {"kty": "oct", "k": crypto::base64_encode(SECRET)},
+            {"kty": "RSA", "kid": "rsa-1", "n": crypto::base64_encode(&rsa::traits::PublicKeyParts::n(&public_key).to_bytes_be()), "e": "AQAB"},
+            {"kty": "EC", "crv": "P-256", "x": crypto::base64_encode(point.x().unwrap()), "y": crypto::base64_encode(point.y().unwrap())},
+        ]});
+        let verifier = JwtVerifier::new(KeySet::from_jwks(&jwks.to_string()).unwrap());
+        for token in [hs256(valid_claims()), rs256(valid_claims()), es256(valid_claims())] {
+            assert!(verifier.verify(&token).is_ok());
+        }
+        assert!(KeySet::from_jwks(r#"{"keys": [{"kty": "EC", "crv": "P-384", "x": "", "y": ""}]}"#).is_err());
+        assert!(KeySet::from_jwks(r#"{"keys": [{"kty": "OKP"}]}"#).is_err());
+        assert!(KeySet::from_jwks("{}").is_err());
+    }
+
+    #[test]
+    fn reload_jwks_file() {
+        let path = std::env::temp_dir().join(format!("rest_server-test-jwks-{}.json", std::process::id()));
+        let jwks = |secret: &[u8]| serde_json::json!({"keys": [{"kty": "oct", "k": crypto::base64_encode(secret)}]}).to_string();
+        fs::write(&path, jwks(b"old secret")).unwrap();
+        let verifier = JwtVerifier::from_jwks_file(path.clone(), Duration::from_secs(3600)).unwrap();
+        let old = token(serde_json::json!({"alg": "HS256"}), valid_claims(), |message| crypto::hmac_sha256(b"old secret", message));
+        assert!(verifier.verify(&old).is_ok());
+
+        fs::write(&path, jwks(SECRET)).unwrap();
+        verifier.reload().unwrap();
+        assert_eq!(verifier.verify(&old), Err(JwtError::InvalidSignature));
+        assert!(verifier.verify(&hs256(valid_claims())).is_ok());
+
+        // an invalid file is reported and the previous keys are kept
+        fs::write(&path, "{").unwrap();
+        assert!(verifier.reload().is_err());
+        assert!(verifier.verify(&hs256(valid_claims())).is_ok());
+        fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn middleware_adds_claims_or_rejects() {
+        let middleware: Arc<dyn Middleware> = Arc::new(JwtAuth::new(String::from("api"), hs256_verifier()));
+        let authorization = format!("Bearer {}", hs256(valid_claims()));
+        let response = testing::run(Arc::clone(&middleware), testing::request(Method::GET, "/", &[("Authorization", &authorization)]));
+        assert!(response.ends_with("handled"));
+
+        let response = testing::run(Arc::clone(&middleware), testing::request(Method::GET, "/", &[("Authorization", "Bearer a.b.c")]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 401 Unauthorized");
+        assert!(testing::header(&response, "WWW-Authenticate").unwrap().contains("error=\"invalid_token\""));
+
+        let response = testing::run(middleware, testing::request(Method::GET, "/", &[]));
+        assert_eq!(testing::header(&response, "WWW-Authenticate"), Some("Bearer realm=\"api\""));
+    }
+}
@@ -6,13 +6,16 @@ Create a server with a given port and a given routes.
   
 # Example
 
- ```rust
-fn main() {
-    let mut app = Server::new();
-    app.set_number_of_worker(8);
-    app.get(String::from("/"), Box::new(index));
-    app.listen(7878);
-}
+ ```no_run
+use rest_server::Server;
+use rest_server::request::Request;
+use rest_server::response::Response;
+use rest_server::status::Status;
+
+let mut app = Server::new();
+app.set_number_of_worker(8);
+app.get(String::from("/"), Box::new(index));
+app.listen(7878).unwrap();
 
 fn index(request: Request, mut response: Response) {
     let content = "Hello";
@@ -30,20 +33,69 @@ pub mod response;
 pub mod request;
 pub mod method;
 pub mod status;
+pub mod form;
+pub mod multipart;
+pub mod cookie;
+pub mod connection;
+pub mod handle;
+pub mod shutdown;
+#[cfg(unix)]
+pub mod unix;
+#[cfg(unix)]
+pub mod systemd;
+pub mod middleware;
+pub mod extensions;
+pub mod session;
+pub mod auth;
+pub mod cors;
+pub mod csrf;
+pub mod security;
+pub mod ratelimit;
+pub mod ipfilter;
+pub mod config;
+pub mod routes;
+mod crypto;
+mod header;
+#[cfg(test)]
+mod testing;
+#[cfg(feature = "json")]
+pub mod json;
+#[cfg(feature = "jwt")]
+pub mod jwt;
 use threadpool::ThreadPool;
 use method::Method;
 use request::{Request, RequestPath};
 use response::Response;
+use connection::{ConnectionInfo, ListenAddr, Listener, Stream};
+use handle::ServerHandle;
+use shutdown::{Shutdown, ShutdownToken};
+use config::{LogFormat, ServerConfig};
+use cookie::Keyring;
+use routes::{RouteHandle, Routes};
+use middleware::{Middleware, Next};
 use status::Status;
 use std::collections::HashMap;
-use std::net::{TcpListener, TcpStream};
+use socket2::{Domain, Protocol, Socket, Type};
+use std::net::{SocketAddr, TcpListener};
+use std::io;
 use std::io::prelude::*;
 use std::str;
-use std::sync::{Arc, RwLock};
-use std::sync::atomic::AtomicBool;
-use ctrlc;
+use std::sync::{mpsc, Arc, RwLock};
+use std::thread;
+use std::time::{Duration, Instant};
+#[cfg(unix)]
+use signal_hook::consts::{SIGHUP, SIGINT, SIGTERM};
+#[cfg(unix)]
+use signal_hook::iterator::Signals;
 
-type IFn = dyn Fn(Request, Response) + Send + 'static + Sync;
+/// Maximum size of the request line and headers, a 431 error is sent if the client exceed it.
+const MAX_HEAD_SIZE: usize = 16 * 1024;
+const HEAD_TOO_LARGE: &str = "Request headers too large";
+const HEAD_TIMEOUT: &str = "Timeout while reading the request headers";
+
+pub(crate) type IFn = dyn Fn(Request, Response) + Send + 'static + Sync;
+type HookFn = dyn Fn() + Send + Sync;
+type StartFn = dyn Fn(&[ListenAddr]) -> Result<(), String> + Send + Sync;
 
 /// Default route if no route is found or if client call /404.html
 fn not_found(_req: Request, mut res: Response) {
@@ -53,13 +105,37 @@ fn not_found(_req: Request, mut res: Response) {
     res.send();
 }
 
+/// Settings used by the workers to read the requests, copied from the server for each connection.
+#[derive(Clone)]
+struct ConnectionSettings {
+    read_timeout: Option<Duration>,
+    max_body_size: u64,
+    cookie_keyring: Option<Arc<Keyring>>,
+}
+
 /// Main struct, start the server and listen on the port given in argument.
 /// 
 /// number_of_workers is the number of threads used to handle the requests.
 /// My advice is to set number_of_workers to the number of logical cores of your CPU.
 pub struct Server {
     number_of_workers: usize,
-    routing: Arc<RwLock<HashMap<(Method, RequestPath), Box<IFn>>>>,
+    max_body_size: u64,
+    cookie_keyring: Option<Arc<Keyring>>,
+    middlewares: Vec<(RequestPath, Arc<dyn Middleware>)>,
+    routing: Arc<RwLock<Routes>>,
+    ipv6_only: bool,
+    shutdown_timeout: Duration,
+    read_timeout: Option<Duration>,
+    log_format: LogFormat,
+    shutdown: Arc<Shutdown>,
+    signal_handling: bool,
+    reload_hooks: Vec<Box<HookFn>>,
+    start_hooks: Vec<Box<StartFn>>,
+    ready_hooks: Vec<Box<StartFn>>,
+    shutdown_hooks: Vec<Box<HookFn>>,
+    stopped_hooks: Vec<Box<HookFn>>,
+    #[cfg(unix)]
+    notifier: Option<systemd::Notifier>,
 }
 
 impl Server {
@@ -67,11 +143,27 @@ impl Server {
     /// Create a new Server.
     /// Socket isn't opened yet, you have to call listen() to open it.
     pub fn new() -> Self {
-        let arc: Arc<RwLock<HashMap<(Method, RequestPath), Box<IFn>>>> = Arc::new(RwLock::new(HashMap::new()));
-        arc.write().unwrap().insert((Method::GET, RequestPath::new(String::from("/404.html"))), Box::new(not_found));
+        let arc: Arc<RwLock<Routes>> = Arc::new(RwLock::new(HashMap::new()));
+        RouteHandle::new(Arc::clone(&arc)).add(Method::GET, String::from("/404.html"), Box::new(not_found));
         Self {
             number_of_workers: 4,
+            max_body_size: 8 * 1024 * 1024,
+            cookie_keyring: None,
+            middlewares: Vec::new(),
             routing: arc,
+            ipv6_only: false,
+            shutdown_timeout: Duration::from_secs(30),
+            read_timeout: Some(Duration::from_secs(30)),
+            log_format: LogFormat::Text,
+            shutdown: Arc::new(Shutdown::new()),
+            signal_handling: false,
+            reload_hooks: Vec::new(),
+            start_hooks: Vec::new(),
+            ready_hooks: Vec::new(),
+            shutdown_hooks: Vec::new(),
+            stopped_hooks: Vec::new(),
+            #[cfg(unix)]
+            notifier: None,
         }
     }
 
@@ -122,7 +214,28 @@ impl Server {
 
     /// add a new route to the server with the given method, the given path and the given function
     pub fn route(&mut self, method: Method, path: String, f: Box<IFn>) {
-        self.routing.write().unwrap().insert((method, RequestPath::new_route(path)), f);
+        self.routes().add(method, path, f);
+    }
+
+    /// Return a handle to add, replace, disable or remove routes, usable from any thread while the server is running.
+    /// 
+    /// The GET /404.html route is the handler of the requests without route, a built-in 404 response is used if it's removed or disabled.
+    pub fn routes(&self) -> RouteHandle {
+        RouteHandle::new(Arc::clone(&self.routing))
+    }
+
+    /// Add a middleware run before the handler of every request, including the ones without route (404).
+    /// 
+    /// Middlewares are run in the order they are added.
+    pub fn middleware(&mut self, middleware: Box<dyn Middleware>) {
+        self.middleware_at(String::from("/"), middleware);
+    }
+
+    /// Add a middleware only run for the requests whose path starts with the given path (e.g. "/admin" for "/admin" and "/admin/users").
+    /// 
+    /// This is how a group of routes can share a middleware (authentication, rate limiting, etc.).
+    pub fn middleware_at(&mut self, path: String, middleware: Box<dyn Middleware>) {
+        self.middlewares.push((RequestPath::new_route(path), Arc::from(middleware)));
     }
 
     /// Set the number of workers used to handle the requests.
@@ -136,95 +249,890 @@ impl Server {
         self.number_of_workers = number;
     }
 
-    /// Open the socket and listen on the given port.
+    /// Set the maximum size in bytes of a request body, a 413 error is sent to the client if the Content-Length is bigger.
+    /// The default value is 8 MiB.
+    /// 
+    /// multipart/form-data bodies aren't rejected on their Content-Length, Request::multipart() limits them with MultipartLimits instead,
+    /// but Request::get_body() doesn't read more than this size whatever the content-type.
+    pub fn set_max_body_size(&mut self, size: u64) {
+        self.max_body_size = size;
+    }
+
+    /// Set the keyring used to sign and encrypt cookies, see Response::add_signed_cookie() and Response::add_private_cookie().
+    /// 
+    /// Use a key stored outside of the program (see cookie::Key::from_bytes()) so cookies are still valid after a restart.
+    pub fn set_cookie_keyring(&mut self, keyring: Keyring) {
+        self.cookie_keyring = Some(Arc::new(keyring));
+    }
+
+    /// Set whether the IPv6 sockets only accept IPv6 connections.
+    /// The default value is false: a socket bound to `[::]` also accepts the IPv4 connections (dual-stack),
+    /// so `0.0.0.0` and `[::]` can't be bound on the same port unless this is set to true.
+    pub fn set_ipv6_only(&mut self, ipv6_only: bool) {
+        self.ipv6_only = ipv6_only;
+    }
+
+    /// Set how long the running requests have to finish once the shutdown starts, the default value is 30 seconds.
+    /// 
+    /// When the shutdown starts, the server stops accepting connections and closes the ones whose request hasn't been received yet.
+    /// The connections still open after this timeout are closed, so their handlers can't read or send anything anymore.
+    pub fn set_shutdown_timeout(&mut self, timeout: Duration) {
+        self.shutdown_timeout = timeout;
+    }
+
+    /// Set the maximum time to wait for data from a client, None to wait forever. The default value is 30 seconds.
+    /// 
+    /// The request line and the headers must be received entirely within this time, otherwise the client gets a 408 error,
+    /// then each read of the body waits at most this time.
+    /// The headers are read by the workers, so a slow client only holds one worker, up to this timeout.
+    pub fn set_read_timeout(&mut self, timeout: Option<Duration>) {
+        self.read_timeout = timeout;
+    }
+
+    /// Set the format of the request log written on the standard output, the default value is LogFormat::Text.
+    pub fn set_log_format(&mut self, log_format: LogFormat) {
+        self.log_format = log_format;
+    }
+
+    /// Apply the settings of a ServerConfig (everything but the addresses, given to listen_on()).
+    pub fn apply_config(&mut self, config: &ServerConfig) {
+        self.set_number_of_worker(config.workers);
+        self.set_ipv6_only(config.ipv6_only);
+        self.set_read_timeout(config.read_timeout);
+        self.set_shutdown_timeout(config.shutdown_timeout);
+        self.set_max_body_size(config.max_body_size);
+        self.set_log_format(config.log_format);
+    }
+
+    /// Return a token which starts the shutdown of the server when triggered, it can be cloned and sent to other threads.
+    /// 
+    /// Once shut down, the server can't be started again, listen() returns immediately.
+    pub fn shutdown_token(&self) -> ShutdownToken {
+        ShutdownToken::new(Arc::clone(&self.shutdown))
+    }
+
+    /// Set whether the server handles the signals itself while it's running, the default value is false,
+    /// so the application can install its own handlers and stop the server with shutdown_token().
+    /// 
+    /// When enabled:
+    /// - SIGTERM starts the graceful shutdown, the next ones are ignored (the service manager kills the process if it takes too long).
+    /// - SIGINT (Ctrl-C) starts the graceful shutdown, a second SIGINT exits immediately.
+    /// - SIGHUP calls the functions registered with on_reload() instead of stopping the server.
+    /// 
+    /// Only Ctrl-C is handled on Windows, and only by one server per process.
+    pub fn set_signal_handling(&mut self, signal_handling: bool) {
+        self.signal_handling = signal_handling;
+    }
+
+    /// Register a function called when the server receives SIGHUP (see set_signal_handling()), to reload the configuration for example.
+    pub fn on_reload(&mut self, f: Box<HookFn>) {
+        self.reload_hooks.push(f);
+    }
+
+    /// Add a reloadable configuration: each request gets a snapshot of it in its extensions (an `Arc<T>`),
+    /// and it's reloaded when the server receives SIGHUP.
+    /// 
+    /// The snapshot is added before the other middlewares run, so they can use it too. See the config module.
+    pub fn set_config<T: Send + Sync + 'static>(&mut self, config: config::Reloadable<T>) {
+        let reloaded = config.clone();
+        self.on_reload(Box::new(move || {
+            if let Err(e) = reloaded.reload() {
+                eprintln!("Cannot reload the configuration, the current one is kept: {}", e);
+            }
+        }));
+        self.middlewares.insert(0, (RequestPath::new_route(String::from("/")), Arc::new(config)));
+    }
+
+    /// Register a function called once the sockets are bound, before the workers are started and the connections accepted,
+    /// it gets the addresses of the sockets (with the port chosen by the system for port 0).
+    /// 
+    /// If a function returns Err, the server doesn't start and listen() returns the error.
+    /// The functions are called in their registration order, for all the startup and shutdown hooks.
+    /// 
+    /// ## Example:
+    /// ```no_run
+    /// use rest_server::Server;
+    /// 
+    /// let mut app = Server::new();
+    /// app.on_start(Box::new(|_addrs| {
+    ///     // warm the caches, fails if the database can't be reached
+    ///     Ok(())
+    /// }));
+    /// app.on_ready(Box::new(|addrs| {
+    ///     println!("Listening on {}", addrs[0]);
+    ///     Ok(())
+    /// }));
+    /// app.on_stopped(Box::new(|| println!("Every request has been handled")));
+    /// app.listen(7878).unwrap();
+    /// ```
+    pub fn on_start(&mut self, f: Box<StartFn>) {
+        self.start_hooks.push(f);
+    }
+
+    /// Register a function called once the workers are running and the connections are accepted, to register the server
+    /// in a service discovery for example.
+    /// 
+    /// If a function returns Err, the server is shut down like with the shutdown token and listen() returns the error.
+    pub fn on_ready(&mut self, f: Box<StartFn>) {
+        self.ready_hooks.push(f);
+    }
+
+    /// Register a function called when the shutdown begins, once the server stopped accepting connections
+    /// and before waiting for the running requests.
+    pub fn on_shutdown(&mut self, f: Box<HookFn>) {
+        self.shutdown_hooks.push(f);
+    }
+
+    /// Register a function called when the server is stopped, once all the workers have finished, to flush buffers for example.
+    pub fn on_stopped(&mut self, f: Box<HookFn>) {
+        self.stopped_hooks.push(f);
+    }
+
+    /// Open the socket and listen on the given port of the loopback address (127.0.0.1), see listen_on() to use other addresses.
     /// The socket is opened in blocking mode to use least CPU usage possible.
     /// 
-    /// Because of that, if you use ctrl+c, the program will not stop immediately, but will wait for the current requests and the next ones to finish.
+    /// The function returns when the shutdown is triggered, by shutdown_token() or by Ctrl-C if set_signal_handling() is enabled:
+    /// the server stops accepting connections and waits for the current requests to finish (see set_shutdown_timeout()).
     /// After, the socket is closed, destructor will be called and the program will stop.
     /// 
-    /// port is the port on which the server will listen, if port isn't positive, the program will panic.
-    pub fn listen(&mut self, port: u32) {
-        assert!(port > 0);
-        let listener = TcpListener::bind(format!("127.0.0.1:{}", port)).expect("Could not bind to port");
+    /// Return Err if the socket can't be opened (the port is already used, etc.).
+    pub fn listen(&mut self, port: u16) -> io::Result<()> {
+        self.listen_on(&[SocketAddr::from(([127, 0, 0, 1], port))])
+    }
+
+    /// Open a socket for each address and listen on all of them, each socket is served by its own thread and the requests
+    /// are handled by the same workers.
+    /// 
+    /// Use `0.0.0.0` to accept the connections on every IPv4 interface and `[::]` for every IPv6 interface (and IPv4 too, see set_ipv6_only()).
+    /// 
+    /// Return Err if one of the sockets can't be opened, no request is handled in this case.
+    /// 
+    /// ## Example:
+    /// ```no_run
+    /// use rest_server::Server;
+    /// use std::net::SocketAddr;
+    /// 
+    /// let mut app = Server::new();
+    /// let addrs: Vec<SocketAddr> = vec!["[::]:8080".parse().unwrap(), "127.0.0.1:9090".parse().unwrap()];
+    /// if let Err(e) = app.listen_on(&addrs) {
+    ///     eprintln!("Cannot start the server: {}", e);
+    /// }
+    /// ```
+    pub fn listen_on(&mut self, addrs: &[SocketAddr]) -> io::Result<()> {
+        if addrs.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No address to listen on"));
+        }
+        let listeners = addrs.iter().map(|addr| self.bind_listener(*addr).map(Listener::Tcp)).collect::<io::Result<Vec<Listener>>>()?;
+        self.serve(listeners, None)
+    }
+
+    /// Open the socket and run the server in a background thread, return a handle to get the bound address and to stop the server.
+    /// 
+    /// Use port 0 to let the system pick a free port, so many servers can run in parallel (in tests for example).
+    /// The server runs until ServerHandle::shutdown() is called, the shutdown token is triggered or the handle is dropped.
+    /// 
+    /// Return once the server is ready (see on_ready()), or Err if the socket can't be opened or if a startup hook fails.
+    pub fn bind(mut self, addr: SocketAddr) -> io::Result<ServerHandle> {
+        let listener = self.bind_listener(addr)?;
+        let local_addr = listener.local_addr()?;
+        let listeners = vec![Listener::Tcp(listener)];
+        let shutdown = Arc::clone(&self.shutdown);
+        let (started, ready) = mpsc::channel();
+        let thread = thread::Builder::new()
+            .name(format!("server {}", local_addr))
+            .spawn(move || self.serve(listeners, Some(started)))?;
+        if ready.recv().is_err() {
+            // the server stopped before being ready, its result tells why
+            return match thread.join() {
+                Ok(Err(e)) => Err(e),
+                Ok(Ok(())) => Err(io::Error::other("The server stopped during startup")),
+                Err(_) => Err(io::Error::other("The server thread panicked")),
+            };
+        }
+        Ok(ServerHandle::new(local_addr, shutdown, thread))
+    }
+
+    /// Listen on a Unix domain socket instead of a TCP port, the requests are handled like the TCP ones
+    /// and the credentials of the client process are available with Request::connection().
+    /// 
+    /// The socket file is removed when the server stops.
+    /// Return Err if the socket can't be created or if its permissions can't be set.
+    #[cfg(unix)]
+    pub fn listen_unix(&mut self, socket: unix::UnixSocket) -> io::Result<()> {
+        let listener = socket.bind()?;
+        let result = self.serve(vec![Listener::Unix(listener)], None);
+        if let Err(e) = std::fs::remove_file(socket.path()) {
+            eprintln!("Cannot remove {}: {}", socket.path().display(), e);
+        }
+        result
+    }
+
+    /// Listen on already opened sockets (TCP or Unix), inherited from the parent process for example.
+    /// 
+    /// Return Err if one of the file descriptors isn't a listening stream socket.
+    #[cfg(unix)]
+    pub fn listen_fds(&mut self, fds: Vec<std::os::fd::OwnedFd>) -> io::Result<()> {
+        if fds.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "No socket to listen on"));
+        }
+        let listeners = fds.into_iter().map(systemd::listener_from_fd).collect::<io::Result<Vec<Listener>>>()?;
+        self.serve(listeners, None)
+    }
+
+    /// Listen on the sockets passed by systemd socket activation (see systemd::listen_fds()),
+    /// and notify systemd when the server is ready or stopping if no notifier has been set with set_notifier().
+    /// 
+    /// The environment variables aren't removed, to remove them call systemd::listen_fds(true) at the beginning of main,
+    /// before spawning threads, and give the sockets to listen_fds() instead.
+    /// 
+    /// Return Err if the process wasn't started by socket activation.
+    /// 
+    /// ## Example:
+    /// ```text
+    /// # my-api.socket
+    /// [Socket]
+    /// ListenStream=8080
+    /// 
+    /// # my-api.service
+    /// [Service]
+    /// Type=notify
+    /// ExecStart=/usr/local/bin/my-api
+    /// WatchdogSec=30
+    /// ```
+    #[cfg(unix)]
+    pub fn listen_systemd(&mut self) -> io::Result<()> {
+        let fds = systemd::listen_fds(false)?;
+        if fds.is_empty() {
+            return Err(io::Error::new(io::ErrorKind::NotFound, "No socket passed by systemd (LISTEN_FDS isn't set)"));
+        }
+        if self.notifier.is_none() {
+            self.notifier = systemd::Notifier::from_env();
+        }
+        self.listen_fds(fds.into_iter().map(|fd| fd.fd).collect())
+    }
+
+    /// Set the notifier used to tell the service manager when the server is ready or stopping, and to feed its watchdog.
+    #[cfg(unix)]
+    pub fn set_notifier(&mut self, notifier: systemd::Notifier) {
+        self.notifier = Some(notifier);
+    }
+
+    /// Open a listening socket on the address.
+    fn bind_listener(&self, addr: SocketAddr) -> io::Result<TcpListener> {
+        let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+        if addr.is_ipv6() {
+            socket.set_only_v6(self.ipv6_only)?;
+        }
+        // same behaviour as TcpListener::bind(), so the server can be restarted while the old connections are in TIME_WAIT
+        #[cfg(unix)]
+        socket.set_reuse_address(true)?;
+        socket.bind(&addr.into()).map_err(|e| io::Error::new(e.kind(), format!("Cannot bind to {}: {}", addr, e)))?;
+        socket.listen(128)?;
+        Ok(socket.into())
+    }
+
+    /// Accept the connections of the listeners until the shutdown starts, then wait for the active connections to be closed,
+    /// up to the shutdown timeout.
+    /// 
+    /// A message is sent to started once the server is ready, it's dropped without message if the startup fails.
+    fn serve(&mut self, listeners: Vec<Listener>, started: Option<mpsc::Sender<()>>) -> io::Result<()> {
+        let addrs = listeners.iter().map(Listener::local_addr).collect::<io::Result<Vec<ListenAddr>>>()?;
+        Self::run_start_hooks(&self.start_hooks, &addrs)?;
         let pool = ThreadPool::new(self.number_of_workers);
-        let exit = Arc::new(RwLock::new(AtomicBool::new(false)));
-        let exit_clone = Arc::clone(&exit);
+        let shutdown = Arc::clone(&self.shutdown);
+        shutdown.set_listeners(&listeners);
 
-        ctrlc::set_handler(move || {
-            // We run like this because we want all already running request to finish and destructors to run before leaving the program
-            if exit_clone.read().unwrap().load(std::sync::atomic::Ordering::SeqCst) {
-                println!("Shutdown sequence already started, forcing exit (not recommended)");
-                std::process::exit(0);
-            } else {
-                println!("Shutting down... (shutdown down sequence will start when next request is received and after all workers are done)");
-                exit_clone.write().unwrap().store(true, std::sync::atomic::Ordering::SeqCst);
-            }
-        }).expect("Error setting Ctrl-C handler");
-
-        for stream in listener.incoming() {
-            let stream = stream.unwrap();
-            let ifn = self.handle_connection(stream.try_clone().unwrap());
-            match ifn {
-                Ok((request, response)) => {
-                    let routing_clone = Arc::clone(&self.routing);
-                    pool.execute(move || {
-                        let r = routing_clone.read().unwrap();
-                        println!("[REQUEST] {} {}", request.method, request.path);
-                        let route = r.get(&(request.method, request.path.clone())).unwrap();
-                        route(request, response)
-                    });
-                    let clone = Arc::clone(&exit);
-                    if clone.read().unwrap().load(std::sync::atomic::Ordering::SeqCst) {
-                        drop(pool);
-                        break;
+        #[cfg(unix)]
+        let signals = match self.signal_handling {
+            true => Some(Signals::new([SIGTERM, SIGINT, SIGHUP]).map_err(|e| io::Error::new(e.kind(), format!("Cannot handle the signals: {}", e)))?),
+            false => None,
+        };
+        #[cfg(not(unix))]
+        if self.signal_handling {
+            let shutdown = Arc::clone(&shutdown);
+            ctrlc::set_handler(move || {
+                if shutdown.request() {
+                    println!("Shutting down... (waiting for the running requests to finish, press Ctrl-C again to force exit)");
+                } else {
+                    println!("Shutdown sequence already started, forcing exit (not recommended)");
+                    std::process::exit(130);
+                }
+            }).map_err(|e| io::Error::other(format!("Error setting Ctrl-C handler: {}", e)))?;
+        }
+
+        let middlewares = Arc::new(self.middlewares.clone());
+        let server = &*self;
+        let result = thread::scope(|scope| {
+            #[cfg(unix)]
+            let signals = signals.map(|mut signals| {
+                let handle = signals.handle();
+                scope.spawn(move || {
+                    for signal in signals.forever() {
+                        server.handle_signal(signal);
+                    }
+                });
+                handle
+            });
+            let (stop_watchdog, watchdog_stopped) = mpsc::channel::<()>();
+            #[cfg(unix)]
+            if let Some((notifier, interval)) = self.notifier.as_ref().and_then(|n| n.get_watchdog_interval().map(|i| (n, i))) {
+                scope.spawn(move || {
+                    // the watchdog is notified twice per interval, so a late message doesn't kill the server
+                    while let Err(mpsc::RecvTimeoutError::Timeout) = watchdog_stopped.recv_timeout(interval / 2) {
+                        if let Err(e) = notifier.watchdog() {
+                            eprintln!("Cannot notify the watchdog: {}", e);
+                        }
+                    }
+                });
+            }
+            let accept_loops = listeners.iter()
+                .map(|listener| scope.spawn(|| server.accept_loop(listener, &pool, &shutdown, &middlewares)))
+                .collect::<Vec<_>>();
+            let result = Self::run_start_hooks(&self.ready_hooks, &addrs);
+            match &result {
+                Ok(()) => {
+                    self.notify("READY=1");
+                    if let Some(started) = started {
+                        let _ = started.send(());
                     }
-                    drop(clone);
                 },
                 Err(e) => {
                     eprintln!("{}", e);
+                    shutdown.request();
                 },
             }
+            for accept_loop in accept_loops {
+                let _ = accept_loop.join();
+            }
+            self.notify("STOPPING=1");
+            for hook in &self.shutdown_hooks {
+                hook();
+            }
+            let forced = shutdown.drain(self.shutdown_timeout);
+            if forced > 0 {
+                eprintln!("Shutdown timeout reached, {} connection(s) closed", forced);
+            }
+            drop(stop_watchdog);
+            #[cfg(unix)]
+            if let Some(signals) = signals {
+                signals.close();
+            }
+            result
+        });
+        drop(pool);
+        for hook in &self.stopped_hooks {
+            hook();
+        }
+        result
+    }
+
+    /// Call the startup hooks, stop at the first error.
+    fn run_start_hooks(hooks: &[Box<StartFn>], addrs: &[ListenAddr]) -> io::Result<()> {
+        hooks.iter()
+            .try_for_each(|hook| hook(addrs))
+            .map_err(|e| io::Error::other(format!("Startup aborted: {}", e)))
+    }
+
+    /// Handle a signal received while the server is running, see set_signal_handling().
+    #[cfg(unix)]
+    fn handle_signal(&self, signal: i32) {
+        match signal {
+            SIGHUP => {
+                println!("Reloading...");
+                for hook in &self.reload_hooks {
+                    hook();
+                }
+            },
+            // We run like this because we want all already running request to finish and destructors to run before leaving the program
+            _ if self.shutdown.request() => {
+                println!("Shutting down... (waiting for the running requests to finish)");
+            },
+            SIGINT => {
+                println!("Shutdown sequence already started, forcing exit (not recommended)");
+                std::process::exit(130);
+            },
+            _ => {},
+        }
+    }
+
+    /// Send a notification to the service manager if a notifier is set.
+    fn notify(&self, state: &str) {
+        #[cfg(unix)]
+        if let Some(notifier) = &self.notifier {
+            if let Err(e) = notifier.notify(state) {
+                eprintln!("Cannot notify the service manager: {}", e);
+            }
+        }
+        #[cfg(not(unix))]
+        let _ = state;
+    }
 
+    /// Accept the connections of a listener and give their requests to the workers, until the shutdown starts.
+    fn accept_loop(&self, listener: &Listener, pool: &ThreadPool, shutdown: &Arc<Shutdown>, middlewares: &Arc<Vec<(RequestPath, Arc<dyn Middleware>)>>) {
+        loop {
+            let stream = match listener.accept() {
+                Ok(stream) => stream,
+                Err(e) => {
+                    eprintln!("Cannot accept connection: {}", e);
+                    if e.kind() == io::ErrorKind::InvalidInput {
+                        // the socket isn't listening anymore
+                        break;
+                    }
+                    if e.kind() != io::ErrorKind::ConnectionAborted {
+                        // out of file descriptors or memory, wait for the workers to close some connections
+                        thread::sleep(Duration::from_millis(100));
+                    }
+                    continue;
+                },
+            };
+            let guard = match shutdown.register(&stream) {
+                Ok(Some(guard)) => guard,
+                // woken up by the shutdown, or a connection accepted after it
+                Ok(None) => break,
+                Err(e) => {
+                    eprintln!("Cannot register connection: {}", e);
+                    continue;
+                },
+            };
+            let settings = ConnectionSettings {
+                read_timeout: self.read_timeout,
+                max_body_size: self.max_body_size,
+                cookie_keyring: self.cookie_keyring.clone(),
+            };
+            let routing_clone = Arc::clone(&self.routing);
+            let middlewares = Arc::clone(middlewares);
+            let log_format = self.log_format;
+            // the headers are read by the worker, so a slow client doesn't delay the next connections
+            pool.execute(move || {
+                match Self::handle_connection(&settings, stream) {
+                    Ok((request, response)) => {
+                        guard.set_active();
+                        Self::dispatch(&routing_clone, &middlewares, log_format, request, response);
+                    },
+                    Err(e) => {
+                        eprintln!("{}", e);
+                    },
+                }
+                drop(guard);
+            });
         }
     }
 
+    /// Run the middlewares matching the request path then the route handler.
+    /// If no enabled route is found, the 404 route (GET /404.html) is used as handler.
+    fn dispatch(routing: &RwLock<Routes>, middlewares: &[(RequestPath, Arc<dyn Middleware>)], log_format: LogFormat, request: Request, response: Response) {
+        println!("{}", log_format.request_line(&request));
+        let route = {
+            // the handler is cloned out of the table, so the routes can be changed while it runs
+            let r = routing.read().unwrap();
+            let get = |method: Method, path: RequestPath| r.get(&(method, path)).filter(|route| route.enabled).map(|route| Arc::clone(&route.handler));
+            get(request.method, request.path.clone())
+                .or_else(|| get(Method::GET, RequestPath::new_route(String::from("/404.html"))))
+                .unwrap_or_else(|| Arc::new(not_found))
+        };
+        let chain = middlewares.iter()
+            .filter(|(prefix, _)| request.path.starts_with(prefix))
+            .map(|(_, middleware)| Arc::clone(middleware))
+            .collect::<Vec<Arc<dyn Middleware>>>();
+        Next::new(&chain, route.as_ref()).run(request, response);
+    }
+
     /// Handle a connection and return a tuple containing the request and the response usable to send a response to the client.
-    /// If a path is not found, the function will return a 404 not found response.
     /// If the method is not supported, the function will return an Result::Err containing the error. (In the future, it will return a 405 method not allowed response)
-    fn handle_connection(&self, mut stream: TcpStream) -> Result<(Request, Response), String> {
-        let buffer = &mut [0; 1024];
-        stream.read(buffer).unwrap();
-        let s = match str::from_utf8(buffer) {
+    /// 
+    /// Only the request line and the headers are read here, the body is read later from the stream when the handler needs it.
+    /// If the headers or the body are too large, an error response is sent to the client and a Result::Err is returned.
+    fn handle_connection(settings: &ConnectionSettings, mut stream: Stream) -> Result<(Request, Response), String> {
+        let (head, leftover) = match Self::read_head(&mut stream, settings.read_timeout) {
+            Ok(v) => v,
+            Err(e) => {
+                if e == HEAD_TOO_LARGE {
+                    Self::reject(&stream, Status::RequestHeaderFieldsTooLarge);
+                } else if e == HEAD_TIMEOUT {
+                    Self::reject(&stream, Status::RequestTimeout);
+                }
+                return Err(e);
+            },
+        };
+        stream.set_read_timeout(settings.read_timeout).map_err(|e| format!("Cannot set read timeout: {}", e))?;
+        let content = match String::from_utf8(head) {
             Ok(v) => v,
             Err(e) => return Err(format!("Cannot convert to str {}", e)),
         };
-        let content = String::from(s);
         let s = content.split("\r\n").collect::<Vec<&str>>();
-        return if let Ok(method) = Method::parse_method(s.get(0)) {
-            return match self.routing.read().unwrap().contains_key(&method) {
-                true => {
-                    let request = self.construct_request(content, method.0, method.1);
-                    let response = self.construct_response(stream.try_clone().unwrap());
-                    Ok((request, response))
-                },
-                false => {
-                    Ok((Request::new(Method::GET, RequestPath::new_route(String::from("/404.html")), String::new()), Response::new(stream.try_clone().unwrap())))
-                    //Err(format!("No handler found for {} {}", method.0, method.1))
-                },
-            }
+        if let Ok(method) = Method::parse_method(s.first()) {
+            let request = Self::construct_request(settings, &content, leftover, stream.try_clone().unwrap(), method.0, method.1)?;
+            let response = Self::construct_response(settings, stream.try_clone().unwrap());
+            Ok((request, response))
         } else {
             Err(String::from("No method found"))
         }
     }
 
-    /// Construct a request from the given content and the given method and path.
-    fn construct_request(&self, content: String, method: Method, path: RequestPath) -> Request {
-        request::Request::new(method, path, content)
+    /// Read the request line and the headers from the stream, they must be received within the timeout.
+    /// 
+    /// Return the head (without the empty line ending it) and the bytes of the body which have been read with it.
+    fn read_head(stream: &mut Stream, timeout: Option<Duration>) -> Result<(Vec<u8>, Vec<u8>), String> {
+        let deadline = timeout.map(|timeout| Instant::now() + timeout);
+        let mut content = Vec::new();
+        let buffer = &mut [0; 1024];
+        loop {
+            // the timeout applies to the whole head, not to each read, so a client can't keep the worker by sending a byte at a time
+            let remaining = deadline.map(|deadline| deadline.saturating_duration_since(Instant::now()));
+            if remaining.is_some_and(|remaining| remaining.is_zero()) {
+                return Err(String::from(HEAD_TIMEOUT));
+            }
+            stream.set_read_timeout(remaining).map_err(|e| format!("Cannot set read timeout: {}", e))?;
+            let size = stream.read(buffer).map_err(|e| match e.kind() {
+                io::ErrorKind::WouldBlock | io::ErrorKind::TimedOut => String::from(HEAD_TIMEOUT),
+                _ => format!("Cannot read request: {}", e),
+            })?;
+            if size == 0 {
+                return Err(String::from("Connection closed before the end of the request headers"));
+            }
+            // the end of the headers can be split between the previous read and this one
+            let search_from = content.len().saturating_sub(3);
+            content.extend_from_slice(&buffer[..size]);
+            if let Some(index) = content[search_from..].windows(4).position(|w| w == b"\r\n\r\n") {
+                let index = search_from + index;
+                let leftover = content.split_off(index + 4);
+                content.truncate(index);
+                return Ok((content, leftover));
+            }
+            if content.len() > MAX_HEAD_SIZE {
+                return Err(String::from(HEAD_TOO_LARGE));
+            }
+        }
+    }
+
+    /// Send a response with an empty body and the given status, used when the request can't be handled.
+    fn reject(stream: &Stream, status: Status) {
+        match stream.try_clone() {
+            Ok(stream) => {
+                let mut response = Response::from_stream(stream);
+                response.set_status(status);
+                response.send();
+            },
+            Err(e) => eprintln!("{}", e),
+        }
+    }
+
+    /// Construct a request from the given head and the given method and path.
+    /// 
+    /// The body is read lazily from leftover (the bytes already read with the head) then from the stream, up to Content-Length bytes.
+    /// Return Err and send an error response to the client if the body can't be accepted.
+    fn construct_request(settings: &ConnectionSettings, head: &str, mut leftover: Vec<u8>, stream: Stream, method: Method, path: RequestPath) -> Result<Request, String> {
+        let mut request = Request::from_head(method, path, head);
+        request.set_connection(ConnectionInfo::from_stream(&stream));
+        if request.get_header("Transfer-Encoding").is_some_and(|v| !v.eq_ignore_ascii_case("identity")) {
+            Self::reject(&stream, Status::NotImplemented);
+            return Err(String::from("Transfer-Encoding isn't supported"));
+        }
+        let length = match request.get_header("Content-Length").map(|v| v.parse::<u64>()) {
+            Some(Ok(length)) => length,
+            Some(Err(e)) => {
+                Self::reject(&stream, Status::BadRequest);
+                return Err(format!("Invalid Content-Length: {}", e));
+            },
+            None => 0,
+        };
+        // multipart bodies aren't kept in memory, they are limited by MultipartLimits instead
+        if length > settings.max_body_size && request.content_type().as_deref() != Some("multipart/form-data") {
+            Self::reject(&stream, Status::PayloadTooLarge);
+            return Err(format!("Request body too large: {} bytes", length));
+        }
+        if length > 0 && request.get_header("Expect").is_some_and(|v| v.eq_ignore_ascii_case("100-continue")) {
+            // the client waits for this interim response before sending a big body
+            if let Err(e) = (&stream).write_all(b"HTTP/1.1 100 Continue\r\n\r\n") {
+                return Err(format!("Cannot send 100 Continue: {}", e));
+            }
+        }
+        leftover.truncate(length.min(usize::MAX as u64) as usize);
+        let remaining = length - leftover.len() as u64;
+        let reader = std::io::Cursor::new(leftover).chain(stream.take(remaining));
+        request.set_body_reader(Box::new(reader));
+        request.set_max_body_size(settings.max_body_size);
+        request.set_keyring(settings.cookie_keyring.clone());
+        Ok(request)
     }
 
     /// Construct a response from the given stream.
     /// The stream is used to send the response to the client and is closed when the response is sent.
-    fn construct_response(&self, stream: TcpStream) -> Response {
-        response::Response::new(stream)
+    fn construct_response(settings: &ConnectionSettings, stream: Stream) -> Response {
+        let mut response = Response::from_stream(stream);
+        response.set_keyring(settings.cookie_keyring.clone());
+        response
+    }
+}
+
+impl Default for Server {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::net::TcpStream;
+    use std::sync::Mutex;
+
+    fn server(read_timeout: Duration) -> ServerHandle {
+        let mut app = Server::new();
+        app.set_number_of_worker(2);
+        app.set_read_timeout(Some(read_timeout));
+        app.get(String::from("/"), Box::new(|_request, mut response| {
+            response.set_body("Hello");
+            response.send();
+        }));
+        app.bind("127.0.0.1:0".parse().unwrap()).unwrap()
+    }
+
+    #[test]
+    fn slow_client_does_not_block_the_next_connections() {
+        let handle = server(Duration::from_secs(5));
+        let mut slow = TcpStream::connect(handle.local_addr()).unwrap();
+        slow.write_all(b"GET / HTTP/1.1\r\n").unwrap();
+
+        let start = Instant::now();
+        let mut fast = TcpStream::connect(handle.local_addr()).unwrap();
+        fast.write_all(b"GET / HTTP/1.1\r\nHost: localhost\r\n\r\n").unwrap();
+        let mut content = String::new();
+        fast.read_to_string(&mut content).unwrap();
+        assert!(content.ends_with("Hello"));
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn headers_must_arrive_before_the_deadline() {
+        let handle = server(Duration::from_millis(500));
+        let mut client = TcpStream::connect(handle.local_addr()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        let mut writer = client.try_clone().unwrap();
+        let start = Instant::now();
+        // a byte every 100 ms: each read is quick, but the headers never end
+        let trickle = thread::spawn(move || {
+            for _ in 0..100 {
+                if writer.write_all(b"a").is_err() {
+                    break;
+                }
+                thread::sleep(Duration::from_millis(100));
+            }
+        });
+        let mut content = Vec::new();
+        let mut buffer = [0; 1024];
+        // the connection can be reset by the bytes written after the server closed it
+        while let Ok(size) = client.read(&mut buffer) {
+            if size == 0 {
+                break;
+            }
+            content.extend_from_slice(&buffer[..size]);
+        }
+        assert!(start.elapsed() < Duration::from_secs(3));
+        assert!(content.is_empty() || content.starts_with(b"HTTP/1.1 408"));
+        drop(client);
+        trickle.join().unwrap();
+    }
+
+    /// Send a GET request for path to the server, the response can be read from the returned stream.
+    fn send(addr: SocketAddr, path: &str) -> TcpStream {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).unwrap();
+        stream
+    }
+
+    /// Read until the connection is closed, a reset connection ends the content too.
+    fn read_all(stream: &mut TcpStream) -> String {
+        let mut content = Vec::new();
+        let mut buffer = [0; 1024];
+        while let Ok(size) = stream.read(&mut buffer) {
+            if size == 0 {
+                break;
+            }
+            content.extend_from_slice(&buffer[..size]);
+        }
+        String::from_utf8(content).unwrap()
+    }
+
+    /// Return a server whose /slow handler signals started then sleeps for delay before answering.
+    fn slow_server(delay: Duration, shutdown_timeout: Duration) -> (ServerHandle, mpsc::Receiver<()>) {
+        let (started, handler_started) = mpsc::channel();
+        let mut app = Server::new();
+        app.set_number_of_worker(2);
+        app.set_shutdown_timeout(shutdown_timeout);
+        app.get(String::from("/slow"), Box::new(move |_request, mut response| {
+            started.send(()).unwrap();
+            thread::sleep(delay);
+            response.set_body("done");
+            response.send();
+        }));
+        (app.bind("127.0.0.1:0".parse().unwrap()).unwrap(), handler_started)
+    }
+
+    #[test]
+    fn idle_server_shuts_down_promptly() {
+        let handle = server(Duration::from_secs(5));
+        let start = Instant::now();
+        handle.shutdown();
+        handle.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn idle_connection_is_closed_by_the_shutdown() {
+        let handle = server(Duration::from_secs(30));
+        let mut client = TcpStream::connect(handle.local_addr()).unwrap();
+        client.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        // wait for the connection to be accepted and registered
+        thread::sleep(Duration::from_millis(200));
+        let start = Instant::now();
+        handle.shutdown();
+        assert_eq!(read_all(&mut client), "");
+        handle.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn running_request_finishes_during_the_grace_period() {
+        let (handle, handler_started) = slow_server(Duration::from_millis(500), Duration::from_secs(10));
+        let mut client = send(handle.local_addr(), "/slow");
+        handler_started.recv_timeout(Duration::from_secs(5)).unwrap();
+        handle.shutdown();
+        let content = read_all(&mut client);
+        assert!(content.starts_with("HTTP/1.1 200"));
+        assert!(content.ends_with("done"));
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn running_request_is_closed_after_the_shutdown_timeout() {
+        let (handle, handler_started) = slow_server(Duration::from_secs(2), Duration::from_millis(200));
+        let mut client = send(handle.local_addr(), "/slow");
+        handler_started.recv_timeout(Duration::from_secs(5)).unwrap();
+        let start = Instant::now();
+        handle.shutdown();
+        // the connection is closed at the end of the grace period, before the handler finishes
+        assert_eq!(read_all(&mut client), "");
+        assert!(start.elapsed() < Duration::from_millis(1500));
+        handle.join().unwrap();
+    }
+
+    /// Events recorded by the hooks and the handlers, in order.
+    type Events = Arc<Mutex<Vec<String>>>;
+
+    fn record(events: &Events, event: &str) {
+        events.lock().unwrap().push(String::from(event));
+    }
+
+    /// Return the error of a server which must fail to start.
+    fn startup_error(app: Server) -> io::Error {
+        match app.bind("127.0.0.1:0".parse().unwrap()) {
+            Err(e) => e,
+            Ok(_) => panic!("the server started"),
+        }
+    }
+
+    #[test]
+    fn start_hook_gets_the_bound_address() {
+        let addrs = Arc::new(Mutex::new(Vec::new()));
+        let mut app = Server::new();
+        let hook_addrs = Arc::clone(&addrs);
+        app.on_start(Box::new(move |addrs| {
+            hook_addrs.lock().unwrap().extend_from_slice(addrs);
+            Ok(())
+        }));
+        let handle = app.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_ne!(handle.local_addr().port(), 0);
+        assert_eq!(*addrs.lock().unwrap(), vec![ListenAddr::Tcp(handle.local_addr())]);
+    }
+
+    #[test]
+    fn failing_start_hook_aborts_the_startup() {
+        let events = Events::default();
+        let bound = Arc::new(Mutex::new(None));
+        let mut app = Server::new();
+        let hook_bound = Arc::clone(&bound);
+        app.on_start(Box::new(move |addrs| {
+            *hook_bound.lock().unwrap() = Some(addrs[0].clone());
+            Err(String::from("database unreachable"))
+        }));
+        let hook_events = Arc::clone(&events);
+        app.on_ready(Box::new(move |_addrs| {
+            record(&hook_events, "ready");
+            Ok(())
+        }));
+        let error = startup_error(app);
+        assert!(error.to_string().contains("database unreachable"));
+        assert!(events.lock().unwrap().is_empty());
+        // the socket has been closed without serving
+        let Some(ListenAddr::Tcp(addr)) = bound.lock().unwrap().clone() else {
+            panic!("the start hook wasn't called with a TCP address");
+        };
+        assert!(TcpStream::connect(addr).is_err());
+    }
+
+    #[test]
+    fn failing_ready_hook_shuts_the_server_down() {
+        let events = Events::default();
+        let mut app = Server::new();
+        app.on_ready(Box::new(|_addrs| Err(String::from("registration failed"))));
+        let hook_events = Arc::clone(&events);
+        app.on_shutdown(Box::new(move || record(&hook_events, "shutdown")));
+        let hook_events = Arc::clone(&events);
+        app.on_stopped(Box::new(move || record(&hook_events, "stopped")));
+        let error = startup_error(app);
+        assert!(error.to_string().contains("registration failed"));
+        assert_eq!(*events.lock().unwrap(), vec!["shutdown", "stopped"]);
+    }
+
+    #[test]
+    fn stopped_hook_runs_after_the_running_requests() {
+        let events = Events::default();
+        let (started, handler_started) = mpsc::channel();
+        let mut app = Server::new();
+        let handler_events = Arc::clone(&events);
+        app.get(String::from("/slow"), Box::new(move |_request, mut response| {
+            started.send(()).unwrap();
+            thread::sleep(Duration::from_millis(300));
+            record(&handler_events, "handled");
+            response.send();
+        }));
+        let hook_events = Arc::clone(&events);
+        app.on_shutdown(Box::new(move || record(&hook_events, "shutdown")));
+        let hook_events = Arc::clone(&events);
+        app.on_stopped(Box::new(move || record(&hook_events, "stopped")));
+        let handle = app.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+
+        let mut client = send(handle.local_addr(), "/slow");
+        handler_started.recv_timeout(Duration::from_secs(5)).unwrap();
+        handle.shutdown();
+        handle.join().unwrap();
+        assert!(read_all(&mut client).starts_with("HTTP/1.1 200"));
+        assert_eq!(*events.lock().unwrap(), vec!["shutdown", "handled", "stopped"]);
+    }
+
+    #[test]
+    fn shutdown_token_stops_the_server_from_another_thread() {
+        let mut app = Server::new();
+        app.set_signal_handling(false);
+        let token = app.shutdown_token();
+        let handle = app.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert!(!token.is_shutdown());
+        thread::spawn(move || token.shutdown()).join().unwrap();
+        let start = Instant::now();
+        handle.join().unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+    }
+
+    #[test]
+    fn listen_returns_immediately_after_the_shutdown() {
+        let mut app = Server::new();
+        let token = app.shutdown_token();
+        token.shutdown();
+        let start = Instant::now();
+        app.listen(0).unwrap();
+        assert!(start.elapsed() < Duration::from_secs(2));
+        assert!(token.is_shutdown());
     }
 }
\ No newline at end of file
@@ -44,6 +44,7 @@ impl Method {
 
     /// Return a Option<Method> from a string representation of the method.
     /// If the string is not a valid method, return None.
+    #[allow(clippy::should_implement_trait)]
     pub fn from_str(s: &str) -> Option<Method> {
         match s {
             "GET" => Some(Method::GET),
@@ -66,7 +67,7 @@ impl Method {
         match content {
             Some(s) => {
                 let el = s.split(" ").collect::<Vec<&str>>();
-                if let Some(s) = el.get(0) {
+                if let Some(s) = el.first() {
                     match Self::from_str(s) {
                         Some(method) => {
                             Ok((method, Self::parse_path(el.get(1))))
@@ -0,0 +1,122 @@
+use crate::request::Request;
+use crate::response::Response;
+use crate::IFn;
+use std::sync::Arc;
+
+/// Middleware trait, used to run code before (and around) the route handlers, see Server::middleware().
+///
+/// A middleware receives the request and the response and decide either to answer itself (by calling response.send())
+/// or to give them, possibly modified, to the rest of the chain with next.run().
+/// Code which must run just before the response is sent can be registered with Response::on_send().
+///
+/// Closures taking (Request, Response, Next) implement this trait.
+///
+/// ## Example:
+/// ```
+/// use rest_server::middleware::Next;
+/// use rest_server::request::Request;
+/// use rest_server::response::Response;
+/// use rest_server::Server;
+///
+/// let mut app = Server::new();
+/// app.middleware(Box::new(|request: Request, mut response: Response, next: Next| {
+///     response.set_header(String::from("X-Powered-By"), String::from("rest_server"));
+///     next.run(request, response);
+/// }));
+/// ```
+pub trait Middleware: Send + Sync {
+    fn handle(&self, request: Request, response: Response, next: Next);
+}
+
+impl<F> Middleware for F
+where
+    F: Fn(Request, Response, Next) + Send + Sync, {
+        fn handle(&self, request: Request, response: Response, next: Next) {
+            self(request, response, next)
+        }
+    }
+
+/// Next struct, the rest of the middleware chain followed by the route handler.
+pub struct Next<'a> {
+    middlewares: &'a [Arc<dyn Middleware>],
+    handler: &'a IFn,
+}
+
+impl<'a> Next<'a> {
+
+    pub(crate) fn new(middlewares: &'a [Arc<dyn Middleware>], handler: &'a IFn) -> Self {
+        Self {
+            middlewares,
+            handler,
+        }
+    }
+
+    /// Run the next middleware of the chain, or the route handler if all the middlewares have been run.
+    pub fn run(self, request: Request, response: Response) {
+        match self.middlewares.split_first() {
+            Some((middleware, rest)) => middleware.handle(request, response, Next::new(rest, self.handler)),
+            None => (self.handler)(request, response),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::testing;
+    use std::sync::Mutex;
+
+    /// Middleware recording its name when it runs.
+    fn recorder(name: &'static str, calls: &Arc<Mutex<Vec<&'static str>>>) -> Arc<dyn Middleware> {
+        let calls = Arc::clone(calls);
+        Arc::new(move |request: Request, response: Response, next: Next| {
+            calls.lock().unwrap().push(name);
+            next.run(request, response);
+        })
+    }
+
+    #[test]
+    fn run_middlewares_in_order_then_handler() {
+        let calls = Arc::new(Mutex::new(Vec::new()));
+        let chain = [recorder("first", &calls), recorder("second", &calls)];
+        let handler_calls = Arc::clone(&calls);
+        let handler = move |_request: Request, mut response: Response| {
+            handler_calls.lock().unwrap().push("handler");
+            response.send();
+        };
+        let (response, client) = testing::response();
+        Next::new(&chain, &handler).run(testing::request(Method::GET, "/", &[]), response);
+        assert!(testing::read_response(client).starts_with("HTTP/1.1 200 OK"));
+        assert_eq!(*calls.lock().unwrap(), vec!["first", "second", "handler"]);
+    }
+
+    #[test]
+    fn middleware_can_answer_without_handler() {
+        let deny: Arc<dyn Middleware> = Arc::new(|_request: Request, mut response: Response, _next: Next| {
+            response.set_status(crate::status::Status::Forbidden);
+            response.send();
+        });
+        let response = testing::run(deny, testing::request(Method::GET, "/", &[]));
+        assert_eq!(testing::status(&response), "HTTP/1.1 403 Forbidden");
+        assert!(!response.ends_with("handled"));
+    }
+
+    #[test]
+    fn middleware_can_modify_request_and_response() {
+        let middleware: Arc<dyn Middleware> = Arc::new(|mut request: Request, mut response: Response, next: Next| {
+            request.extensions_mut().insert(String::from("alice"));
+            response.set_header(String::from("X-Powered-By"), String::from("rest_server"));
+            next.run(request, response);
+        });
+        let handler = |request: Request, mut response: Response| {
+            response.set_body(request.extensions().get::<String>().unwrap());
+            response.send();
+        };
+        let (response, client) = testing::response();
+        Next::new(&[middleware], &handler).run(testing::request(Method::GET, "/", &[]), response);
+        let response = testing::read_response(client);
+        assert_eq!(testing::header(&response, "X-Powered-By"), Some("rest_server"));
+        assert!(response.ends_with("alice"));
+    }
+}
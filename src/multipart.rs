@@ -0,0 +1,738 @@
+use crate::header;
+use crate::status::Status;
+use std::collections::HashMap;
+use std::fmt::{Debug, Display, Formatter};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Cursor, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Size of the chunks read from the client.
+const CHUNK_SIZE: usize = 8 * 1024;
+
+/// Counter used to give a unique name to the temporary files created by the parser.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Limits applied when parsing a `multipart/form-data` body.
+///
+/// A request exceeding one of these limits is rejected with a MultipartError, see MultipartError::status() to know which status to send.
+#[derive(Debug, Clone)]
+pub struct MultipartLimits {
+    /// Maximum size in bytes of the whole body, default to 64 MiB.
+    pub max_total_size: u64,
+    /// Maximum number of parts (fields and files) in the body, default to 128.
+    pub max_parts: usize,
+    /// Maximum size in bytes of the headers of a part, default to 8 KiB.
+    pub max_header_size: usize,
+    /// Maximum size in bytes of a field which isn't a file, default to 64 KiB, fields are always kept in memory.
+    pub max_field_size: u64,
+    /// Maximum size in bytes of a file, default to 32 MiB.
+    pub max_file_size: u64,
+    /// Files bigger than this size in bytes are spooled to a temporary file instead of being kept in memory, default to 256 KiB.
+    pub memory_threshold: usize,
+    /// Directory where the temporary files are created, default to std::env::temp_dir().
+    pub temp_dir: Option<PathBuf>,
+}
+
+impl Default for MultipartLimits {
+    fn default() -> Self {
+        Self {
+            max_total_size: 64 * 1024 * 1024,
+            max_parts: 128,
+            max_header_size: 8 * 1024,
+            max_field_size: 64 * 1024,
+            max_file_size: 32 * 1024 * 1024,
+            memory_threshold: 256 * 1024,
+            temp_dir: None,
+        }
+    }
+}
+
+/// Streaming parser of a `multipart/form-data` body (see [RFC 7578](https://tools.ietf.org/html/rfc7578)).
+///
+/// The body is read from the client part by part when iterating, only the part being read is kept in memory
+/// (or spooled to a temporary file if it's a big file), so the whole body is never loaded at once.
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::multipart::MultipartLimits;
+/// use rest_server::request::Request;
+/// use rest_server::response::Response;
+///
+/// fn upload(request: Request, mut response: Response) {
+///     let multipart = request.multipart(MultipartLimits::default()).unwrap();
+///     for part in multipart {
+///         let part = part.unwrap();
+///         // never use part.filename in a path, the client can send "../../etc/passwd"
+///         match part.sanitized_filename() {
+///             Some(filename) => part.persist(format!("uploads/{}", filename)).unwrap(),
+///             None if part.is_file() => eprintln!("Invalid file name: {:?}", part.filename),
+///             None => println!("{} = {}", part.name, part.text().unwrap()),
+///         }
+///     }
+///     response.send();
+/// }
+/// ```
+pub struct Multipart {
+    reader: Box<dyn Read + Send>,
+    delimiter: Vec<u8>,
+    limits: MultipartLimits,
+    buffer: Vec<u8>,
+    read: u64,
+    parts: usize,
+    started: bool,
+    finished: bool,
+    eof: bool,
+}
+
+impl Multipart {
+
+    /// Create a new parser reading the body from reader, boundary is the boundary parameter of the Content-Type header.
+    pub fn new(reader: Box<dyn Read + Send>, boundary: &str, limits: MultipartLimits) -> Self {
+        Self {
+            reader,
+            delimiter: format!("\r\n--{}", boundary).into_bytes(),
+            limits,
+            // The first delimiter isn't preceded by a CRLF, adding one let us search every delimiter the same way
+            buffer: b"\r\n".to_vec(),
+            read: 0,
+            parts: 0,
+            started: false,
+            finished: false,
+            eof: false,
+        }
+    }
+
+    /// Create a new parser from a body already loaded in memory.
+    pub fn from_bytes(body: Vec<u8>, boundary: &str, limits: MultipartLimits) -> Self {
+        Self::new(Box::new(Cursor::new(body)), boundary, limits)
+    }
+
+    /// Read and return the next part of the body.
+    ///
+    /// Return None when all the parts have been read, or if a previous call returned an error.
+    pub fn next_part(&mut self) -> Option<Result<Part, MultipartError>> {
+        if self.finished {
+            return None;
+        }
+        let part = self.read_part();
+        match part {
+            Ok(Some(part)) => Some(Ok(part)),
+            Ok(None) => {
+                self.finished = true;
+                None
+            },
+            Err(e) => {
+                self.finished = true;
+                Some(Err(e))
+            },
+        }
+    }
+
+    fn read_part(&mut self) -> Result<Option<Part>, MultipartError> {
+        if !self.started {
+            self.skip_preamble()?;
+            self.started = true;
+        }
+        if !self.read_delimiter_end()? {
+            return Ok(None);
+        }
+        self.parts += 1;
+        if self.parts > self.limits.max_parts {
+            return Err(MultipartError::TooManyParts(self.limits.max_parts));
+        }
+        let headers = self.read_headers()?;
+        let disposition = headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Content-Disposition"))
+            .map(|(_, value)| parse_disposition(value))
+            .ok_or_else(|| MultipartError::InvalidPart(String::from("missing Content-Disposition header")))?;
+        let name = disposition.get("name").cloned()
+            .ok_or_else(|| MultipartError::InvalidPart(String::from("missing name in Content-Disposition header")))?;
+        let filename = disposition.get("filename*").or_else(|| disposition.get("filename")).cloned();
+        let content_type = headers.iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("Content-Type"))
+            .map(|(_, value)| value.clone());
+        let data = self.read_data(&name, filename.is_some())?;
+        Ok(Some(Part {
+            name,
+            filename,
+            content_type,
+            headers,
+            data,
+        }))
+    }
+
+    /// Read more data from the client, return false if the end of the body has been reached.
+    fn fill(&mut self) -> Result<bool, MultipartError> {
+        if self.eof {
+            return Ok(false);
+        }
+        let mut chunk = [0; CHUNK_SIZE];
+        loop {
+            match self.reader.read(&mut chunk) {
+                Ok(0) => {
+                    self.eof = true;
+                    return Ok(false);
+                },
+                Ok(size) => {
+                    self.read += size as u64;
+                    if self.read > self.limits.max_total_size {
+                        return Err(MultipartError::BodyTooLarge(self.limits.max_total_size));
+                    }
+                    self.buffer.extend_from_slice(&chunk[..size]);
+                    return Ok(true);
+                },
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(e) => return Err(MultipartError::Io(e)),
+            }
+        }
+    }
+
+    /// Discard everything before the first delimiter.
+    fn skip_preamble(&mut self) -> Result<(), MultipartError> {
+        loop {
+            if let Some(index) = find(&self.buffer, &self.delimiter) {
+                self.buffer.drain(..index + self.delimiter.len());
+                return Ok(());
+            }
+            let keep = self.delimiter.len() - 1;
+            if self.buffer.len() > keep {
+                self.buffer.drain(..self.buffer.len() - keep);
+            }
+            if !self.fill()? {
+                return Err(MultipartError::UnexpectedEof);
+            }
+        }
+    }
+
+    /// Read the end of the line of a delimiter, return false if this is the close delimiter (`--boundary--`).
+    fn read_delimiter_end(&mut self) -> Result<bool, MultipartError> {
+        while self.buffer.len() < 2 {
+            if !self.fill()? {
+                return Err(MultipartError::UnexpectedEof);
+            }
+        }
+        if self.buffer.starts_with(b"--") {
+            return Ok(false);
+        }
+        loop {
+            if let Some(index) = find(&self.buffer, b"\r\n") {
+                // Only linear whitespaces (transport padding) are allowed after a delimiter
+                if !self.buffer[..index].iter().all(|b| *b == b' ' || *b == b'\t') {
+                    return Err(MultipartError::InvalidPart(String::from("invalid characters after boundary")));
+                }
+                self.buffer.drain(..index + 2);
+                return Ok(true);
+            }
+            if self.buffer.len() > self.limits.max_header_size {
+                return Err(MultipartError::HeadersTooLarge(self.limits.max_header_size));
+            }
+            if !self.fill()? {
+                return Err(MultipartError::UnexpectedEof);
+            }
+        }
+    }
+
+    fn read_headers(&mut self) -> Result<HashMap<String, String>, MultipartError> {
+        loop {
+            if self.buffer.starts_with(b"\r\n") {
+                // No header in this part
+                self.buffer.drain(..2);
+                return Ok(HashMap::new());
+            }
+            if let Some(index) = find(&self.buffer, b"\r\n\r\n") {
+                let headers = String::from_utf8_lossy(&self.buffer[..index])
+                    .split("\r\n")
+                    .filter_map(|line| line.split_once(':'))
+                    .map(|(key, value)| (key.trim().to_string(), value.trim().to_string()))
+                    .collect::<HashMap<String, String>>();
+                self.buffer.drain(..index + 4);
+                return Ok(headers);
+            }
+            if self.buffer.len() > self.limits.max_header_size {
+                return Err(MultipartError::HeadersTooLarge(self.limits.max_header_size));
+            }
+            if !self.fill()? {
+                return Err(MultipartError::UnexpectedEof);
+            }
+        }
+    }
+
+    /// Read the content of a part until the next delimiter, spooling it to a temporary file if it's a big file.
+    fn read_data(&mut self, name: &str, is_file: bool) -> Result<PartData, MultipartError> {
+        let limit = if is_file { self.limits.max_file_size } else { self.limits.max_field_size };
+        let mut data = PartData::Memory(Vec::new());
+        let mut size: u64 = 0;
+        loop {
+            let (end, found) = match find(&self.buffer, &self.delimiter) {
+                Some(index) => (index, true),
+                None => (self.buffer.len().saturating_sub(self.delimiter.len() - 1), false),
+            };
+            size += end as u64;
+            if size > limit {
+                return Err(MultipartError::PartTooLarge {
+                    name: name.to_string(),
+                    limit,
+                });
+            }
+            data.write_all(&self.buffer[..end])?;
+            if is_file {
+                if let PartData::Memory(bytes) = &data {
+                    if bytes.len() > self.limits.memory_threshold {
+                        data = PartData::spool(bytes, self.limits.temp_dir.as_deref())?;
+                    }
+                }
+            }
+            if found {
+                self.buffer.drain(..end + self.delimiter.len());
+                data.flush()?;
+                return Ok(data);
+            }
+            self.buffer.drain(..end);
+            if !self.fill()? {
+                return Err(MultipartError::UnexpectedEof);
+            }
+        }
+    }
+}
+
+impl Iterator for Multipart {
+    type Item = Result<Part, MultipartError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.next_part()
+    }
+}
+
+impl Debug for Multipart {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Multipart")
+            .field("limits", &self.limits)
+            .field("parts", &self.parts)
+            .field("finished", &self.finished)
+            .finish()
+    }
+}
+
+/// A part of a `multipart/form-data` body, either a simple field or a file.
+#[derive(Debug)]
+pub struct Part {
+    /// Name of the form field.
+    pub name: String,
+    /// Name of the file sent by the client, None if the part isn't a file.
+    pub filename: Option<String>,
+    /// Content-Type of the part, None if the client didn't send it.
+    pub content_type: Option<String>,
+    /// Every headers of the part.
+    pub headers: HashMap<String, String>,
+    data: PartData,
+}
+
+impl Part {
+
+    /// Return true if the part is a file.
+    pub fn is_file(&self) -> bool {
+        self.filename.is_some()
+    }
+
+    /// Return the name of the file without its directories, so it can be used in a path.
+    ///
+    /// Browsers only send the name of the file, but other clients can send a path (`C:\Users\me\photo.png`, `../../etc/passwd`),
+    /// only its last component is kept. Return None if the part isn't a file or if nothing usable is left
+    /// (empty name, `..`, control characters).
+    pub fn sanitized_filename(&self) -> Option<String> {
+        let filename = self.filename.as_deref()?;
+        let name = filename.rsplit(['/', '\\']).next().unwrap_or("").trim();
+        if name.is_empty() || name == "." || name == ".." || name.chars().any(char::is_control) {
+            return None;
+        }
+        Some(name.to_string())
+    }
+
+    /// Return the size of the content in bytes.
+    pub fn size(&self) -> u64 {
+        match &self.data {
+            PartData::Memory(bytes) => bytes.len() as u64,
+            PartData::File(file) => file.size,
+        }
+    }
+
+    /// Return true if the content has been spooled to a temporary file.
+    pub fn is_spooled(&self) -> bool {
+        matches!(self.data, PartData::File(_))
+    }
+
+    /// Return the path of the temporary file if the content has been spooled, otherwise return None.
+    ///
+    /// The temporary file is removed when the part is dropped, use persist() to keep it.
+    pub fn path(&self) -> Option<&Path> {
+        match &self.data {
+            PartData::Memory(_) => None,
+            PartData::File(file) => Some(&file.path),
+        }
+    }
+
+    /// Return a reader over the content of the part.
+    pub fn reader(&self) -> io::Result<Box<dyn Read + '_>> {
+        match &self.data {
+            PartData::Memory(bytes) => Ok(Box::new(bytes.as_slice())),
+            PartData::File(file) => Ok(Box::new(File::open(&file.path)?)),
+        }
+    }
+
+    /// Return the content of the part, read from the temporary file if it has been spooled.
+    pub fn bytes(&self) -> io::Result<Vec<u8>> {
+        match &self.data {
+            PartData::Memory(bytes) => Ok(bytes.clone()),
+            PartData::File(file) => fs::read(&file.path),
+        }
+    }
+
+    /// Return the content of the part as a String.
+    ///
+    /// Return Err if the content isn't valid UTF-8.
+    pub fn text(&self) -> io::Result<String> {
+        String::from_utf8(self.bytes()?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// Write the content of the part to the given path.
+    ///
+    /// If the content has been spooled, the temporary file is moved instead of being copied when possible.
+    pub fn persist<P: AsRef<Path>>(self, destination: P) -> io::Result<()> {
+        match self.data {
+            PartData::Memory(bytes) => fs::write(destination, bytes),
+            PartData::File(file) => file.persist(destination.as_ref()),
+        }
+    }
+}
+
+/// Content of a part, in memory or in a temporary file.
+#[derive(Debug)]
+enum PartData {
+    Memory(Vec<u8>),
+    File(TempFile),
+}
+
+impl PartData {
+
+    /// Move the content to a new temporary file.
+    fn spool(bytes: &[u8], directory: Option<&Path>) -> io::Result<Self> {
+        let mut file = TempFile::create(directory)?;
+        file.write_all(bytes)?;
+        Ok(PartData::File(file))
+    }
+}
+
+impl Write for PartData {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            PartData::Memory(bytes) => bytes.write(buf),
+            PartData::File(file) => file.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            PartData::Memory(_) => Ok(()),
+            PartData::File(file) => file.flush(),
+        }
+    }
+}
+
+/// Temporary file removed when dropped.
+#[derive(Debug)]
+struct TempFile {
+    path: PathBuf,
+    file: Option<File>,
+    size: u64,
+}
+
+impl TempFile {
+
+    fn create(directory: Option<&Path>) -> io::Result<Self> {
+        let directory = directory.map(Path::to_path_buf).unwrap_or_else(std::env::temp_dir);
+        let nanos = SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.subsec_nanos()).unwrap_or(0);
+        let path = directory.join(format!(
+            "rest_server-upload-{}-{}-{}",
+            std::process::id(),
+            TEMP_FILE_COUNTER.fetch_add(1, Ordering::SeqCst),
+            nanos
+        ));
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        // the uploads can contain private data, only the server can read them
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        let file = options.open(&path)?;
+        Ok(Self {
+            path,
+            file: Some(file),
+            size: 0,
+        })
+    }
+
+    fn persist(mut self, destination: &Path) -> io::Result<()> {
+        drop(self.file.take());
+        if fs::rename(&self.path, destination).is_err() {
+            // rename doesn't work across file systems
+            fs::copy(&self.path, destination)?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for TempFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        let size = self.file.as_mut().ok_or(io::ErrorKind::NotConnected)?.write(buf)?;
+        self.size += size as u64;
+        Ok(size)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.as_mut().ok_or(io::ErrorKind::NotConnected)?.flush()
+    }
+}
+
+impl Drop for TempFile {
+    fn drop(&mut self) {
+        drop(self.file.take());
+        let _ = fs::remove_file(&self.path);
+    }
+}
+
+/// Error returned when a `multipart/form-data` body can't be parsed.
+#[derive(Debug)]
+pub enum MultipartError {
+    /// The request content-type isn't `multipart/form-data`.
+    UnsupportedContentType(String),
+    /// The Content-Type header doesn't have a boundary parameter.
+    MissingBoundary,
+    /// The body is bigger than allowed.
+    BodyTooLarge(u64),
+    /// The body contains more parts than allowed.
+    TooManyParts(usize),
+    /// The headers of a part are bigger than allowed.
+    HeadersTooLarge(usize),
+    /// The content of a part is bigger than allowed.
+    PartTooLarge {
+        name: String,
+        limit: u64,
+    },
+    /// A part is malformed.
+    InvalidPart(String),
+    /// The body ended before the close delimiter.
+    UnexpectedEof,
+    /// Error while reading the body or writing a temporary file.
+    Io(io::Error),
+}
+
+impl MultipartError {
+
+    /// Return the status which should be sent to the client for this error.
+    pub fn status(&self) -> Status {
+        match self {
+            MultipartError::UnsupportedContentType(_) => Status::UnsupportedMediaType,
+            MultipartError::BodyTooLarge(_) | MultipartError::TooManyParts(_) | MultipartError::HeadersTooLarge(_) | MultipartError::PartTooLarge { .. } => Status::PayloadTooLarge,
+            MultipartError::MissingBoundary | MultipartError::InvalidPart(_) | MultipartError::UnexpectedEof => Status::BadRequest,
+            MultipartError::Io(_) => Status::InternalServerError,
+        }
+    }
+}
+
+impl Display for MultipartError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MultipartError::UnsupportedContentType(content_type) => write!(f, "Unsupported content-type: {}", content_type),
+            MultipartError::MissingBoundary => write!(f, "Missing boundary in Content-Type header"),
+            MultipartError::BodyTooLarge(limit) => write!(f, "Body too large, limit is {} bytes", limit),
+            MultipartError::TooManyParts(limit) => write!(f, "Too many parts, limit is {}", limit),
+            MultipartError::HeadersTooLarge(limit) => write!(f, "Part headers too large, limit is {} bytes", limit),
+            MultipartError::PartTooLarge { name, limit } => write!(f, "Part {} too large, limit is {} bytes", name, limit),
+            MultipartError::InvalidPart(reason) => write!(f, "Invalid part: {}", reason),
+            MultipartError::UnexpectedEof => write!(f, "Unexpected end of body"),
+            MultipartError::Io(e) => write!(f, "IO error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for MultipartError {}
+
+impl From<io::Error> for MultipartError {
+    fn from(e: io::Error) -> Self {
+        MultipartError::Io(e)
+    }
+}
+
+/// Return the index of the first occurrence of needle in haystack.
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack.windows(needle.len()).position(|window| window == needle)
+}
+
+/// Parse the parameters of a Content-Disposition header, parameter names are returned in lowercase.
+///
+/// Quoted values are unescaped and extended values (`filename*=UTF-8''na%C3%AFve.txt`, see [RFC 5987](https://tools.ietf.org/html/rfc5987)) are decoded.
+fn parse_disposition(value: &str) -> HashMap<String, String> {
+    let mut params = HashMap::new();
+    let mut chars = value.chars().peekable();
+    // Skip the disposition type (form-data)
+    for c in chars.by_ref() {
+        if c == ';' {
+            break;
+        }
+    }
+    loop {
+        let mut key = String::new();
+        for c in chars.by_ref() {
+            if c == '=' {
+                break;
+            }
+            key.push(c);
+        }
+        let key = key.trim().to_ascii_lowercase();
+        if key.is_empty() {
+            break;
+        }
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let mut value = String::new();
+        if chars.peek() == Some(&'"') {
+            chars.next();
+            while let Some(c) = chars.next() {
+                match c {
+                    '\\' => {
+                        if let Some(escaped) = chars.next() {
+                            value.push(escaped);
+                        }
+                    },
+                    '"' => break,
+                    c => value.push(c),
+                }
+            }
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+            }
+        } else {
+            for c in chars.by_ref() {
+                if c == ';' {
+                    break;
+                }
+                value.push(c);
+            }
+            value = value.trim().to_string();
+        }
+        if key.ends_with('*') {
+            if let Some(decoded) = header::decode_ext_value(&value) {
+                value = decoded;
+            }
+        }
+        params.insert(key, value);
+    }
+    params
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn body(parts: &[(&str, Option<&str>, &[u8])]) -> Vec<u8> {
+        let mut body = Vec::new();
+        for (name, filename, content) in parts {
+            body.extend_from_slice(b"--XyZ\r\n");
+            match filename {
+                Some(filename) => body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"; filename=\"{}\"\r\n\r\n", name, filename).as_bytes()),
+                None => body.extend_from_slice(format!("Content-Disposition: form-data; name=\"{}\"\r\n\r\n", name).as_bytes()),
+            }
+            body.extend_from_slice(content);
+            body.extend_from_slice(b"\r\n");
+        }
+        body.extend_from_slice(b"--XyZ--\r\n");
+        body
+    }
+
+    #[test]
+    fn parse_fields_and_files() {
+        let body = body(&[("title", None, b"Hello"), ("file", Some("notes.txt"), b"line 1\r\nline 2")]);
+        let parts = Multipart::from_bytes(body, "XyZ", MultipartLimits::default()).collect::<Result<Vec<Part>, MultipartError>>().unwrap();
+        assert_eq!(parts.len(), 2);
+        assert_eq!(parts[0].name, "title");
+        assert_eq!(parts[0].text().unwrap(), "Hello");
+        assert!(!parts[0].is_file());
+        assert_eq!(parts[1].filename.as_deref(), Some("notes.txt"));
+        assert_eq!(parts[1].bytes().unwrap(), b"line 1\r\nline 2");
+    }
+
+    #[test]
+    fn reject_body_bigger_than_total_limit() {
+        let content = vec![b'a'; 40 * 1024];
+        let body = body(&[("a", Some("a.bin"), &content), ("b", Some("b.bin"), &content)]);
+        let limits = MultipartLimits {
+            max_total_size: 64 * 1024,
+            ..MultipartLimits::default()
+        };
+        let mut multipart = Multipart::from_bytes(body, "XyZ", limits);
+        assert!(multipart.next_part().unwrap().is_ok());
+        let error = multipart.next_part().unwrap().unwrap_err();
+        assert!(matches!(error, MultipartError::BodyTooLarge(limit) if limit == 64 * 1024));
+        assert_eq!(error.status(), Status::PayloadTooLarge);
+        assert!(multipart.next_part().is_none());
+    }
+
+    #[test]
+    fn sanitize_filename() {
+        let filenames = [
+            ("photo.png", Some("photo.png")),
+            ("../../etc/passwd", Some("passwd")),
+            // backslashes are escaped in a quoted value
+            ("C:\\\\Users\\\\me\\\\report.pdf", Some("report.pdf")),
+            ("uploads/..", None),
+            ("dir/", None),
+            ("a\u{0}b", None),
+            ("", None),
+        ];
+        for (filename, expected) in filenames {
+            let body = body(&[("file", Some(filename), b"x")]);
+            let part = Multipart::from_bytes(body, "XyZ", MultipartLimits::default()).next().unwrap().unwrap();
+            assert_eq!(part.sanitized_filename().as_deref(), expected, "{:?}", filename);
+        }
+        let body = body(&[("field", None, b"x")]);
+        let part = Multipart::from_bytes(body, "XyZ", MultipartLimits::default()).next().unwrap().unwrap();
+        assert_eq!(part.sanitized_filename(), None);
+    }
+
+    #[test]
+    fn spool_big_files() {
+        let content = vec![b'z'; 4096];
+        let body = body(&[("file", Some("big.bin"), &content)]);
+        let limits = MultipartLimits {
+            memory_threshold: 1024,
+            ..MultipartLimits::default()
+        };
+        let part = Multipart::from_bytes(body, "XyZ", limits).next().unwrap().unwrap();
+        assert!(part.is_spooled());
+        assert_eq!(part.size(), 4096);
+        let path = part.path().unwrap().to_path_buf();
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            assert_eq!(fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+        }
+        assert_eq!(part.bytes().unwrap(), content);
+        drop(part);
+        assert!(!path.exists());
+    }
+
+    #[test]
+    fn reject_file_bigger_than_limit() {
+        let body = body(&[("file", Some("a.bin"), &[b'a'; 100])]);
+        let limits = MultipartLimits {
+            max_file_size: 10,
+            ..MultipartLimits::default()
+        };
+        let error = Multipart::from_bytes(body, "XyZ", limits).next().unwrap().unwrap_err();
+        assert!(matches!(error, MultipartError::PartTooLarge { limit: 10, .. }));
+    }
+}
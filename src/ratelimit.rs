@@ -0,0 +1,406 @@
+use crate::ipfilter::ClientIp;
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+use crate::status::Status;
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+type KeyExtractor = dyn Fn(&Request) -> Option<String> + Send + Sync;
+
+/// Algorithm used to limit the requests of a client.
+#[derive(Debug, Clone, Copy)]
+pub enum RateLimit {
+    /// Token bucket: a client can make up to capacity requests in a burst, then the bucket is refilled
+    /// at a rate of capacity tokens per period.
+    TokenBucket {
+        capacity: u32,
+        period: Duration,
+    },
+    /// Sliding window: a client can make up to limit requests in any window,
+    /// approximated from the number of requests of the current and the previous fixed windows.
+    SlidingWindow {
+        limit: u32,
+        window: Duration,
+    },
+}
+
+impl RateLimit {
+
+    /// Return the maximum number of requests, sent in the RateLimit-Limit header.
+    fn limit(&self) -> u32 {
+        match self {
+            RateLimit::TokenBucket { capacity, .. } => *capacity,
+            RateLimit::SlidingWindow { limit, .. } => *limit,
+        }
+    }
+
+    /// Return how long a key must stay unused before it can be forgotten without changing the result of the next request.
+    fn idle_timeout(&self) -> Duration {
+        match self {
+            RateLimit::TokenBucket { period, .. } => *period,
+            RateLimit::SlidingWindow { window, .. } => *window * 2,
+        }
+    }
+}
+
+/// Key identifying the clients.
+pub enum RateLimitKey {
+    /// The address of the client (without the port), the ClientIp added by an IpFilter middleware is used if there is one.
+    PeerAddr,
+    /// The value of a header, an API key for example.
+    Header(String),
+    /// A custom function, the requests for which it returns None aren't limited.
+    Custom(Box<KeyExtractor>),
+}
+
+impl RateLimitKey {
+
+    /// Return the key of the request, return None if the request shouldn't be limited.
+    fn extract(&self, request: &Request) -> Option<String> {
+        match self {
+            RateLimitKey::PeerAddr => match request.extensions().get::<ClientIp>() {
+                Some(client) => Some(client.ip().to_string()),
+                None => request.connection().peer_addr.map(|addr| addr.ip().to_string()),
+            },
+            RateLimitKey::Header(name) => request.get_header(name).map(String::from),
+            RateLimitKey::Custom(extractor) => extractor(request),
+        }
+    }
+}
+
+/// State of a key.
+enum State {
+    Bucket {
+        tokens: f64,
+        updated: Instant,
+    },
+    Window {
+        start: Instant,
+        current: u32,
+        previous: u32,
+    },
+}
+
+/// Result of a request for a key.
+struct Decision {
+    allowed: bool,
+    remaining: u32,
+    /// Time until the limit is fully restored.
+    reset: Duration,
+    /// Time until the next request is allowed.
+    retry_after: Duration,
+}
+
+/// Middleware limiting the number of requests per client, the other requests are rejected with 429 Too Many Requests
+/// and a Retry-After header.
+///
+/// The responses get the RateLimit-Limit, RateLimit-Remaining and RateLimit-Reset headers
+/// (see the [IETF draft](https://datatracker.ietf.org/doc/draft-ietf-httpapi-ratelimit-headers/)).
+/// The state of the clients is kept in memory, the keys unused for a while are removed every sweep interval (1 minute by default).
+///
+/// ## Example:
+/// ```
+/// use rest_server::ratelimit::{RateLimit, RateLimitKey, RateLimiter};
+/// use rest_server::Server;
+/// use std::time::Duration;
+///
+/// let mut app = Server::new();
+/// // bursts of 20 requests, then 20 requests per minute per IP address
+/// app.middleware(Box::new(RateLimiter::new(RateLimit::TokenBucket { capacity: 20, period: Duration::from_secs(60) }, RateLimitKey::PeerAddr)));
+/// // 1000 requests per hour per API key
+/// app.middleware_at(String::from("/api"), Box::new(RateLimiter::new(
+///     RateLimit::SlidingWindow { limit: 1000, window: Duration::from_secs(3600) },
+///     RateLimitKey::Header(String::from("X-Api-Key")),
+/// )));
+/// ```
+pub struct RateLimiter {
+    limit: RateLimit,
+    key: RateLimitKey,
+    sweep_interval: Duration,
+    states: Mutex<(HashMap<String, State>, Instant)>,
+}
+
+impl RateLimiter {
+
+    /// Create a new RateLimiter.
+    ///
+    /// Panic if the capacity, the limit, the period or the window is zero.
+    pub fn new(limit: RateLimit, key: RateLimitKey) -> Self {
+        match limit {
+            RateLimit::TokenBucket { capacity, period } => assert!(capacity > 0 && !period.is_zero(), "RateLimit::TokenBucket: the capacity and the period must be greater than zero"),
+            RateLimit::SlidingWindow { limit, window } => assert!(limit > 0 && !window.is_zero(), "RateLimit::SlidingWindow: the limit and the window must be greater than zero"),
+        }
+        Self {
+            limit,
+            key,
+            sweep_interval: Duration::from_secs(60),
+            states: Mutex::new((HashMap::new(), Instant::now())),
+        }
+    }
+
+    /// Set how often the unused keys are removed.
+    pub fn sweep_interval(mut self, sweep_interval: Duration) -> Self {
+        self.sweep_interval = sweep_interval;
+        self
+    }
+
+    /// Return the number of keys kept in memory.
+    pub fn len(&self) -> usize {
+        self.states.lock().unwrap().0.len()
+    }
+
+    /// Return true if no key is kept in memory.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Count a request for the key received at the given time.
+    fn check(&self, key: String, now: Instant) -> Decision {
+        let mut guard = self.states.lock().unwrap();
+        let (states, last_sweep) = &mut *guard;
+        if now.duration_since(*last_sweep) >= self.sweep_interval {
+            let idle_timeout = self.limit.idle_timeout();
+            states.retain(|_, state| {
+                let last_use = match state {
+                    State::Bucket { updated, .. } => *updated,
+                    State::Window { start, .. } => *start,
+                };
+                now.duration_since(last_use) < idle_timeout
+            });
+            *last_sweep = now;
+        }
+        match self.limit {
+            RateLimit::TokenBucket { capacity, period } => {
+                let state = states.entry(key).or_insert(State::Bucket {
+                    tokens: capacity as f64,
+                    updated: now,
+                });
+                let State::Bucket { tokens, updated } = state else { unreachable!() };
+                let rate = capacity as f64 / period.as_secs_f64();
+                *tokens = (*tokens + now.duration_since(*updated).as_secs_f64() * rate).min(capacity as f64);
+                *updated = now;
+                let allowed = *tokens >= 1.0;
+                if allowed {
+                    *tokens -= 1.0;
+                }
+                Decision {
+                    allowed,
+                    remaining: tokens.floor() as u32,
+                    reset: Duration::from_secs_f64((capacity as f64 - *tokens) / rate),
+                    retry_after: Duration::from_secs_f64(((1.0 - *tokens) / rate).max(0.0)),
+                }
+            },
+            RateLimit::SlidingWindow { limit, window } => {
+                let state = states.entry(key).or_insert(State::Window {
+                    start: now,
+                    current: 0,
+                    previous: 0,
+                });
+                let State::Window { start, current, previous } = state else { unreachable!() };
+                let elapsed = now.duration_since(*start);
+                if elapsed >= window {
+                    let windows = elapsed.as_nanos() / window.as_nanos();
+                    *previous = if windows == 1 { *current } else { 0 };
+                    *current = 0;
+                    *start = now - Duration::from_nanos((elapsed.as_nanos() % window.as_nanos()) as u64);
+                }
+                let elapsed = now.duration_since(*start);
+                let window_secs = window.as_secs_f64();
+                let weight = 1.0 - elapsed.as_secs_f64() / window_secs;
+                let estimated = *previous as f64 * weight + *current as f64;
+                let allowed = estimated + 1.0 <= limit as f64;
+                if allowed {
+                    *current += 1;
+                }
+                let estimated = *previous as f64 * weight + *current as f64;
+                let retry_after = if allowed {
+                    Duration::ZERO
+                } else if *current >= limit {
+                    // the current window alone is full, wait until its weight in the next window is low enough
+                    let ratio = if *current == 0 { 1.0 } else { 1.0 - limit.saturating_sub(1) as f64 / *current as f64 };
+                    window - elapsed + window.mul_f64(ratio.max(0.0))
+                } else {
+                    // wait until the weight of the previous window is low enough
+                    let weight = (limit - *current - 1) as f64 / *previous as f64;
+                    Duration::from_secs_f64((window_secs * (1.0 - weight) - elapsed.as_secs_f64()).max(0.0))
+                };
+                Decision {
+                    allowed,
+                    remaining: (limit as f64 - estimated).max(0.0).floor() as u32,
+                    reset: window - elapsed + if *current > 0 { window } else { Duration::ZERO },
+                    retry_after,
+                }
+            },
+        }
+    }
+}
+
+impl Middleware for RateLimiter {
+    fn handle(&self, request: Request, mut response: Response, next: Next) {
+        let key = match self.key.extract(&request) {
+            Some(key) => key,
+            None => return next.run(request, response),
+        };
+        let decision = self.check(key, Instant::now());
+        response.set_header(String::from("RateLimit-Limit"), self.limit.limit().to_string());
+        response.set_header(String::from("RateLimit-Remaining"), decision.remaining.to_string());
+        response.set_header(String::from("RateLimit-Reset"), ceil_secs(decision.reset).to_string());
+        if decision.allowed {
+            next.run(request, response);
+        } else {
+            response.set_status(Status::TooManyRequests);
+            response.set_header(String::from("Retry-After"), ceil_secs(decision.retry_after).max(1).to_string());
+            response.set_header(String::from("Content-Type"), String::from("text/plain; charset=utf-8"));
+            response.set_body(Status::TooManyRequests.as_str());
+            response.send();
+        }
+    }
+}
+
+/// Return the duration in seconds, rounded up.
+fn ceil_secs(duration: Duration) -> u64 {
+    duration.as_secs() + u64::from(duration.subsec_nanos() > 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::testing;
+    use std::sync::Arc;
+
+    fn limiter(limit: RateLimit) -> RateLimiter {
+        RateLimiter::new(limit, RateLimitKey::PeerAddr)
+    }
+
+    fn secs(secs: f64) -> Duration {
+        Duration::from_secs_f64(secs)
+    }
+
+    #[test]
+    fn token_bucket() {
+        let limiter = limiter(RateLimit::TokenBucket { capacity: 3, period: Duration::from_secs(3) });
+        let start = Instant::now();
+        for remaining in [2, 1, 0] {
+            let decision = limiter.check(String::from("a"), start);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+            assert_eq!(decision.reset, Duration::from_secs(3 - remaining as u64));
+        }
+        let decision = limiter.check(String::from("a"), start);
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, Duration::from_secs(1));
+        assert_eq!(decision.reset, Duration::from_secs(3));
+
+        // one token per second is refilled, other keys have their own bucket
+        assert!(limiter.check(String::from("a"), start + secs(1.0)).allowed);
+        assert!(!limiter.check(String::from("a"), start + secs(1.5)).allowed);
+        assert!(limiter.check(String::from("b"), start + secs(1.5)).allowed);
+        // the bucket never holds more than its capacity
+        let decision = limiter.check(String::from("a"), start + secs(100.0));
+        assert_eq!(decision.remaining, 2);
+    }
+
+    #[test]
+    fn sliding_window() {
+        let limiter = limiter(RateLimit::SlidingWindow { limit: 4, window: Duration::from_secs(10) });
+        let start = Instant::now();
+        for remaining in [3, 2, 1, 0] {
+            let decision = limiter.check(String::from("a"), start);
+            assert!(decision.allowed);
+            assert_eq!(decision.remaining, remaining);
+        }
+        let decision = limiter.check(String::from("a"), start);
+        assert!(!decision.allowed);
+        // the 4 requests weigh 3 after a quarter of the next window
+        assert_eq!(decision.retry_after, secs(12.5));
+        assert_eq!(decision.reset, Duration::from_secs(20));
+        assert!(!limiter.check(String::from("a"), start + secs(12.0)).allowed);
+        assert!(limiter.check(String::from("a"), start + secs(12.5)).allowed);
+
+        // halfway through the second window the previous one weighs 2
+        let limiter = self::limiter(RateLimit::SlidingWindow { limit: 4, window: Duration::from_secs(10) });
+        for _ in 0..4 {
+            limiter.check(String::from("a"), start);
+        }
+        assert!(limiter.check(String::from("a"), start + secs(15.0)).allowed);
+        assert!(limiter.check(String::from("a"), start + secs(15.0)).allowed);
+        let decision = limiter.check(String::from("a"), start + secs(15.0));
+        assert!(!decision.allowed);
+        assert_eq!(decision.retry_after, secs(2.5));
+
+        // after more than one window the previous requests don't count anymore
+        let decision = limiter.check(String::from("a"), start + secs(31.0));
+        assert!(decision.allowed);
+        assert_eq!(decision.remaining, 3);
+    }
+
+    #[test]
+    fn forget_idle_keys() {
+        let limiter = limiter(RateLimit::TokenBucket { capacity: 1, period: Duration::from_secs(10) }).sweep_interval(Duration::ZERO);
+        let start = Instant::now();
+        limiter.check(String::from("a"), start);
+        limiter.check(String::from("b"), start + secs(5.0));
+        assert_eq!(limiter.len(), 2);
+        limiter.check(String::from("c"), start + secs(12.0));
+        assert_eq!(limiter.len(), 2);
+    }
+
+    #[test]
+    #[should_panic(expected = "must be greater than zero")]
+    fn reject_zero_capacity() {
+        limiter(RateLimit::TokenBucket { capacity: 0, period: Duration::from_secs(1) });
+    }
+
+    #[test]
+    #[should_panic(expected = "must be greater than zero")]
+    fn reject_zero_period() {
+        limiter(RateLimit::TokenBucket { capacity: 1, period: Duration::ZERO });
+    }
+
+    #[test]
+    #[should_panic(expected = "must be greater than zero")]
+    fn reject_zero_limit() {
+        limiter(RateLimit::SlidingWindow { limit: 0, window: Duration::from_secs(1) });
+    }
+
+    #[test]
+    #[should_panic(expected = "must be greater than zero")]
+    fn reject_zero_window() {
+        limiter(RateLimit::SlidingWindow { limit: 1, window: Duration::ZERO });
+    }
+
+    #[test]
+    fn send_headers() {
+        let middleware: Arc<dyn Middleware> = Arc::new(RateLimiter::new(
+            RateLimit::TokenBucket { capacity: 1, period: Duration::from_secs(60) },
+            RateLimitKey::Header(String::from("X-Api-Key")),
+        ));
+        let request = || testing::request(Method::GET, "/", &[("X-Api-Key", "key")]);
+        let response = testing::run(Arc::clone(&middleware), request());
+        assert!(response.ends_with("handled"));
+        assert_eq!(testing::header(&response, "RateLimit-Limit"), Some("1"));
+        assert_eq!(testing::header(&response, "RateLimit-Remaining"), Some("0"));
+        assert_eq!(testing::header(&response, "RateLimit-Reset"), Some("60"));
+
+        let response = testing::run(Arc::clone(&middleware), request());
+        assert_eq!(testing::status(&response), "HTTP/1.1 429 Too Many Requests");
+        assert_eq!(testing::header(&response, "RateLimit-Remaining"), Some("0"));
+        assert_eq!(testing::header(&response, "Retry-After"), Some("60"));
+
+        // the requests without a key aren't limited
+        let response = testing::run(middleware, testing::request(Method::GET, "/", &[]));
+        assert!(response.ends_with("handled"));
+        assert_eq!(testing::header(&response, "RateLimit-Limit"), None);
+    }
+
+    #[test]
+    fn round_up_seconds() {
+        assert_eq!(ceil_secs(Duration::ZERO), 0);
+        assert_eq!(ceil_secs(Duration::from_millis(1)), 1);
+        assert_eq!(ceil_secs(Duration::from_secs(2)), 2);
+        assert_eq!(ceil_secs(Duration::from_millis(2001)), 3);
+    }
+}
@@ -1,15 +1,34 @@
 use crate::Method;
+use crate::form::{Charset, Form, FormError, FromForm};
+use crate::connection::ConnectionInfo;
+use crate::cookie::{CookieJar, Keyring};
+use crate::extensions::Extensions;
+use crate::multipart::{Multipart, MultipartError, MultipartLimits};
+#[cfg(feature = "json")]
+use crate::json::JsonError;
 use std::collections::HashMap;
 use core::fmt::Display;
+use std::fmt::{Debug, Formatter};
 use std::hash::{Hash, Hasher};
+use std::io::{Cursor, Read};
+use std::sync::{Arc, Mutex, OnceLock};
 
 /// Request struct, used to represent a HTTP request send to the server.
+/// 
+/// The body isn't read from the client until get_body(), form() or multipart() is called.
 #[derive(Debug)]
 pub struct Request {
     pub method: Method,
     pub path: RequestPath,
     pub headers: HashMap<String, String>,
-    body: String,
+    /// Every header line as received, in order, including the repeated headers.
+    header_lines: Vec<(String, String)>,
+    body: OnceLock<Vec<u8>>,
+    body_reader: Mutex<Option<BodyReader>>,
+    max_body_size: u64,
+    keyring: Option<Arc<Keyring>>,
+    connection: ConnectionInfo,
+    extensions: Extensions,
 }
 
 impl Request {
@@ -17,59 +36,260 @@ impl Request {
     /// Create a new Request struct.
     /// Headers are parsed in parse_headers() (private method) method before return the Request object.
     pub fn new(method: Method, path: RequestPath, body: String) -> Self {
-        let headers = Self::parse_header(body.as_str());
+        let header_lines = Self::parse_header_lines(body.as_str());
         let body = body.split_once("\r\n\r\n").unwrap_or(("", "")).1.to_string().replace("\0", "");
         Self {
             method,
             path,
-            headers,
-            body,
+            headers: header_lines.iter().cloned().collect(),
+            header_lines,
+            body: OnceLock::from(body.into_bytes()),
+            body_reader: Mutex::new(None),
+            max_body_size: u64::MAX,
+            keyring: None,
+            connection: ConnectionInfo::default(),
+            extensions: Extensions::new(),
         }
     }
 
+    /// Create a new Request struct from the head of the request (request line and headers).
+    /// The body is empty until a reader is given with set_body_reader().
+    pub(crate) fn from_head(method: Method, path: RequestPath, head: &str) -> Self {
+        let header_lines = Self::parse_header_lines(head);
+        Self {
+            method,
+            path,
+            headers: header_lines.iter().cloned().collect(),
+            header_lines,
+            body: OnceLock::new(),
+            body_reader: Mutex::new(None),
+            max_body_size: u64::MAX,
+            keyring: None,
+            connection: ConnectionInfo::default(),
+            extensions: Extensions::new(),
+        }
+    }
+
+    /// Return the data attached to the request by the middlewares (session, authenticated user, etc.).
+    pub fn extensions(&self) -> &Extensions {
+        &self.extensions
+    }
+
+    /// Return the data attached to the request, used by the middlewares to add their data.
+    pub fn extensions_mut(&mut self) -> &mut Extensions {
+        &mut self.extensions
+    }
+
+    /// Return the information about the connection on which the request has been received (addresses, identifier, etc.).
+    pub fn connection(&self) -> &ConnectionInfo {
+        &self.connection
+    }
+
+    /// Set the information about the connection.
+    pub(crate) fn set_connection(&mut self, connection: ConnectionInfo) {
+        self.connection = connection;
+    }
+
+    /// Set the maximum number of bytes read by get_body_bytes().
+    pub(crate) fn set_max_body_size(&mut self, size: u64) {
+        self.max_body_size = size;
+    }
+
+    /// Set the keyring used to read signed and private cookies.
+    pub(crate) fn set_keyring(&mut self, keyring: Option<Arc<Keyring>>) {
+        self.keyring = keyring;
+    }
+
+    /// Set the reader from which the body will be read when needed.
+    pub(crate) fn set_body_reader(&mut self, reader: Box<dyn Read + Send>) {
+        self.body_reader = Mutex::new(Some(BodyReader(reader)));
+    }
+
     /// Give the header value from the request body, key is the header name.
     /// 
+    /// Header names are case insensitive, an exact match is tried first.
+    /// 
     /// Return a Option object containing the header value, if the header is not found, return None.
     pub fn get_header(&self, key: &str) -> Option<&str> {
-        self.headers.get(key).map(|s| s.as_str())
+        self.headers.get(key)
+            .or_else(|| self.headers.iter().find(|(name, _)| name.eq_ignore_ascii_case(key)).map(|(_, value)| value))
+            .map(|s| s.as_str())
     }
 
-    /// Parse the headers of the request and return a HashMap containing the headers, key is the header name and value is the header value.
-    fn parse_header(body: &str) -> HashMap<String, String> {
-        let mut headers = HashMap::new();
-        for line in body.split("\r\n") {
-            let parts = line.split(": ").collect::<Vec<&str>>();
-            if parts.len() == 2 {
-                headers.insert(String::from(parts[0]), String::from(parts[1]));
+    /// Give every value of a header in the order they were received, key is the header name (case insensitive).
+    /// 
+    /// Use it for the headers which can be repeated (Forwarded, X-Forwarded-For, etc.), get_header() only returns one of the values.
+    /// The values are the ones received from the client, the changes made to the headers field aren't seen.
+    pub fn get_headers(&self, key: &str) -> Vec<&str> {
+        self.header_lines.iter()
+            .filter(|(name, _)| name.eq_ignore_ascii_case(key))
+            .map(|(_, value)| value.as_str())
+            .collect()
+    }
+
+    /// Parse the headers of the request and return every (name, value) pair in order.
+    /// 
+    /// The request line is ignored and the parsing stops at the first empty line (the beginning of the body).
+    fn parse_header_lines(body: &str) -> Vec<(String, String)> {
+        let mut headers = Vec::new();
+        for line in body.split("\r\n").skip(1).take_while(|line| !line.is_empty()) {
+            if let Some((key, value)) = line.split_once(':') {
+                headers.push((String::from(key.trim()), String::from(value.trim())));
             }
         };
         headers
     }
 
+    /// Return the cookies sent by the client in the Cookie header.
+    pub fn cookies(&self) -> CookieJar {
+        self.get_header("Cookie").map(CookieJar::parse).unwrap_or_default()
+    }
+
+    /// Return the value of the cookie with the given name, if the cookie is not found, return None.
+    pub fn get_cookie(&self, name: &str) -> Option<String> {
+        self.cookies().get(name).map(String::from)
+    }
+
+    /// Return the value of a cookie signed with Response::add_signed_cookie().
+    /// 
+    /// Return None if the cookie is not found, if its signature isn't valid or if no keyring has been set with Server::set_cookie_keyring().
+    pub fn get_signed_cookie(&self, name: &str) -> Option<String> {
+        let keyring = self.keyring.as_ref()?;
+        self.cookies().get_all(name).into_iter().find_map(|value| keyring.verify(name, value))
+    }
+
+    /// Return the value of a cookie encrypted with Response::add_private_cookie().
+    /// 
+    /// Return None if the cookie is not found, if it can't be decrypted or if no keyring has been set with Server::set_cookie_keyring().
+    pub fn get_private_cookie(&self, name: &str) -> Option<String> {
+        let keyring = self.keyring.as_ref()?;
+        self.cookies().get_all(name).into_iter().find_map(|value| keyring.decrypt(name, value))
+    }
+
     /// Return the body of the request, the body hasn't been decoded (see decode_body() method).
+    /// 
+    /// Invalid UTF-8 sequences are replaced by U+FFFD, use get_body_bytes() to get the raw body.
     pub fn get_body(&self) -> String {
-        self.body.clone()
+        String::from_utf8_lossy(self.get_body_bytes()).into_owned()
+    }
+
+    /// Return the raw body of the request, it's read from the client on the first call.
+    /// 
+    /// If the body has already been consumed by multipart(), or if it's bigger than the maximum body size
+    /// (see Server::set_max_body_size(), it also applies to multipart/form-data bodies here), return an empty slice.
+    pub fn get_body_bytes(&self) -> &[u8] {
+        self.body.get_or_init(|| {
+            let mut body = Vec::new();
+            if let Some(reader) = self.body_reader.lock().unwrap().take() {
+                // one more byte than allowed is read to know if the body is too large
+                if let Err(e) = reader.0.take(self.max_body_size.saturating_add(1)).read_to_end(&mut body) {
+                    eprintln!("Cannot read request body: {}", e);
+                }
+                if body.len() as u64 > self.max_body_size {
+                    eprintln!("Request body too large, limit is {} bytes", self.max_body_size);
+                    body = Vec::new();
+                }
+            }
+            body
+        })
+    }
+
+    /// Return the media type of the request (e.g. "application/json"), in lowercase and without its parameters.
+    /// 
+    /// Return None if the request doesn't have a Content-Type header.
+    pub fn content_type(&self) -> Option<String> {
+        self.get_header("Content-Type").map(|s| s.split(';').next().unwrap_or("").trim().to_ascii_lowercase())
+    }
+
+    /// Return the value of a parameter of the Content-Type header (e.g. "charset" or "boundary"), without quotes.
+    /// 
+    /// Parameter name is case insensitive, return None if the parameter is not found.
+    pub fn content_type_param(&self, name: &str) -> Option<String> {
+        self.get_header("Content-Type")?
+            .split(';')
+            .skip(1)
+            .filter_map(|param| param.split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.trim().trim_matches('"').to_string())
     }
 
     /// Return the request body as a String map.
     /// 
-    /// The key is the name of the form field, and the value is the value of the form field.
+    /// The key is the name of the form field, and the value is the percent-decoded value of the form field.
+    /// If a field is sent several times, only the last value is kept, use form() to get all of them.
     /// 
-    /// Return None if the request content-type is not application/x-www-form-urlencoded, otherwise return the HashMap in the Option object.
+    /// Return None if the request content-type is not application/x-www-form-urlencoded or if its charset isn't supported, otherwise return the HashMap in the Option object.
     pub fn decode_body(&self) -> Option<HashMap<String, String>> {
-        if self.get_header("Content-Type").unwrap_or("").contains("application/x-www-form-urlencoded") {
-            let mut params = HashMap::new();
-            for param in self.body.split("&") {
-                let parts = param.split("=").collect::<Vec<&str>>();
-                params.insert(String::from(parts[0]), String::from(*parts.get(1).unwrap_or(&"")));
-            }
-            Some(params)
-        } else {
-            None
+        self.form().ok().map(|form| form.to_map())
+    }
+
+    /// Decode the application/x-www-form-urlencoded body of the request.
+    /// 
+    /// The charset is read from the Content-Type header, then from the `_charset_` field sent by browsers, UTF-8 is used by default.
+    /// 
+    /// Return Err if the request content-type is not application/x-www-form-urlencoded or if the charset isn't supported.
+    pub fn form(&self) -> Result<Form, FormError> {
+        let content_type = self.content_type().unwrap_or_default();
+        if content_type != "application/x-www-form-urlencoded" {
+            return Err(FormError::UnsupportedContentType(content_type));
+        }
+        match self.content_type_param("charset") {
+            Some(label) => match Charset::from_label(&label) {
+                Some(charset) => Ok(Form::parse(self.get_body_bytes(), charset)),
+                None => Err(FormError::UnsupportedCharset(label)),
+            },
+            None => Form::parse_detect_charset(self.get_body_bytes()),
         }
     }
+
+    /// Decode the application/x-www-form-urlencoded body of the request and convert it to T.
+    /// 
+    /// See the FromForm trait to know how to implement the conversion for your own types.
+    pub fn form_as<T: FromForm>(&self) -> Result<T, FormError> {
+        self.form()?.deserialize()
+    }
+
+    /// Deserialize the JSON body of the request to T.
+    /// 
+    /// Return Err if the content-type isn't application/json (or a +json media type) or if the body can't be deserialized,
+    /// use JsonError::status() to get the status to send to the client (415 or 400).
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::de::DeserializeOwned>(&self) -> Result<T, JsonError> {
+        let content_type = self.content_type().unwrap_or_default();
+        if !crate::json::is_json_media_type(&content_type) {
+            return Err(JsonError::UnsupportedContentType(content_type));
+        }
+        serde_json::from_slice(self.get_body_bytes()).map_err(JsonError::parse)
+    }
+
+    /// Return a streaming parser over the multipart/form-data body of the request.
+    /// 
+    /// The body is read from the client while iterating over the parts, so it can only be done once.
+    /// If the body has already been read (by get_body() for example), the parser use the body kept in memory.
+    /// 
+    /// Return Err if the request content-type is not multipart/form-data or if the boundary is missing.
+    pub fn multipart(&self, limits: MultipartLimits) -> Result<Multipart, MultipartError> {
+        let content_type = self.content_type().unwrap_or_default();
+        if content_type != "multipart/form-data" {
+            return Err(MultipartError::UnsupportedContentType(content_type));
+        }
+        let boundary = self.content_type_param("boundary").filter(|b| !b.is_empty()).ok_or(MultipartError::MissingBoundary)?;
+        let reader = match self.body_reader.lock().unwrap().take() {
+            Some(reader) => reader.0,
+            None => Box::new(Cursor::new(self.body.get().cloned().unwrap_or_default())),
+        };
+        Ok(Multipart::new(reader, &boundary, limits))
+    }
 }
 
+/// Part of the request body which hasn't been read from the client yet.
+struct BodyReader(Box<dyn Read + Send>);
+
+impl Debug for BodyReader {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "BodyReader")
+    }
+}
 
 /// RequestPath struct, used to represent the path of the request.
 /// path is the path of the request without the query, and query is the opposite.
@@ -84,7 +304,7 @@ impl RequestPath {
     /// Create a new RequestPath struct, used when a client make a request.
     pub fn new(path: String) -> Self {
         let url = path.split_once("?").unwrap_or((path.as_str(), ""));
-        let path = url.0.split("/").filter(|s| !s.is_empty()).map(|s| String::from(s)).collect::<Vec<String>>();
+        let path = url.0.split("/").filter(|s| !s.is_empty()).map(String::from).collect::<Vec<String>>();
         let query = url.1.split("&").filter(|s| !s.is_empty()).map(|s| s.split("=").collect::<Vec<&str>>()).map(|v| (String::from(v[0]), String::from(*v.get(1).unwrap_or(&"")))).collect::<HashMap<String, String>>();
         RequestPath {
             path,
@@ -94,7 +314,7 @@ impl RequestPath {
 
     /// Create a new RequestPath struct, used when the server create a new route.
     pub fn new_route(path: String) -> Self {
-        let path = path.split("/").filter(|s| !s.is_empty()).map(|s| String::from(s)).collect::<Vec<String>>();
+        let path = path.split("/").filter(|s| !s.is_empty()).map(String::from).collect::<Vec<String>>();
         RequestPath {
             path,
             query: HashMap::with_capacity(0),
@@ -111,17 +331,24 @@ impl RequestPath {
         self.path.join("/")
     }
 
+    /// Return true if the path starts with all the segments of prefix, "/" is a prefix of every path.
+    pub fn starts_with(&self, prefix: &RequestPath) -> bool {
+        self.path.starts_with(&prefix.path)
+    }
+
     /// Return the value of the path parameter at the position index.
     /// 
     /// Return None if the index is out of range.
     /// 
     /// ## Example: 
-    /// ```request: /user/profile?id=1
+    /// ```text
+    /// request: /user/profile?id=1
     /// get_path_positon(0) => /user
     /// get_path_positon(1) => /profile
-    /// get_path_positon(2) => None```
+    /// get_path_positon(2) => None
+    /// ```
     pub fn get_path_position(&self, position: usize) -> Option<String> {
-        self.path.get(position).map(|s| s.clone())
+        self.path.get(position).cloned()
     }
 }
 
@@ -144,3 +371,46 @@ impl Display for RequestPath {
         write!(f, "/{}, params: {:?}", self.get_path(), self.query)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn request(head: &str, body: &[u8]) -> Request {
+        let mut request = Request::from_head(Method::POST, RequestPath::new(String::from("/")), head);
+        request.set_body_reader(Box::new(Cursor::new(body.to_vec())));
+        request
+    }
+
+    #[test]
+    fn read_body_up_to_max_size() {
+        let mut request = request("POST / HTTP/1.1\r\nContent-Type: text/plain", b"0123456789");
+        request.set_max_body_size(10);
+        assert_eq!(request.get_body_bytes(), b"0123456789");
+    }
+
+    #[test]
+    fn drop_body_bigger_than_max_size() {
+        // multipart bodies aren't rejected on their Content-Length, get_body() must not read them entirely
+        let mut request = request("POST / HTTP/1.1\r\nContent-Type: multipart/form-data; boundary=x", &[b'a'; 1024]);
+        request.set_max_body_size(100);
+        assert!(request.get_body_bytes().is_empty());
+        assert_eq!(request.get_body(), "");
+    }
+
+    #[test]
+    fn header_names_are_case_insensitive() {
+        let request = request("GET / HTTP/1.1\r\ncontent-type: text/html; charset=\"UTF-8\"", b"");
+        assert_eq!(request.get_header("Content-Type"), Some("text/html; charset=\"UTF-8\""));
+        assert_eq!(request.content_type().as_deref(), Some("text/html"));
+        assert_eq!(request.content_type_param("Charset").as_deref(), Some("UTF-8"));
+    }
+
+    #[test]
+    fn keep_repeated_headers_in_order() {
+        let request = request("GET / HTTP/1.1\r\nAccept: text/html\r\nVia: 1.1 a\r\naccept: */*\r\nACCEPT: image/png", b"");
+        assert_eq!(request.get_headers("Accept"), vec!["text/html", "*/*", "image/png"]);
+        assert_eq!(request.get_headers("via"), vec!["1.1 a"]);
+        assert!(request.get_headers("Host").is_empty());
+    }
+}
@@ -1,7 +1,14 @@
+use crate::connection::Stream;
 use std::net::TcpStream;
 use std::io::Write;
 use std::collections::HashMap;
 use crate::status::Status;
+use crate::cookie::{Cookie, Keyring};
+use std::sync::Arc;
+#[cfg(feature = "json")]
+use crate::json::JsonError;
+
+type SendHook = Box<dyn FnOnce(&mut Response) + Send>;
 
 /// Response struct, used to send a response to the client.
 /// The response is sent by calling the send() method.
@@ -9,19 +16,32 @@ pub struct Response {
     pub status: Status,
     pub headers: HashMap<String, String>,
     pub body: String,
-    stream: TcpStream,
+    appended_headers: Vec<(String, String)>,
+    cookies: Vec<Cookie>,
+    keyring: Option<Arc<Keyring>>,
+    send_hooks: Vec<SendHook>,
+    stream: Stream,
 }
 
 impl Response {
 
     /// Create a new Response struct
     pub fn new(stream: TcpStream) -> Self {
+        Self::from_stream(Stream::Tcp(stream))
+    }
+
+    /// Create a new Response struct sending the response on a TCP or a Unix socket.
+    pub(crate) fn from_stream(stream: Stream) -> Self {
         let mut headers = HashMap::new();
         headers.insert(String::from("Content-Type"), String::from("text/html; charset=utf-8"));
         Self {
             status: Status::Ok,
             headers,
             body: String::new(),
+            appended_headers: Vec::new(),
+            cookies: Vec::new(),
+            keyring: None,
+            send_hooks: Vec::new(),
             stream,
         }
     }
@@ -33,11 +53,65 @@ impl Response {
         self.headers.insert(key, value);
     }
 
+    /// Add a header to the response without replacing the existing ones with the same name.
+    /// 
+    /// Used for headers which can be sent several times (Link, Vary, etc.), cookies should be added with add_cookie().
+    pub fn append_header(&mut self, key: String, value: String) {
+        self.appended_headers.push((key, value));
+    }
+
     /// Remove a header from the response.
     /// 
     /// If the header doesn't exist, nothing will happen.
+    /// Headers added with append_header() are also removed.
     pub fn remove_header(&mut self, key: String) {
         self.headers.remove(&key);
+        self.appended_headers.retain(|(name, _)| !name.eq_ignore_ascii_case(&key));
+    }
+
+    /// Add a cookie to the response, each cookie is sent in its own Set-Cookie header.
+    /// 
+    /// If a cookie with the same name, path and domain has already been added, it's replaced.
+    pub fn add_cookie(&mut self, cookie: Cookie) {
+        self.cookies.retain(|c| c.name() != cookie.name() || c.get_path() != cookie.get_path() || c.get_domain() != cookie.get_domain());
+        self.cookies.push(cookie);
+    }
+
+    /// Add a cookie signed with the primary key of the server keyring, the client can read the value but can't modify it.
+    /// Read it with Request::get_signed_cookie().
+    /// 
+    /// The cookie isn't added if no keyring has been set with Server::set_cookie_keyring().
+    pub fn add_signed_cookie(&mut self, cookie: Cookie) {
+        match self.keyring.clone() {
+            Some(keyring) => self.add_cookie(keyring.sign(&cookie)),
+            None => eprintln!("Cannot sign cookie {}: no keyring set on the server", cookie.name()),
+        }
+    }
+
+    /// Add a cookie encrypted with the primary key of the server keyring, the client can't read nor modify the value.
+    /// Read it with Request::get_private_cookie().
+    /// 
+    /// The cookie isn't added if no keyring has been set with Server::set_cookie_keyring().
+    pub fn add_private_cookie(&mut self, cookie: Cookie) {
+        match self.keyring.clone() {
+            Some(keyring) => self.add_cookie(keyring.encrypt(&cookie)),
+            None => eprintln!("Cannot encrypt cookie {}: no keyring set on the server", cookie.name()),
+        }
+    }
+
+    /// Set the keyring used to sign and encrypt cookies.
+    pub(crate) fn set_keyring(&mut self, keyring: Option<Arc<Keyring>>) {
+        self.keyring = keyring;
+    }
+
+    /// Ask the client to remove the cookie with the given name, see Cookie::removal() if the cookie has a path or a domain.
+    pub fn remove_cookie(&mut self, name: String) {
+        self.add_cookie(Cookie::removal(name));
+    }
+
+    /// Return the cookies which will be sent with the response.
+    pub fn get_cookies(&self) -> &[Cookie] {
+        &self.cookies
     }
 
     /// Set the status of the response (200, 404, etc.) using the Status enum
@@ -52,19 +126,90 @@ impl Response {
         self.body = body.to_string();
     }
 
+    /// Serialize the value to JSON and use it as the body of the response.
+    /// The Content-Type header is set to application/json.
+    /// 
+    /// Return Err if the value can't be serialized, the response is left unchanged in this case.
+    #[cfg(feature = "json")]
+    pub fn json<T: serde::Serialize + ?Sized>(&mut self, value: &T) -> Result<(), JsonError> {
+        let body = serde_json::to_string(value).map_err(|e| JsonError::Serialize(e.to_string()))?;
+        self.set_header(String::from("Content-Type"), String::from("application/json"));
+        self.body = body;
+        Ok(())
+    }
+
+    /// Register a function called by send() just before the response is written, it can still modify the response.
+    /// 
+    /// Used by the middlewares which need to act after the handler (to save the session, add headers, etc.),
+    /// hooks are called in the reverse order of their registration, so the first middleware is the last to modify the response.
+    pub fn on_send(&mut self, hook: Box<dyn FnOnce(&mut Response) + Send>) {
+        self.send_hooks.push(hook);
+    }
+
     /// Send a HTTP/1.1 response to the client.
     /// 
     /// The stream is closed after the response is sent, so this method should be called only once and at the end of your function.
     pub fn send(&mut self) {
+        while let Some(hook) = self.send_hooks.pop() {
+            hook(self);
+        }
         let status_line = format!("HTTP/1.1 {}\r\n", self.status.as_str());
         let mut response = status_line.to_string();
         self.set_header(String::from("Content-Length"), self.body.len().to_string());
-        for (key, value) in &self.headers {
+        for (key, value) in self.headers.iter().chain(self.appended_headers.iter().map(|(key, value)| (key, value))) {
             response.push_str(&format!("{}: {}\r\n", key, value));
         }
+        for cookie in &self.cookies {
+            response.push_str(&format!("Set-Cookie: {}\r\n", cookie));
+        }
         response.push_str("\r\n");
         response.push_str(&self.body);
-        self.stream.write(response.as_bytes()).unwrap();
-        self.stream.flush().unwrap();
+        // the client may have closed the connection, or the server closed it at the end of the shutdown timeout
+        if let Err(e) = self.stream.write_all(response.as_bytes()).and_then(|_| self.stream.flush()) {
+            eprintln!("Cannot send response: {}", e);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::testing;
+    use super::*;
+
+    #[test]
+    fn send_hooks_run_in_reverse_order_before_writing() {
+        let (mut response, client) = testing::response();
+        response.on_send(Box::new(|response: &mut Response| {
+            response.set_body(&format!("{} first", response.body));
+            response.set_header(String::from("X-Hook"), String::from("first"));
+        }));
+        response.on_send(Box::new(|response: &mut Response| {
+            response.set_body(&format!("{} second", response.body));
+            response.set_header(String::from("X-Hook"), String::from("second"));
+        }));
+        response.set_body("handler");
+        response.send();
+        drop(response);
+        let content = testing::read_response(client);
+        assert_eq!(testing::header(&content, "X-Hook"), Some("first"));
+        assert_eq!(testing::header(&content, "Content-Length"), Some("20"));
+        assert!(content.ends_with("\r\n\r\nhandler second first"));
+    }
+
+    #[test]
+    fn send_headers_and_cookies() {
+        let (mut response, client) = testing::response();
+        response.set_status(Status::Created);
+        response.append_header(String::from("Vary"), String::from("Origin"));
+        response.append_header(String::from("Vary"), String::from("Cookie"));
+        response.add_cookie(Cookie::new(String::from("a"), String::from("1")));
+        response.add_cookie(Cookie::new(String::from("a"), String::from("2")));
+        response.add_cookie(Cookie::new(String::from("b"), String::from("3")));
+        response.send();
+        drop(response);
+        let content = testing::read_response(client);
+        assert_eq!(testing::status(&content), "HTTP/1.1 201 Created");
+        assert_eq!(testing::headers(&content, "Vary"), vec!["Origin", "Cookie"]);
+        assert_eq!(testing::headers(&content, "Set-Cookie"), vec!["a=2", "b=3"]);
     }
 }
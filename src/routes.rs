@@ -0,0 +1,230 @@
+use crate::method::Method;
+use crate::request::RequestPath;
+use crate::IFn;
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock, RwLockWriteGuard};
+
+/// A route of the routing table.
+pub(crate) struct Route {
+    pub(crate) handler: Arc<IFn>,
+    pub(crate) enabled: bool,
+}
+
+pub(crate) type Routes = HashMap<(Method, RequestPath), Route>;
+
+/// RouteHandle struct, adds, replaces, disables or removes the routes of a server, even while it's running, see Server::routes().
+///
+/// The handlers don't hold the routing table while they run: a change applies to the next requests
+/// and the running requests finish with the handler they started with.
+///
+/// ## Example:
+/// ```
+/// use rest_server::method::Method;
+/// use rest_server::Server;
+///
+/// let mut app = Server::new();
+/// let routes = app.routes();
+/// routes.add(Method::GET, String::from("/beta"), Box::new(|_request, mut response| {
+///     response.set_body("New feature");
+///     response.send();
+/// }));
+/// // feature flag turned off, the route answers 404 until it's enabled again
+/// routes.disable(Method::GET, String::from("/beta"));
+///
+/// // several changes applied at once, the requests see all of them or none
+/// let mut table = routes.lock();
+/// table.remove(Method::GET, String::from("/beta"));
+/// table.add(Method::GET, String::from("/plugin/status"), Box::new(|_request, mut response| {
+///     response.set_body("OK");
+///     response.send();
+/// }));
+/// drop(table);
+/// assert_eq!(routes.is_enabled(Method::GET, String::from("/plugin/status")), Some(true));
+/// ```
+#[derive(Clone)]
+pub struct RouteHandle {
+    routing: Arc<RwLock<Routes>>,
+}
+
+impl RouteHandle {
+
+    pub(crate) fn new(routing: Arc<RwLock<Routes>>) -> Self {
+        Self {
+            routing,
+        }
+    }
+
+    /// Lock the routing table to apply several changes atomically, the requests wait until the RouteTable is dropped.
+    pub fn lock(&self) -> RouteTable<'_> {
+        RouteTable {
+            routes: self.routing.write().unwrap(),
+        }
+    }
+
+    /// Add a route or replace the handler of an existing one, the route is enabled.
+    /// Return true if a route has been replaced.
+    pub fn add(&self, method: Method, path: String, f: Box<IFn>) -> bool {
+        self.lock().add(method, path, f)
+    }
+
+    /// Remove a route, return false if there was no such route.
+    pub fn remove(&self, method: Method, path: String) -> bool {
+        self.lock().remove(method, path)
+    }
+
+    /// Enable a disabled route, return false if there is no such route.
+    pub fn enable(&self, method: Method, path: String) -> bool {
+        self.lock().enable(method, path)
+    }
+
+    /// Disable a route, it answers 404 until it's enabled again. Return false if there is no such route.
+    pub fn disable(&self, method: Method, path: String) -> bool {
+        self.lock().disable(method, path)
+    }
+
+    /// Return whether the route is enabled, None if there is no such route.
+    pub fn is_enabled(&self, method: Method, path: String) -> Option<bool> {
+        self.routing.read().unwrap().get(&(method, RequestPath::new_route(path))).map(|route| route.enabled)
+    }
+
+    /// Return the routes (method, path and whether they're enabled), sorted by path.
+    pub fn list(&self) -> Vec<(Method, String, bool)> {
+        let mut routes = self.routing.read().unwrap().iter()
+            .map(|((method, path), route)| (*method, format!("/{}", path.get_path()), route.enabled))
+            .collect::<Vec<(Method, String, bool)>>();
+        routes.sort_by(|a, b| a.1.cmp(&b.1).then_with(|| a.0.as_str().cmp(b.0.as_str())));
+        routes
+    }
+}
+
+/// RouteTable struct, the routing table locked by RouteHandle::lock(), the changes are seen by the requests once it's dropped.
+pub struct RouteTable<'a> {
+    routes: RwLockWriteGuard<'a, Routes>,
+}
+
+impl RouteTable<'_> {
+
+    /// Add a route or replace the handler of an existing one, the route is enabled.
+    /// Return true if a route has been replaced.
+    pub fn add(&mut self, method: Method, path: String, f: Box<IFn>) -> bool {
+        let route = Route {
+            handler: Arc::from(f),
+            enabled: true,
+        };
+        self.routes.insert((method, RequestPath::new_route(path)), route).is_some()
+    }
+
+    /// Remove a route, return false if there was no such route.
+    pub fn remove(&mut self, method: Method, path: String) -> bool {
+        self.routes.remove(&(method, RequestPath::new_route(path))).is_some()
+    }
+
+    /// Enable a disabled route, return false if there is no such route.
+    pub fn enable(&mut self, method: Method, path: String) -> bool {
+        self.set_enabled(method, path, true)
+    }
+
+    /// Disable a route, it answers 404 until it's enabled again. Return false if there is no such route.
+    pub fn disable(&mut self, method: Method, path: String) -> bool {
+        self.set_enabled(method, path, false)
+    }
+
+    fn set_enabled(&mut self, method: Method, path: String, enabled: bool) -> bool {
+        match self.routes.get_mut(&(method, RequestPath::new_route(path))) {
+            Some(route) => {
+                route.enabled = enabled;
+                true
+            },
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::handle::ServerHandle;
+    use crate::request::Request;
+    use crate::response::Response;
+    use crate::testing;
+    use crate::Server;
+    use std::io::{Read, Write};
+    use std::net::{SocketAddr, TcpStream};
+    use std::sync::{mpsc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    fn hello(_request: Request, mut response: Response) {
+        response.set_body("Hello");
+        response.send();
+    }
+
+    fn server() -> (ServerHandle, RouteHandle) {
+        let app = Server::new();
+        let routes = app.routes();
+        (app.bind("127.0.0.1:0".parse().unwrap()).unwrap(), routes)
+    }
+
+    /// Send a GET request and return the status line of the response.
+    fn get(addr: SocketAddr, path: &str) -> String {
+        let mut stream = TcpStream::connect(addr).unwrap();
+        stream.set_read_timeout(Some(Duration::from_secs(10))).unwrap();
+        stream.write_all(format!("GET {} HTTP/1.1\r\nHost: localhost\r\n\r\n", path).as_bytes()).unwrap();
+        let mut content = String::new();
+        stream.read_to_string(&mut content).unwrap();
+        String::from(testing::status(&content))
+    }
+
+    #[test]
+    fn add_route_while_running() {
+        let (handle, routes) = server();
+        assert_eq!(get(handle.local_addr(), "/hello"), "HTTP/1.1 404 Not Found");
+        assert!(!routes.add(Method::GET, String::from("/hello"), Box::new(hello)));
+        assert_eq!(get(handle.local_addr(), "/hello"), "HTTP/1.1 200 OK");
+    }
+
+    #[test]
+    fn disable_enable_and_remove() {
+        let (handle, routes) = server();
+        routes.add(Method::GET, String::from("/hello"), Box::new(hello));
+        assert!(routes.disable(Method::GET, String::from("/hello")));
+        assert_eq!(routes.is_enabled(Method::GET, String::from("/hello")), Some(false));
+        assert_eq!(get(handle.local_addr(), "/hello"), "HTTP/1.1 404 Not Found");
+        assert!(routes.enable(Method::GET, String::from("/hello")));
+        assert_eq!(get(handle.local_addr(), "/hello"), "HTTP/1.1 200 OK");
+        assert!(routes.remove(Method::GET, String::from("/hello")));
+        assert_eq!(routes.is_enabled(Method::GET, String::from("/hello")), None);
+        assert_eq!(get(handle.local_addr(), "/hello"), "HTTP/1.1 404 Not Found");
+        assert!(!routes.remove(Method::GET, String::from("/hello")));
+        assert!(!routes.enable(Method::GET, String::from("/hello")));
+    }
+
+    #[test]
+    fn running_handler_doesnt_block_changes() {
+        let (handle, routes) = server();
+        let (started, handler_started) = mpsc::channel();
+        let (release, released) = mpsc::channel::<()>();
+        let released = Mutex::new(released);
+        routes.add(Method::GET, String::from("/blocked"), Box::new(move |_request, mut response| {
+            started.send(()).unwrap();
+            let _ = released.lock().unwrap().recv();
+            response.send();
+        }));
+        let addr = handle.local_addr();
+        let blocked = thread::spawn(move || get(addr, "/blocked"));
+        handler_started.recv_timeout(Duration::from_secs(5)).unwrap();
+
+        // the routing table can be changed while the handler runs
+        let (added, changed) = mpsc::channel();
+        let writer = routes.clone();
+        thread::spawn(move || {
+            writer.add(Method::GET, String::from("/hello"), Box::new(hello));
+            added.send(()).unwrap();
+        });
+        changed.recv_timeout(Duration::from_secs(2)).expect("the routing table is locked by the running handler");
+        assert_eq!(get(handle.local_addr(), "/hello"), "HTTP/1.1 200 OK");
+
+        release.send(()).unwrap();
+        assert_eq!(blocked.join().unwrap(), "HTTP/1.1 200 OK");
+    }
+}
@@ -0,0 +1,244 @@
+use crate::crypto;
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+
+/// Placeholder replaced by the nonce of the request in the Content-Security-Policy.
+const NONCE_PLACEHOLDER: &str = "{nonce}";
+
+/// CspNonce struct, the nonce of the Content-Security-Policy of the response, added to the request extensions by the SecurityHeaders middleware.
+///
+/// The inline scripts and styles of the page must use it: `<script nonce="...">`.
+#[derive(Debug, Clone)]
+pub struct CspNonce {
+    nonce: String,
+}
+
+impl CspNonce {
+
+    /// Return the nonce.
+    pub fn value(&self) -> &str {
+        &self.nonce
+    }
+
+    /// Return the nonce attribute to add to the script and style tags: `nonce="..."`.
+    pub fn attribute(&self) -> String {
+        format!("nonce=\"{}\"", self.nonce)
+    }
+}
+
+/// Middleware adding hardening headers to the responses.
+///
+/// The headers are set before the route handler is called, so a handler can override one of them with Response::set_header()
+/// or drop it with Response::remove_header() (use the same case as the names below).
+/// Every header can be changed or disabled (with None), the defaults are:
+/// ```text
+/// Strict-Transport-Security: max-age=31536000; includeSubDomains
+/// Content-Security-Policy: default-src 'self'; script-src 'self' 'nonce-{nonce}'; style-src 'self' 'nonce-{nonce}'; object-src 'none'; base-uri 'self'; frame-ancestors 'none'
+/// X-Content-Type-Options: nosniff
+/// Referrer-Policy: strict-origin-when-cross-origin
+/// Permissions-Policy: camera=(), microphone=(), geolocation=()
+/// X-Frame-Options: DENY
+/// ```
+///
+/// `{nonce}` is replaced by a random nonce on each request, available to the handlers as a CspNonce in the request extensions.
+///
+/// ## Example:
+/// ```
+/// use rest_server::security::{CspNonce, SecurityHeaders};
+/// use rest_server::request::Request;
+/// use rest_server::response::Response;
+/// use rest_server::Server;
+///
+/// fn index(request: Request, mut response: Response) {
+///     let nonce = request.extensions().get::<CspNonce>().unwrap();
+///     response.set_body(&format!("<script {}>console.log('hello')</script>", nonce.attribute()));
+///     response.send();
+/// }
+///
+/// fn embeddable(_request: Request, mut response: Response) {
+///     // this page can be displayed in a frame of the same origin
+///     response.set_header(String::from("X-Frame-Options"), String::from("SAMEORIGIN"));
+///     response.send();
+/// }
+///
+/// let mut app = Server::new();
+/// app.middleware(Box::new(SecurityHeaders::new().hsts(None)));
+/// app.get(String::from("/"), Box::new(index));
+/// app.get(String::from("/widget"), Box::new(embeddable));
+/// ```
+pub struct SecurityHeaders {
+    hsts: Option<String>,
+    content_security_policy: Option<String>,
+    content_type_options: Option<String>,
+    referrer_policy: Option<String>,
+    permissions_policy: Option<String>,
+    frame_options: Option<String>,
+}
+
+impl SecurityHeaders {
+
+    /// Create a new SecurityHeaders middleware with the default headers.
+    pub fn new() -> Self {
+        Self {
+            hsts: Some(String::from("max-age=31536000; includeSubDomains")),
+            content_security_policy: Some(String::from("default-src 'self'; script-src 'self' 'nonce-{nonce}'; style-src 'self' 'nonce-{nonce}'; object-src 'none'; base-uri 'self'; frame-ancestors 'none'")),
+            content_type_options: Some(String::from("nosniff")),
+            referrer_policy: Some(String::from("strict-origin-when-cross-origin")),
+            permissions_policy: Some(String::from("camera=(), microphone=(), geolocation=()")),
+            frame_options: Some(String::from("DENY")),
+        }
+    }
+
+    /// Set the Strict-Transport-Security header, it should be disabled if the server isn't reachable only through HTTPS.
+    pub fn hsts(mut self, value: Option<String>) -> Self {
+        self.hsts = value;
+        self
+    }
+
+    /// Set the Content-Security-Policy header, `{nonce}` is replaced by the nonce of the request.
+    pub fn content_security_policy(mut self, value: Option<String>) -> Self {
+        self.content_security_policy = value;
+        self
+    }
+
+    /// Set the X-Content-Type-Options header.
+    pub fn content_type_options(mut self, value: Option<String>) -> Self {
+        self.content_type_options = value;
+        self
+    }
+
+    /// Set the Referrer-Policy header.
+    pub fn referrer_policy(mut self, value: Option<String>) -> Self {
+        self.referrer_policy = value;
+        self
+    }
+
+    /// Set the Permissions-Policy header.
+    pub fn permissions_policy(mut self, value: Option<String>) -> Self {
+        self.permissions_policy = value;
+        self
+    }
+
+    /// Set the X-Frame-Options header, for the browsers which don't support the frame-ancestors directive of the Content-Security-Policy.
+    pub fn frame_options(mut self, value: Option<String>) -> Self {
+        self.frame_options = value;
+        self
+    }
+}
+
+impl Default for SecurityHeaders {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Middleware for SecurityHeaders {
+    fn handle(&self, mut request: Request, mut response: Response, next: Next) {
+        let headers = [
+            ("Strict-Transport-Security", &self.hsts),
+            ("X-Content-Type-Options", &self.content_type_options),
+            ("Referrer-Policy", &self.referrer_policy),
+            ("Permissions-Policy", &self.permissions_policy),
+            ("X-Frame-Options", &self.frame_options),
+        ];
+        for (name, value) in headers {
+            if let Some(value) = value {
+                response.set_header(String::from(name), value.clone());
+            }
+        }
+        if let Some(policy) = &self.content_security_policy {
+            if policy.contains(NONCE_PLACEHOLDER) {
+                let nonce = crypto::random_token(16);
+                response.set_header(String::from("Content-Security-Policy"), policy.replace(NONCE_PLACEHOLDER, &nonce));
+                request.extensions_mut().insert(CspNonce {
+                    nonce,
+                });
+            } else {
+                response.set_header(String::from("Content-Security-Policy"), policy.clone());
+            }
+        }
+        next.run(request, response);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::testing;
+    use std::sync::Arc;
+
+    /// Run the middleware, the handler answers with the nonce it received ("none" without nonce).
+    fn run(security: SecurityHeaders) -> String {
+        run_with(security, |request, mut response| {
+            let nonce = request.extensions().get::<CspNonce>().map(|nonce| nonce.value().to_string());
+            response.set_body(nonce.as_deref().unwrap_or("none"));
+            response.send();
+        })
+    }
+
+    fn run_with(security: SecurityHeaders, handler: fn(Request, Response)) -> String {
+        let middleware: Arc<dyn Middleware> = Arc::new(security);
+        let (response, client) = testing::response();
+        Next::new(&[middleware], &handler).run(testing::request(Method::GET, "/", &[]), response);
+        testing::read_response(client)
+    }
+
+    fn body(response: &str) -> &str {
+        response.split("\r\n\r\n").nth(1).unwrap()
+    }
+
+    #[test]
+    fn default_headers() {
+        let response = run(SecurityHeaders::new());
+        assert_eq!(testing::header(&response, "Strict-Transport-Security"), Some("max-age=31536000; includeSubDomains"));
+        assert_eq!(testing::header(&response, "X-Content-Type-Options"), Some("nosniff"));
+        assert_eq!(testing::header(&response, "Referrer-Policy"), Some("strict-origin-when-cross-origin"));
+        assert_eq!(testing::header(&response, "Permissions-Policy"), Some("camera=(), microphone=(), geolocation=()"));
+        assert_eq!(testing::header(&response, "X-Frame-Options"), Some("DENY"));
+        let policy = testing::header(&response, "Content-Security-Policy").unwrap();
+        assert!(policy.starts_with("default-src 'self'; script-src 'self' 'nonce-"));
+        assert!(!policy.contains(NONCE_PLACEHOLDER));
+    }
+
+    #[test]
+    fn fresh_nonce_on_each_request() {
+        let first = run(SecurityHeaders::new());
+        let second = run(SecurityHeaders::new());
+        let nonce = body(&first);
+        assert_eq!(nonce.len(), 22);
+        assert_ne!(nonce, body(&second));
+
+        let policy = testing::header(&first, "Content-Security-Policy").unwrap();
+        assert_eq!(policy.matches(&format!("'nonce-{}'", nonce)).count(), 2);
+    }
+
+    #[test]
+    fn disabled_header_isnt_sent() {
+        let response = run(SecurityHeaders::new().hsts(None).frame_options(None));
+        assert_eq!(testing::header(&response, "Strict-Transport-Security"), None);
+        assert_eq!(testing::header(&response, "X-Frame-Options"), None);
+        assert_eq!(testing::header(&response, "X-Content-Type-Options"), Some("nosniff"));
+
+        let response = run(SecurityHeaders::new().content_security_policy(None));
+        assert_eq!(testing::header(&response, "Content-Security-Policy"), None);
+        assert_eq!(body(&response), "none");
+    }
+
+    #[test]
+    fn policy_without_nonce() {
+        let response = run(SecurityHeaders::new().content_security_policy(Some(String::from("default-src 'none'"))));
+        assert_eq!(testing::header(&response, "Content-Security-Policy"), Some("default-src 'none'"));
+        assert_eq!(body(&response), "none");
+    }
+
+    #[test]
+    fn handler_overrides_header() {
+        let response = run_with(SecurityHeaders::new(), |_request, mut response| {
+            response.set_header(String::from("X-Frame-Options"), String::from("SAMEORIGIN"));
+            response.send();
+        });
+        assert_eq!(testing::headers(&response, "X-Frame-Options"), vec!["SAMEORIGIN"]);
+    }
+}
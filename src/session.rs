@@ -0,0 +1,745 @@
+use crate::cookie::{self, Cookie, SameSite};
+use crate::crypto;
+use crate::form::{Charset, Form};
+use crate::middleware::{Middleware, Next};
+use crate::request::Request;
+use crate::response::Response;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+/// Size in bytes of the random part of a session ID.
+const SESSION_ID_SIZE: usize = 32;
+
+/// Counter used to give a unique name to the temporary files written by the FileStore.
+static TEMP_FILE_COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+/// Trait implemented by the types which can be stored in a session.
+///
+/// The data must be encoded to a String to be saved by a store, it's implemented for `HashMap<String, String>`
+/// and, with the `json` feature, for `Json<T>` where T can be serialized with serde.
+pub trait SessionData: Clone + Default + Send + Sync + 'static {
+    /// Encode the data to a String saved by the store.
+    fn encode(&self) -> String;
+    /// Decode the data saved by the store, return None if it can't be decoded.
+    fn decode(data: &str) -> Option<Self>;
+}
+
+impl SessionData for HashMap<String, String> {
+    fn encode(&self) -> String {
+        self.iter().map(|(key, value)| (key.clone(), value.clone())).collect::<Form>().encode()
+    }
+
+    fn decode(data: &str) -> Option<Self> {
+        Some(Form::parse(data.as_bytes(), Charset::Utf8).to_map())
+    }
+}
+
+/// Wrapper storing any serde serializable type as JSON in a session.
+#[cfg(feature = "json")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Json<T>(pub T);
+
+#[cfg(feature = "json")]
+impl<T> SessionData for Json<T>
+where
+    T: serde::Serialize + serde::de::DeserializeOwned + Clone + Default + Send + Sync + 'static, {
+        fn encode(&self) -> String {
+            serde_json::to_string(&self.0).unwrap_or_default()
+        }
+
+        fn decode(data: &str) -> Option<Self> {
+            serde_json::from_str(data).ok().map(Json)
+        }
+    }
+
+/// Trait implemented by the session stores, a store saves the data of the sessions by their ID.
+pub trait SessionStore<T: SessionData>: Send + Sync {
+    /// Return the data of the session, if the session doesn't exist or has expired, return None.
+    fn load(&self, id: &str) -> Option<T>;
+    /// Save the data of the session, the session expires after ttl.
+    fn save(&self, id: &str, data: &T, ttl: Duration) -> Result<(), String>;
+    /// Remove the session, nothing happens if it doesn't exist.
+    fn destroy(&self, id: &str);
+}
+
+/// Session store keeping the sessions in memory, sessions are lost when the server stops.
+///
+/// Expired sessions are removed during a save, at most once per sweep interval (default to 60 seconds).
+pub struct MemoryStore<T> {
+    sessions: Mutex<HashMap<String, (T, Instant)>>,
+    sweep_interval: Duration,
+    last_sweep: Mutex<Instant>,
+}
+
+impl<T: SessionData> MemoryStore<T> {
+
+    /// Create a new empty MemoryStore.
+    pub fn new() -> Self {
+        Self::with_sweep_interval(Duration::from_secs(60))
+    }
+
+    /// Create a new empty MemoryStore sweeping the expired sessions at most once per interval.
+    pub fn with_sweep_interval(sweep_interval: Duration) -> Self {
+        Self {
+            sessions: Mutex::new(HashMap::new()),
+            sweep_interval,
+            last_sweep: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Remove all the expired sessions.
+    pub fn sweep(&self) {
+        let now = Instant::now();
+        self.sessions.lock().unwrap().retain(|_, (_, expires)| *expires > now);
+        *self.last_sweep.lock().unwrap() = now;
+    }
+
+    /// Return the number of sessions in the store, including the expired ones not swept yet.
+    pub fn len(&self) -> usize {
+        self.sessions.lock().unwrap().len()
+    }
+
+    /// Return true if the store doesn't contain any session.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<T: SessionData> Default for MemoryStore<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T: SessionData> SessionStore<T> for MemoryStore<T> {
+    fn load(&self, id: &str) -> Option<T> {
+        let sessions = self.sessions.lock().unwrap();
+        sessions.get(id)
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(data, _)| data.clone())
+    }
+
+    fn save(&self, id: &str, data: &T, ttl: Duration) -> Result<(), String> {
+        if self.last_sweep.lock().unwrap().elapsed() >= self.sweep_interval {
+            self.sweep();
+        }
+        self.sessions.lock().unwrap().insert(id.to_string(), (data.clone(), Instant::now() + ttl));
+        Ok(())
+    }
+
+    fn destroy(&self, id: &str) {
+        self.sessions.lock().unwrap().remove(id);
+    }
+}
+
+/// Session store keeping each session in a file of the given directory, sessions are kept when the server restarts.
+///
+/// Expired sessions are removed during a save, at most once per sweep interval (default to 10 minutes).
+pub struct FileStore {
+    directory: PathBuf,
+    sweep_interval: Duration,
+    last_sweep: Mutex<Instant>,
+}
+
+impl FileStore {
+
+    /// Create a new FileStore saving the sessions in directory, the directory is created if it doesn't exist.
+    pub fn new(directory: PathBuf) -> io::Result<Self> {
+        Self::with_sweep_interval(directory, Duration::from_secs(600))
+    }
+
+    /// Create a new FileStore sweeping the expired sessions at most once per interval.
+    pub fn with_sweep_interval(directory: PathBuf, sweep_interval: Duration) -> io::Result<Self> {
+        fs::create_dir_all(&directory)?;
+        Ok(Self {
+            directory,
+            sweep_interval,
+            last_sweep: Mutex::new(Instant::now()),
+        })
+    }
+
+    /// Remove all the expired sessions.
+    ///
+    /// Only the session files (`*.session`) are read, the temporary files of the running saves and the other files are kept.
+    pub fn sweep(&self) {
+        *self.last_sweep.lock().unwrap() = Instant::now();
+        let entries = match fs::read_dir(&self.directory) {
+            Ok(entries) => entries,
+            Err(e) => {
+                eprintln!("Cannot sweep sessions in {}: {}", self.directory.display(), e);
+                return;
+            },
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.extension().is_none_or(|extension| extension != "session") {
+                continue;
+            }
+            let expired = fs::read_to_string(&path).ok()
+                .and_then(|content| Self::parse(&content).map(|(expires, _)| expires <= now()))
+                .unwrap_or(false);
+            if expired {
+                let _ = fs::remove_file(path);
+            }
+        }
+    }
+
+    /// Return the path of the file of the session, return None if the ID contains characters which could escape the directory.
+    fn path(&self, id: &str) -> Option<PathBuf> {
+        if !id.is_empty() && id.chars().all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_') {
+            Some(self.directory.join(format!("{}.session", id)))
+        } else {
+            None
+        }
+    }
+
+    /// Create a new file only readable by the server (the sessions contain user data) and write content to it.
+    fn write_private(path: &Path, content: &[u8]) -> io::Result<()> {
+        let mut options = OpenOptions::new();
+        options.write(true).create_new(true);
+        #[cfg(unix)]
+        std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+        options.open(path)?.write_all(content)
+    }
+
+    /// Parse the content of a session file, the first line is the expiration time in seconds since UNIX epoch and the rest is the data.
+    fn parse(content: &str) -> Option<(u64, &str)> {
+        let (expires, data) = content.split_once('\n')?;
+        Some((expires.parse().ok()?, data))
+    }
+}
+
+impl<T: SessionData> SessionStore<T> for FileStore {
+    fn load(&self, id: &str) -> Option<T> {
+        let content = fs::read_to_string(self.path(id)?).ok()?;
+        let (expires, data) = Self::parse(&content)?;
+        if expires <= now() {
+            return None;
+        }
+        T::decode(data)
+    }
+
+    fn save(&self, id: &str, data: &T, ttl: Duration) -> Result<(), String> {
+        if self.last_sweep.lock().unwrap().elapsed() >= self.sweep_interval {
+            self.sweep();
+        }
+        let path = self.path(id).ok_or_else(|| format!("Invalid session ID: {}", id))?;
+        // Write to a temporary file then rename it, so a concurrent load never reads a partial file,
+        // each save has its own temporary file so concurrent saves of the same session can't be mixed
+        let temporary = path.with_extension(format!("{}-{}.tmp", std::process::id(), TEMP_FILE_COUNTER.fetch_add(1, Ordering::SeqCst)));
+        let result = Self::write_private(&temporary, format!("{}\n{}", now() + ttl.as_secs(), data.encode()).as_bytes())
+            .and_then(|_| fs::rename(&temporary, &path));
+        if result.is_err() {
+            let _ = fs::remove_file(&temporary);
+        }
+        result.map_err(|e| format!("Cannot save session in {}: {}", path.display(), e))
+    }
+
+    fn destroy(&self, id: &str) {
+        if let Some(path) = self.path(id) {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Return the number of seconds since UNIX epoch.
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Session struct, the session of the client, added to the request extensions by the SessionMiddleware.
+///
+/// Session is a handle, clones share the same session.
+/// The data is saved by the middleware when the response is sent, a new session is only saved if its data has been set.
+///
+/// ## Example:
+/// ```
+/// use rest_server::request::Request;
+/// use rest_server::response::Response;
+/// use rest_server::session::Session;
+/// use std::collections::HashMap;
+///
+/// fn login(request: Request, mut response: Response) {
+///     let session = request.extensions().get::<Session<HashMap<String, String>>>().unwrap();
+///     // a new ID is given to the session when the user logs in, to prevent session fixation
+///     session.regenerate();
+///     session.update(|data| {
+///         data.insert(String::from("user"), String::from("alice"));
+///     });
+///     response.send();
+/// }
+/// ```
+pub struct Session<T> {
+    state: Arc<Mutex<SessionState<T>>>,
+}
+
+struct SessionState<T> {
+    id: String,
+    data: T,
+    is_new: bool,
+    modified: bool,
+    destroyed: bool,
+    replaced: Vec<String>,
+}
+
+impl<T: SessionData> Session<T> {
+
+    fn new(id: String, data: T, is_new: bool) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(SessionState {
+                id,
+                data,
+                is_new,
+                modified: false,
+                destroyed: false,
+                replaced: Vec::new(),
+            })),
+        }
+    }
+
+    /// Return the ID of the session.
+    pub fn id(&self) -> String {
+        self.state.lock().unwrap().id.clone()
+    }
+
+    /// Return true if the client didn't have a valid session before this request.
+    pub fn is_new(&self) -> bool {
+        self.state.lock().unwrap().is_new
+    }
+
+    /// Return a copy of the data of the session.
+    pub fn get(&self) -> T {
+        self.state.lock().unwrap().data.clone()
+    }
+
+    /// Replace the data of the session.
+    pub fn set(&self, data: T) {
+        let mut state = self.state.lock().unwrap();
+        state.data = data;
+        state.modified = true;
+    }
+
+    /// Modify the data of the session in place.
+    pub fn update<F: FnOnce(&mut T)>(&self, f: F) {
+        let mut state = self.state.lock().unwrap();
+        f(&mut state.data);
+        state.modified = true;
+    }
+
+    /// Give a new ID to the session and remove the old one from the store, the data is kept.
+    ///
+    /// Call it when the privileges of the user change (login, logout, etc.) so an attacker can't reuse an ID they know (session fixation).
+    pub fn regenerate(&self) {
+        let mut state = self.state.lock().unwrap();
+        let old = std::mem::replace(&mut state.id, new_session_id());
+        if !state.is_new {
+            state.replaced.push(old);
+        }
+        state.modified = true;
+    }
+
+    /// Remove the session from the store and ask the client to remove the session cookie.
+    pub fn destroy(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.destroyed = true;
+    }
+}
+
+impl<T> Clone for Session<T> {
+    fn clone(&self) -> Self {
+        Self {
+            state: Arc::clone(&self.state),
+        }
+    }
+}
+
+/// Return a new random session ID.
+fn new_session_id() -> String {
+    crypto::random_token(SESSION_ID_SIZE)
+}
+
+/// Middleware giving a Session<T> to every request, the session ID is kept in a cookie and the data in a SessionStore.
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::session::{MemoryStore, SessionMiddleware};
+/// use rest_server::Server;
+/// use std::collections::HashMap;
+/// use std::time::Duration;
+///
+/// let mut app = Server::new();
+/// let store: MemoryStore<HashMap<String, String>> = MemoryStore::new();
+/// app.middleware(Box::new(SessionMiddleware::new(Box::new(store)).ttl(Duration::from_secs(3600))));
+/// app.listen(7878).unwrap();
+/// ```
+pub struct SessionMiddleware<T: SessionData> {
+    store: Arc<dyn SessionStore<T>>,
+    ttl: Duration,
+    cookie: CookieSettings,
+}
+
+impl<T: SessionData> SessionMiddleware<T> {
+
+    /// Create a new SessionMiddleware saving the sessions in store.
+    ///
+    /// By default, the cookie is named "session_id", sessions expire after 24 hours without request,
+    /// and the cookie is sent for every path with the HttpOnly and SameSite=Lax attributes.
+    pub fn new(store: Box<dyn SessionStore<T>>) -> Self {
+        Self {
+            store: Arc::from(store),
+            ttl: Duration::from_secs(24 * 3600),
+            cookie: CookieSettings {
+                name: String::from("session_id"),
+                path: String::from("/"),
+                domain: None,
+                secure: false,
+                same_site: SameSite::Lax,
+            },
+        }
+    }
+
+    /// Set the name of the session cookie.
+    ///
+    /// Panic if the name isn't a valid cookie name, so an invalid name is caught when the server is configured.
+    pub fn cookie_name(mut self, cookie_name: String) -> Self {
+        if let Err(e) = Cookie::try_new(cookie_name.clone(), String::new()) {
+            panic!("{}", e);
+        }
+        self.cookie.name = cookie_name;
+        self
+    }
+
+    /// Set the time after which a session expires if the client doesn't make any request.
+    pub fn ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    /// Set the Path attribute of the session cookie.
+    ///
+    /// Panic if the path isn't a valid cookie path, see Cookie::try_path().
+    pub fn path(mut self, path: String) -> Self {
+        if let Err(e) = cookie::check_path(&path) {
+            panic!("{}", e);
+        }
+        self.cookie.path = path;
+        self
+    }
+
+    /// Set the Domain attribute of the session cookie.
+    ///
+    /// Panic if the domain isn't a valid cookie domain, see Cookie::try_domain().
+    pub fn domain(mut self, domain: String) -> Self {
+        if let Err(e) = cookie::check_domain(&domain) {
+            panic!("{}", e);
+        }
+        self.cookie.domain = Some(domain);
+        self
+    }
+
+    /// Set the Secure attribute of the session cookie, it should be enabled when the server is behind HTTPS.
+    pub fn secure(mut self, secure: bool) -> Self {
+        self.cookie.secure = secure;
+        self
+    }
+
+    /// Set the SameSite attribute of the session cookie.
+    pub fn same_site(mut self, same_site: SameSite) -> Self {
+        self.cookie.same_site = same_site;
+        self
+    }
+}
+
+impl<T: SessionData> Middleware for SessionMiddleware<T> {
+    fn handle(&self, mut request: Request, mut response: Response, next: Next) {
+        let loaded = request.cookies().get_all(&self.cookie.name).into_iter()
+            .find_map(|id| self.store.load(id).map(|data| (id.to_string(), data)));
+        let session = match loaded {
+            Some((id, data)) => Session::new(id, data, false),
+            None => Session::new(new_session_id(), T::default(), true),
+        };
+        request.extensions_mut().insert(session.clone());
+
+        let store = Arc::clone(&self.store);
+        let ttl = self.ttl;
+        let cookie = self.cookie.clone();
+        response.on_send(Box::new(move |response: &mut Response| {
+            let mut state = session.state.lock().unwrap();
+            for id in state.replaced.drain(..) {
+                store.destroy(&id);
+            }
+            if state.destroyed {
+                store.destroy(&state.id);
+                response.add_cookie(cookie.build(String::new()).max_age(Duration::ZERO).expires(UNIX_EPOCH));
+                return;
+            }
+            if state.is_new && !state.modified {
+                // don't create a session for the clients which don't use it
+                return;
+            }
+            // the session is saved on every request, so it only expires after ttl without request
+            match store.save(&state.id, &state.data, ttl) {
+                Ok(()) => response.add_cookie(cookie.build(state.id.clone()).max_age(ttl)),
+                Err(e) => eprintln!("{}", e),
+            }
+        }));
+        next.run(request, response);
+    }
+}
+
+/// Attributes of the session cookie.
+#[derive(Clone)]
+struct CookieSettings {
+    name: String,
+    path: String,
+    domain: Option<String>,
+    secure: bool,
+    same_site: SameSite,
+}
+
+impl CookieSettings {
+
+    /// Return the session cookie with the given value, the attributes have been validated by the SessionMiddleware builder.
+    fn build(&self, value: String) -> Cookie {
+        let cookie = Cookie::new(self.name.clone(), value)
+            .path(self.path.clone())
+            .secure(self.secure)
+            .http_only(true)
+            .same_site(self.same_site);
+        match &self.domain {
+            Some(domain) => cookie.domain(domain.clone()),
+            None => cookie,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::method::Method;
+    use crate::testing;
+    use std::thread;
+
+    type Data = HashMap<String, String>;
+
+    fn directory(name: &str) -> PathBuf {
+        let directory = std::env::temp_dir().join(format!("rest_server-test-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    fn data(value: &str) -> Data {
+        HashMap::from([(String::from("value"), String::from(value))])
+    }
+
+    #[test]
+    fn file_store_round_trip() {
+        let directory = directory("round-trip");
+        let store = FileStore::new(directory.clone()).unwrap();
+        SessionStore::<Data>::save(&store, "abc", &data("x y&z"), Duration::from_secs(60)).unwrap();
+        assert_eq!(SessionStore::<Data>::load(&store, "abc"), Some(data("x y&z")));
+        #[cfg(unix)]
+        {
+            use std::os::unix::fs::PermissionsExt;
+            let mode = fs::metadata(directory.join("abc.session")).unwrap().permissions().mode();
+            assert_eq!(mode & 0o777, 0o600);
+        }
+        SessionStore::<Data>::destroy(&store, "abc");
+        assert_eq!(SessionStore::<Data>::load(&store, "abc"), None);
+        assert!(SessionStore::<Data>::save(&store, "../escape", &data("x"), Duration::from_secs(60)).is_err());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn file_store_expires_sessions() {
+        let directory = directory("expire");
+        let store = FileStore::new(directory.clone()).unwrap();
+        SessionStore::<Data>::save(&store, "old", &data("x"), Duration::ZERO).unwrap();
+        assert_eq!(SessionStore::<Data>::load(&store, "old"), None);
+        store.sweep();
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 0);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn file_store_sweep_only_removes_sessions() {
+        let directory = directory("sweep-others");
+        let store = FileStore::new(directory.clone()).unwrap();
+        SessionStore::<Data>::save(&store, "old", &data("x"), Duration::ZERO).unwrap();
+        // a save in progress and an unrelated file, both starting with a past timestamp
+        fs::write(directory.join("old.1-0.tmp"), "1\nvalue=x").unwrap();
+        fs::write(directory.join("notes.txt"), "1\nkeep me").unwrap();
+        store.sweep();
+        let mut names = fs::read_dir(&directory).unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect::<Vec<String>>();
+        names.sort();
+        assert_eq!(names, vec!["notes.txt", "old.1-0.tmp"]);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn file_store_concurrent_saves() {
+        let directory = directory("concurrent");
+        let store = Arc::new(FileStore::new(directory.clone()).unwrap());
+        let threads = (0..8).map(|i| {
+            let store = Arc::clone(&store);
+            thread::spawn(move || {
+                let value = i.to_string().repeat(10000);
+                for _ in 0..20 {
+                    SessionStore::<Data>::save(&*store, "shared", &data(&value), Duration::from_secs(60)).unwrap();
+                }
+            })
+        }).collect::<Vec<_>>();
+        for thread in threads {
+            thread.join().unwrap();
+        }
+        // the file contains one of the saves, not a mix of several ones
+        let value = SessionStore::<Data>::load(&*store, "shared").unwrap().remove("value").unwrap();
+        assert_eq!(value.len(), 10000);
+        assert!(value.chars().all(|c| c == value.chars().next().unwrap()));
+        assert_eq!(fs::read_dir(&directory).unwrap().count(), 1);
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[test]
+    fn memory_store_expires_sessions() {
+        let store = MemoryStore::<Data>::with_sweep_interval(Duration::from_secs(3600));
+        store.save("old", &data("x"), Duration::ZERO).unwrap();
+        store.save("current", &data("y"), Duration::from_secs(60)).unwrap();
+        assert_eq!(store.load("old"), None);
+        assert_eq!(store.load("current"), Some(data("y")));
+        assert_eq!(store.len(), 2);
+        store.sweep();
+        assert_eq!(store.len(), 1);
+        assert_eq!(store.load("current"), Some(data("y")));
+
+        // a save sweeps the store once the interval has elapsed
+        let store = MemoryStore::<Data>::with_sweep_interval(Duration::ZERO);
+        store.save("old", &data("x"), Duration::ZERO).unwrap();
+        store.save("current", &data("y"), Duration::from_secs(60)).unwrap();
+        assert_eq!(store.len(), 1);
+    }
+
+    /// Store shared with the test, to check what the middleware saved.
+    struct Shared(Arc<MemoryStore<Data>>);
+
+    impl SessionStore<Data> for Shared {
+        fn load(&self, id: &str) -> Option<Data> {
+            self.0.load(id)
+        }
+
+        fn save(&self, id: &str, data: &Data, ttl: Duration) -> Result<(), String> {
+            self.0.save(id, data, ttl)
+        }
+
+        fn destroy(&self, id: &str) {
+            self.0.destroy(id)
+        }
+    }
+
+    /// Run the middleware, the handler logs in, regenerates or destroys the session depending on the path.
+    fn run_session(middleware: &Arc<dyn Middleware>, target: &str, cookie: Option<&str>) -> String {
+        let handler = |request: Request, mut response: Response| {
+            let session = request.extensions().get::<Session<Data>>().unwrap();
+            match request.path.get_path().as_str() {
+                "login" => session.set(data("alice")),
+                "regenerate" => session.regenerate(),
+                "logout" => session.destroy(),
+                _ => {},
+            }
+            response.set_body(&session.get().get("value").cloned().unwrap_or_default());
+            response.send();
+        };
+        let headers = cookie.map(|cookie| vec![("Cookie", cookie)]).unwrap_or_default();
+        let (response, client) = testing::response();
+        Next::new(std::slice::from_ref(middleware), &handler).run(testing::request(Method::GET, target, &headers), response);
+        testing::read_response(client)
+    }
+
+    /// Return the session ID set by the response.
+    fn session_id(response: &str) -> &str {
+        let cookie = testing::header(response, "Set-Cookie").unwrap();
+        cookie.split(';').next().unwrap().strip_prefix("session_id=").unwrap()
+    }
+
+    #[test]
+    fn regenerate_replaces_the_session_id() {
+        let store = Arc::new(MemoryStore::new());
+        let middleware: Arc<dyn Middleware> = Arc::new(SessionMiddleware::new(Box::new(Shared(Arc::clone(&store)))));
+        let response = run_session(&middleware, "/login", None);
+        let old = session_id(&response).to_string();
+
+        let response = run_session(&middleware, "/regenerate", Some(&format!("session_id={}", old)));
+        let new = session_id(&response).to_string();
+        assert_ne!(new, old);
+        assert!(response.ends_with("alice"));
+        assert_eq!(store.load(&old), None);
+        assert_eq!(store.load(&new), Some(data("alice")));
+        assert_eq!(store.len(), 1);
+
+        // the old ID doesn't give access to the session anymore
+        let response = run_session(&middleware, "/", Some(&format!("session_id={}", old)));
+        assert!(!response.ends_with("alice"));
+    }
+
+    #[test]
+    fn destroy_removes_the_session() {
+        let store = Arc::new(MemoryStore::new());
+        let middleware: Arc<dyn Middleware> = Arc::new(SessionMiddleware::new(Box::new(Shared(Arc::clone(&store)))));
+        let response = run_session(&middleware, "/login", None);
+        let id = session_id(&response).to_string();
+
+        let response = run_session(&middleware, "/logout", Some(&format!("session_id={}", id)));
+        let cookie = testing::header(&response, "Set-Cookie").unwrap();
+        assert!(cookie.starts_with("session_id=;"));
+        assert!(cookie.contains("; Max-Age=0"));
+        assert_eq!(store.load(&id), None);
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid cookie name")]
+    fn reject_invalid_cookie_name() {
+        let _ = SessionMiddleware::<Data>::new(Box::new(MemoryStore::new())).cookie_name(String::from("session id"));
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid cookie path")]
+    fn reject_invalid_cookie_path() {
+        let _ = SessionMiddleware::<Data>::new(Box::new(MemoryStore::new())).path(String::from("/app;"));
+    }
+
+    #[test]
+    fn middleware_saves_modified_sessions() {
+        let middleware: Arc<dyn Middleware> = Arc::new(SessionMiddleware::<Data>::new(Box::new(MemoryStore::new())));
+        let handler = |request: Request, mut response: Response| {
+            let session = request.extensions().get::<Session<Data>>().unwrap();
+            if request.path.get_path() == "login" {
+                session.set(data("alice"));
+            }
+            response.set_body(&session.get().get("value").cloned().unwrap_or_default());
+            response.send();
+        };
+
+        // no session is created if it isn't used
+        let (response, client) = testing::response();
+        Next::new(std::slice::from_ref(&middleware), &handler).run(testing::request(Method::GET, "/", &[]), response);
+        assert_eq!(testing::header(&testing::read_response(client), "Set-Cookie"), None);
+
+        let (response, client) = testing::response();
+        Next::new(std::slice::from_ref(&middleware), &handler).run(testing::request(Method::GET, "/login", &[]), response);
+        let content = testing::read_response(client);
+        let cookie = testing::header(&content, "Set-Cookie").unwrap().split(';').next().unwrap().to_string();
+        assert!(cookie.starts_with("session_id="));
+
+        let (response, client) = testing::response();
+        Next::new(std::slice::from_ref(&middleware), &handler).run(testing::request(Method::GET, "/", &[("Cookie", &cookie)]), response);
+        assert!(testing::read_response(client).ends_with("alice"));
+    }
+}
@@ -0,0 +1,185 @@
+use crate::connection::{Listener, Stream, Waker};
+use std::collections::HashMap;
+use std::io;
+use std::net;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// State of a registered connection.
+struct Entry {
+    stream: Stream,
+    /// true once the request has been given to the workers, false while its head is read.
+    active: bool,
+}
+
+/// ShutdownToken struct, stops a server from anywhere (another thread, a signal handler of the application, etc.),
+/// see Server::shutdown_token().
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::Server;
+/// use std::time::Duration;
+///
+/// let mut app = Server::new();
+/// let token = app.shutdown_token();
+/// std::thread::spawn(move || {
+///     std::thread::sleep(Duration::from_secs(60));
+///     token.shutdown();
+/// });
+/// // returns once the token is triggered and the running requests are done
+/// app.listen(7878).unwrap();
+/// ```
+#[derive(Clone)]
+pub struct ShutdownToken {
+    shutdown: Arc<Shutdown>,
+}
+
+impl ShutdownToken {
+
+    pub(crate) fn new(shutdown: Arc<Shutdown>) -> Self {
+        Self {
+            shutdown,
+        }
+    }
+
+    /// Start the graceful shutdown of the server: it stops accepting connections and the running requests are handled,
+    /// up to the shutdown timeout (see Server::set_shutdown_timeout()).
+    ///
+    /// Return immediately, the shutdown starts when the server starts if it isn't running yet.
+    pub fn shutdown(&self) {
+        self.shutdown.request();
+    }
+
+    /// Return true if the shutdown has started.
+    pub fn is_shutdown(&self) -> bool {
+        self.shutdown.is_requested()
+    }
+}
+
+impl std::fmt::Debug for ShutdownToken {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("ShutdownToken").field("is_shutdown", &self.is_shutdown()).finish()
+    }
+}
+
+/// Shutdown state of a server, shared by the accept loops, the workers and what stops the server (signals, ShutdownToken, ServerHandle).
+///
+/// The open connections are registered, so the idle ones can be closed as soon as the shutdown starts
+/// and the active ones can be drained then force-closed.
+pub(crate) struct Shutdown {
+    requested: AtomicBool,
+    wakers: Mutex<Vec<Waker>>,
+    connections: Mutex<(HashMap<u64, Entry>, u64)>,
+    closed: Condvar,
+}
+
+impl Shutdown {
+
+    /// Create the shutdown state of a server which isn't listening yet.
+    pub(crate) fn new() -> Self {
+        Self {
+            requested: AtomicBool::new(false),
+            wakers: Mutex::new(Vec::new()),
+            connections: Mutex::new((HashMap::new(), 0)),
+            closed: Condvar::new(),
+        }
+    }
+
+    /// Set the listeners woken up by the shutdown, if the shutdown has already started they are woken up immediately.
+    pub(crate) fn set_listeners(&self, listeners: &[Listener]) {
+        let mut wakers = self.wakers.lock().unwrap();
+        *wakers = listeners.iter().map(Listener::waker).collect();
+        if self.is_requested() {
+            for waker in wakers.iter() {
+                waker.wake();
+            }
+        }
+    }
+
+    /// Return true if the shutdown has started.
+    pub(crate) fn is_requested(&self) -> bool {
+        self.requested.load(Ordering::SeqCst)
+    }
+
+    /// Start the shutdown: wake up the accept loops so they stop accepting, and close the idle connections.
+    ///
+    /// Return false if the shutdown had already started.
+    pub(crate) fn request(&self) -> bool {
+        {
+            // the flag is set with the lock held, so no connection can be registered after the idle ones are closed
+            let connections = self.connections.lock().unwrap();
+            if self.requested.swap(true, Ordering::SeqCst) {
+                return false;
+            }
+            for entry in connections.0.values().filter(|entry| !entry.active) {
+                let _ = entry.stream.shutdown(net::Shutdown::Both);
+            }
+        }
+        for waker in self.wakers.lock().unwrap().iter() {
+            waker.wake();
+        }
+        true
+    }
+
+    /// Register a new connection, return None if the shutdown has started, the connection must be dropped in this case.
+    pub(crate) fn register(self: &Arc<Self>, stream: &Stream) -> io::Result<Option<ConnectionGuard>> {
+        let stream = stream.try_clone()?;
+        let mut connections = self.connections.lock().unwrap();
+        if self.is_requested() {
+            return Ok(None);
+        }
+        connections.1 += 1;
+        let id = connections.1;
+        connections.0.insert(id, Entry {
+            stream,
+            active: false,
+        });
+        Ok(Some(ConnectionGuard {
+            shutdown: Arc::clone(self),
+            id,
+        }))
+    }
+
+    /// Wait until every connection is closed, after the timeout the remaining connections are force-closed.
+    ///
+    /// Return the number of connections which have been force-closed.
+    pub(crate) fn drain(&self, timeout: Duration) -> usize {
+        let deadline = Instant::now() + timeout;
+        let mut connections = self.connections.lock().unwrap();
+        while !connections.0.is_empty() {
+            let now = Instant::now();
+            if now >= deadline {
+                for entry in connections.0.values() {
+                    let _ = entry.stream.shutdown(net::Shutdown::Both);
+                }
+                return connections.0.len();
+            }
+            connections = self.closed.wait_timeout(connections, deadline - now).unwrap().0;
+        }
+        0
+    }
+}
+
+/// Registration of an open connection, the connection is unregistered when the guard is dropped.
+pub(crate) struct ConnectionGuard {
+    shutdown: Arc<Shutdown>,
+    id: u64,
+}
+
+impl ConnectionGuard {
+
+    /// Mark the connection as active, it isn't closed before the end of the grace period anymore.
+    pub(crate) fn set_active(&self) {
+        if let Some(entry) = self.shutdown.connections.lock().unwrap().0.get_mut(&self.id) {
+            entry.active = true;
+        }
+    }
+}
+
+impl Drop for ConnectionGuard {
+    fn drop(&mut self) {
+        self.shutdown.connections.lock().unwrap().0.remove(&self.id);
+        self.shutdown.closed.notify_all();
+    }
+}
@@ -5,6 +5,7 @@
 /// See [RFC 7231](https://tools.ietf.org/html/rfc7231) and [RFC 6585](https://tools.ietf.org/html/rfc6585) for more information.
 /// A simplified version is also available [here](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status).
 /// About the TeaPot error please see [MDN](https://developer.mozilla.org/en-US/docs/Web/HTTP/Status/418) or [RFC 2324](https://tools.ietf.org/html/rfc2324)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[repr(u16)]
 pub enum Status {
     Continue = 100,
@@ -0,0 +1,260 @@
+use crate::connection::Listener;
+use socket2::{Socket, Type};
+use std::env;
+use std::io;
+use std::os::fd::{FromRawFd, OwnedFd, RawFd};
+use std::os::unix::net::{UnixDatagram, UnixListener};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
+
+/// First file descriptor passed by systemd (SD_LISTEN_FDS_START).
+const LISTEN_FDS_START: RawFd = 3;
+
+/// Set once the sockets passed by systemd have been taken, so they can't be owned twice.
+static LISTEN_FDS_TAKEN: AtomicBool = AtomicBool::new(false);
+
+/// ListenFd struct, a socket opened by systemd and inherited by the server.
+#[derive(Debug)]
+pub struct ListenFd {
+    /// Name of the socket (FileDescriptorName= in the .socket unit), None if systemd didn't give the names.
+    pub name: Option<String>,
+    /// The socket.
+    pub fd: OwnedFd,
+}
+
+/// Return the sockets passed by systemd socket activation (`LISTEN_PID`, `LISTEN_FDS` and `LISTEN_FDNAMES`, see sd_listen_fds(3)).
+///
+/// Return an empty Vec if the process wasn't started by socket activation. The sockets are only returned by the first call,
+/// the next calls return an empty Vec.
+///
+/// If unset_env is true, the environment variables are removed. Changing the environment is only sound while the process
+/// has a single thread, so only pass true at the beginning of main, before any thread is spawned.
+/// Otherwise the variables are kept, the child processes ignore them as `LISTEN_PID` isn't their PID.
+pub fn listen_fds(unset_env: bool) -> io::Result<Vec<ListenFd>> {
+    if LISTEN_FDS_TAKEN.swap(true, Ordering::SeqCst) {
+        return Ok(Vec::new());
+    }
+    let pid = env::var("LISTEN_PID").ok();
+    let count = env::var("LISTEN_FDS").ok();
+    let names = env::var("LISTEN_FDNAMES").ok();
+    if unset_env {
+        env::remove_var("LISTEN_PID");
+        env::remove_var("LISTEN_FDS");
+        env::remove_var("LISTEN_FDNAMES");
+    }
+
+    let (Some(pid), Some(count)) = (pid, count) else {
+        return Ok(Vec::new());
+    };
+    if pid.parse::<u32>().ok() != Some(std::process::id()) {
+        // the variables were meant for another process
+        return Ok(Vec::new());
+    }
+    let count = count.parse::<RawFd>().map_err(|_| io::Error::new(io::ErrorKind::InvalidData, format!("Invalid LISTEN_FDS: {}", count)))?;
+    let names = names.map(|names| names.split(':').map(String::from).collect::<Vec<String>>()).unwrap_or_default();
+    let mut fds = Vec::with_capacity(count.max(0) as usize);
+    for (index, fd) in (LISTEN_FDS_START..LISTEN_FDS_START.saturating_add(count)).enumerate() {
+        // SAFETY: systemd passes the sockets as file descriptors LISTEN_FDS_START..LISTEN_FDS_START + LISTEN_FDS,
+        // nothing else owns them as they are only taken once
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let socket = Socket::from(fd);
+        socket.set_cloexec(true)?;
+        fds.push(ListenFd {
+            name: names.get(index).cloned(),
+            fd: socket.into(),
+        });
+    }
+    Ok(fds)
+}
+
+/// Convert an inherited socket to a listener, return Err if it isn't a listening stream socket.
+pub(crate) fn listener_from_fd(fd: OwnedFd) -> io::Result<Listener> {
+    let socket = Socket::from(fd);
+    if socket.r#type()? != Type::STREAM {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, "The inherited file descriptor isn't a stream socket"));
+    }
+    socket.set_nonblocking(false)?;
+    let addr = socket.local_addr()?;
+    if addr.as_socket().is_some() {
+        Ok(Listener::Tcp(socket.into()))
+    } else if addr.is_unix() {
+        Ok(Listener::Unix(UnixListener::from(OwnedFd::from(socket))))
+    } else {
+        Err(io::Error::new(io::ErrorKind::InvalidInput, "The inherited socket isn't a TCP or a Unix socket"))
+    }
+}
+
+/// Notifier struct, sends the state of the server to the service manager (see sd_notify(3)).
+///
+/// The server sends READY=1 once it accepts the connections, STOPPING=1 when it stops,
+/// and WATCHDOG=1 at half the watchdog interval if the watchdog is enabled (WatchdogSec= in the service unit).
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::systemd::Notifier;
+/// use rest_server::Server;
+/// use std::path::PathBuf;
+///
+/// // with a fake notify socket, read the messages with: socat UNIX-RECV:/tmp/notify.sock STDOUT
+/// let mut app = Server::new();
+/// app.set_notifier(Notifier::new(PathBuf::from("/tmp/notify.sock")));
+/// app.listen(7878).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct Notifier {
+    path: PathBuf,
+    watchdog_interval: Option<Duration>,
+}
+
+impl Notifier {
+
+    /// Create a new Notifier sending the messages to the datagram socket at path, without watchdog.
+    ///
+    /// A path starting with `@` is a socket in the abstract namespace (Linux only).
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            watchdog_interval: None,
+        }
+    }
+
+    /// Create a Notifier from the environment set by systemd (`NOTIFY_SOCKET`, `WATCHDOG_USEC` and `WATCHDOG_PID`),
+    /// return None if the service manager doesn't expect notifications.
+    pub fn from_env() -> Option<Self> {
+        let path = env::var_os("NOTIFY_SOCKET")?;
+        let watchdog_pid = env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok());
+        let watchdog_interval = env::var("WATCHDOG_USEC").ok()
+            .and_then(|usec| usec.parse::<u64>().ok())
+            .filter(|usec| *usec > 0 && watchdog_pid.is_none_or(|pid| pid == std::process::id()))
+            .map(Duration::from_micros);
+        Some(Self {
+            path: PathBuf::from(path),
+            watchdog_interval,
+        })
+    }
+
+    /// Set the interval in which the service manager expects WATCHDOG=1 messages.
+    pub fn watchdog_interval(mut self, interval: Duration) -> Self {
+        self.watchdog_interval = Some(interval);
+        self
+    }
+
+    /// Return the watchdog interval, None if the watchdog is disabled.
+    pub fn get_watchdog_interval(&self) -> Option<Duration> {
+        self.watchdog_interval
+    }
+
+    /// Send a notification, state contains newline separated assignments (`READY=1`, `STATUS=...`, etc.).
+    pub fn notify(&self, state: &str) -> io::Result<()> {
+        let socket = UnixDatagram::unbound()?;
+        let path = self.path.to_string_lossy();
+        match path.strip_prefix('@') {
+            #[cfg(any(target_os = "linux", target_os = "android"))]
+            Some(name) => {
+                #[cfg(target_os = "android")]
+                use std::os::android::net::SocketAddrExt;
+                #[cfg(target_os = "linux")]
+                use std::os::linux::net::SocketAddrExt;
+                let addr = std::os::unix::net::SocketAddr::from_abstract_name(name.as_bytes())?;
+                socket.send_to_addr(state.as_bytes(), &addr)?;
+            },
+            #[cfg(not(any(target_os = "linux", target_os = "android")))]
+            Some(_) => return Err(io::Error::new(io::ErrorKind::Unsupported, "Abstract sockets are only supported on Linux")),
+            None => {
+                socket.send_to(state.as_bytes(), &self.path)?;
+            },
+        }
+        Ok(())
+    }
+
+    /// Tell the service manager that the server is ready.
+    pub fn ready(&self) -> io::Result<()> {
+        self.notify("READY=1")
+    }
+
+    /// Tell the service manager that the server is stopping.
+    pub fn stopping(&self) -> io::Result<()> {
+        self.notify("STOPPING=1")
+    }
+
+    /// Tell the service manager that the server is still alive.
+    pub fn watchdog(&self) -> io::Result<()> {
+        self.notify("WATCHDOG=1")
+    }
+
+    /// Send a status message, shown by systemctl status.
+    pub fn status(&self, status: &str) -> io::Result<()> {
+        self.notify(&format!("STATUS={}", status.replace('\n', " ")))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Server;
+    use std::fs;
+    use std::net::{TcpListener, UdpSocket};
+
+    /// Bind a fake notify socket in a new empty directory.
+    fn notify_socket(name: &str) -> (UnixDatagram, PathBuf) {
+        let dir = env::temp_dir().join(format!("rest_server-test-systemd-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("notify.sock");
+        let socket = UnixDatagram::bind(&path).unwrap();
+        socket.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+        (socket, path)
+    }
+
+    fn receive(socket: &UnixDatagram) -> String {
+        let mut buffer = [0; 256];
+        let size = socket.recv(&mut buffer).unwrap();
+        String::from_utf8(buffer[..size].to_vec()).unwrap()
+    }
+
+    #[test]
+    fn notifier_messages() {
+        let (socket, path) = notify_socket("messages");
+        let notifier = Notifier::new(path);
+        notifier.ready().unwrap();
+        assert_eq!(receive(&socket), "READY=1");
+        notifier.stopping().unwrap();
+        assert_eq!(receive(&socket), "STOPPING=1");
+        notifier.watchdog().unwrap();
+        assert_eq!(receive(&socket), "WATCHDOG=1");
+        notifier.status("Loading\nthe cache").unwrap();
+        assert_eq!(receive(&socket), "STATUS=Loading the cache");
+    }
+
+    #[test]
+    fn server_notifies_ready_and_stopping() {
+        let (socket, path) = notify_socket("server");
+        let mut app = Server::new();
+        app.set_notifier(Notifier::new(path));
+        let handle = app.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+        assert_eq!(receive(&socket), "READY=1");
+        handle.shutdown();
+        handle.join().unwrap();
+        assert_eq!(receive(&socket), "STOPPING=1");
+    }
+
+    #[test]
+    fn listener_from_tcp_fd() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        match listener_from_fd(OwnedFd::from(listener)).unwrap() {
+            Listener::Tcp(listener) => assert_eq!(listener.local_addr().unwrap(), addr),
+            _ => panic!("expected a TCP listener"),
+        }
+    }
+
+    #[test]
+    fn reject_udp_fd() {
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        match listener_from_fd(OwnedFd::from(socket)) {
+            Err(e) => assert_eq!(e.kind(), io::ErrorKind::InvalidInput),
+            Ok(_) => panic!("a UDP socket can't be a listener"),
+        }
+    }
+}
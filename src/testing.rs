@@ -0,0 +1,64 @@
+//! Helpers shared by the unit tests.
+use crate::method::Method;
+use crate::middleware::{Middleware, Next};
+use crate::request::{Request, RequestPath};
+use crate::response::Response;
+use std::io::Read;
+use std::net::{TcpListener, TcpStream};
+use std::sync::Arc;
+
+/// Create a request with the given headers and no body.
+pub(crate) fn request(method: Method, target: &str, headers: &[(&str, &str)]) -> Request {
+    let mut head = format!("{} {} HTTP/1.1", method, target);
+    for (name, value) in headers {
+        head.push_str(&format!("\r\n{}: {}", name, value));
+    }
+    Request::from_head(method, RequestPath::new(target.to_string()), &head)
+}
+
+/// Create a response connected to a client socket, read what the client receives with read_response().
+pub(crate) fn response() -> (Response, TcpStream) {
+    let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+    let client = TcpStream::connect(listener.local_addr().unwrap()).unwrap();
+    let (server, _) = listener.accept().unwrap();
+    (Response::new(server), client)
+}
+
+/// Read everything sent to the client, the response must have been dropped.
+pub(crate) fn read_response(mut client: TcpStream) -> String {
+    let mut content = String::new();
+    client.read_to_string(&mut content).unwrap();
+    content
+}
+
+/// Run the middleware with a handler answering "handled", return what the client receives.
+pub(crate) fn run(middleware: Arc<dyn Middleware>, request: Request) -> String {
+    let (response, client) = response();
+    let handler = |_request: Request, mut response: Response| {
+        response.set_body("handled");
+        response.send();
+    };
+    Next::new(&[middleware], &handler).run(request, response);
+    read_response(client)
+}
+
+/// Return the value of the first header with this name in a raw response.
+pub(crate) fn header<'a>(response: &'a str, name: &str) -> Option<&'a str> {
+    headers(response, name).into_iter().next()
+}
+
+/// Return the values of every header with this name in a raw response.
+pub(crate) fn headers<'a>(response: &'a str, name: &str) -> Vec<&'a str> {
+    response.split("\r\n\r\n").next().unwrap_or("")
+        .split("\r\n")
+        .skip(1)
+        .filter_map(|line| line.split_once(": "))
+        .filter(|(key, _)| key.eq_ignore_ascii_case(name))
+        .map(|(_, value)| value)
+        .collect()
+}
+
+/// Return the status line of a raw response.
+pub(crate) fn status(response: &str) -> &str {
+    response.split("\r\n").next().unwrap_or("")
+}
@@ -1,5 +1,6 @@
 use std::vec::Vec;
 use std::thread;
+use std::panic::{self, AssertUnwindSafe};
 use std::sync::{mpsc, Arc, Mutex};
 
 /// ThreadPool struct to manage multiple tasks in parallel.
@@ -29,7 +30,7 @@ impl ThreadPool {
 
     pub fn execute<F>(&self, f: F)
     where 
-        F: FnOnce() + Send + 'static + Sync, {
+        F: FnOnce() + Send + 'static, {
             let job = Box::new(f);
             self.sender.send(Message::NewJob(job)).unwrap();
         }
@@ -60,8 +61,10 @@ impl Worker {
             let message = receiver.lock().unwrap().recv().unwrap();
             match message {
                 Message::NewJob(job) => {
-                    // println!("Worker {} got a job to do", id);
-                    job();
+                    // a panicking handler mustn't kill the worker, the pool would shrink and its shutdown would panic
+                    if panic::catch_unwind(AssertUnwindSafe(job)).is_err() {
+                        eprintln!("A request handler panicked");
+                    }
                 }
                 Message::Terminate => {
                     break;
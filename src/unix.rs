@@ -0,0 +1,250 @@
+use crate::connection::PeerCredentials;
+use std::fs;
+use std::io;
+use std::os::fd::AsRawFd;
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::{UnixListener, UnixStream};
+use std::path::{Path, PathBuf};
+
+/// UnixSocket struct, a Unix domain socket on which the server listens, see Server::listen_unix().
+///
+/// ## Example:
+/// ```no_run
+/// use rest_server::unix::UnixSocket;
+/// use rest_server::Server;
+/// use std::path::PathBuf;
+///
+/// let mut app = Server::new();
+/// // nginx: proxy_pass http://unix:/run/my-api/api.sock;
+/// let socket = UnixSocket::new(PathBuf::from("/run/my-api/api.sock")).mode(0o660);
+/// app.listen_unix(socket).unwrap();
+/// ```
+#[derive(Debug, Clone)]
+pub struct UnixSocket {
+    path: PathBuf,
+    mode: Option<u32>,
+    owner: Option<u32>,
+    group: Option<u32>,
+    remove_stale: bool,
+}
+
+impl UnixSocket {
+
+    /// Create a new UnixSocket at the given path.
+    ///
+    /// By default, the permissions of the socket file depend on the umask of the process
+    /// and a stale socket file left by a previous run is removed.
+    pub fn new(path: PathBuf) -> Self {
+        Self {
+            path,
+            mode: None,
+            owner: None,
+            group: None,
+            remove_stale: true,
+        }
+    }
+
+    /// Return the path of the socket.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Set the permissions of the socket file (0o660 for example), a process needs the write permission to connect.
+    pub fn mode(mut self, mode: u32) -> Self {
+        self.mode = Some(mode);
+        self
+    }
+
+    /// Set the user owning the socket file, changing the owner usually requires to run as root.
+    pub fn owner(mut self, uid: u32) -> Self {
+        self.owner = Some(uid);
+        self
+    }
+
+    /// Set the group owning the socket file, the process must be a member of this group unless it runs as root.
+    pub fn group(mut self, gid: u32) -> Self {
+        self.group = Some(gid);
+        self
+    }
+
+    /// Set whether a socket file left by a previous run is removed before binding.
+    ///
+    /// The file is only removed if it's a socket on which nobody listens anymore, binding fails if another server uses it.
+    pub fn remove_stale(mut self, remove_stale: bool) -> Self {
+        self.remove_stale = remove_stale;
+        self
+    }
+
+    /// Create the socket and apply the permissions and the ownership.
+    ///
+    /// When they are set, the socket is created with the permissions 0o600 (the umask of the process is tightened during the bind,
+    /// the files created by other threads meanwhile get these permissions too), so nobody else can connect before they are applied.
+    /// Without mode, the permissions given by the umask are then restored. The socket file is removed if they can't be applied.
+    pub(crate) fn bind(&self) -> io::Result<UnixListener> {
+        if self.remove_stale {
+            self.remove_stale_socket()?;
+        }
+        let restricted = self.mode.is_some() || self.owner.is_some() || self.group.is_some();
+        let (listener, umask) = if restricted {
+            with_umask(0o177, || UnixListener::bind(&self.path))
+        } else {
+            (UnixListener::bind(&self.path), 0)
+        };
+        let listener = listener.map_err(|e| io::Error::new(e.kind(), format!("Cannot bind to {}: {}", self.path.display(), e)))?;
+        if restricted {
+            if let Err(e) = self.set_permissions(self.mode.unwrap_or(0o777 & !umask)) {
+                let _ = fs::remove_file(&self.path);
+                return Err(io::Error::new(e.kind(), format!("Cannot set the permissions of {}: {}", self.path.display(), e)));
+            }
+        }
+        Ok(listener)
+    }
+
+    /// Change the ownership of the socket file, then its permissions.
+    fn set_permissions(&self, mode: u32) -> io::Result<()> {
+        if self.owner.is_some() || self.group.is_some() {
+            std::os::unix::fs::chown(&self.path, self.owner, self.group)?;
+        }
+        fs::set_permissions(&self.path, fs::Permissions::from_mode(mode))
+    }
+
+    /// Remove the socket file if no server listens on it.
+    fn remove_stale_socket(&self) -> io::Result<()> {
+        let metadata = match fs::symlink_metadata(&self.path) {
+            Ok(metadata) => metadata,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(e) => return Err(e),
+        };
+        if !metadata.file_type().is_socket() {
+            // never remove a file which isn't a socket, the path is probably wrong
+            return Err(io::Error::new(io::ErrorKind::AlreadyExists, format!("{} exists and isn't a socket", self.path.display())));
+        }
+        match UnixStream::connect(&self.path) {
+            Ok(_) => Err(io::Error::new(io::ErrorKind::AddrInUse, format!("Another server listens on {}", self.path.display()))),
+            Err(e) if e.kind() == io::ErrorKind::ConnectionRefused => fs::remove_file(&self.path),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+/// Call f with the umask of the process set to mask, return its result and the previous umask.
+fn with_umask<T>(mask: u32, f: impl FnOnce() -> T) -> (T, u32) {
+    // SAFETY: umask can't fail, it only swaps the file mode creation mask of the process
+    let previous = unsafe { libc::umask(mask as libc::mode_t) };
+    let result = f();
+    // SAFETY: same as above
+    unsafe { libc::umask(previous) };
+    (result, previous as u32)
+}
+
+/// Return the credentials of the process connected to the stream.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) fn peer_credentials(stream: &UnixStream) -> Option<PeerCredentials> {
+    let mut credentials = libc::ucred { pid: 0, uid: 0, gid: 0 };
+    let mut length = std::mem::size_of::<libc::ucred>() as libc::socklen_t;
+    // SAFETY: credentials and length are valid for writes and length is the size of credentials
+    let result = unsafe {
+        libc::getsockopt(stream.as_raw_fd(), libc::SOL_SOCKET, libc::SO_PEERCRED, &mut credentials as *mut libc::ucred as *mut libc::c_void, &mut length)
+    };
+    if result != 0 {
+        return None;
+    }
+    Some(PeerCredentials {
+        pid: Some(credentials.pid),
+        uid: credentials.uid,
+        gid: credentials.gid,
+    })
+}
+
+/// Return the credentials of the process connected to the stream.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) fn peer_credentials(stream: &UnixStream) -> Option<PeerCredentials> {
+    let (mut uid, mut gid) = (0, 0);
+    // SAFETY: uid and gid are valid for writes
+    let result = unsafe { libc::getpeereid(stream.as_raw_fd(), &mut uid, &mut gid) };
+    if result != 0 {
+        return None;
+    }
+    Some(PeerCredentials {
+        pid: None,
+        uid,
+        gid,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::os::unix::fs::MetadataExt;
+    use std::sync::Mutex;
+
+    /// Held by the tests changing the umask, which is shared by the threads running the tests.
+    static UMASK: Mutex<()> = Mutex::new(());
+
+    /// Return a path for a socket in a new empty directory.
+    fn socket_path(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("rest_server-test-unix-{}-{}", std::process::id(), name));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir(&dir).unwrap();
+        dir.join("api.sock")
+    }
+
+    #[test]
+    fn apply_mode_and_keep_umask() {
+        let _lock = UMASK.lock().unwrap();
+        let path = socket_path("mode");
+        let (_, umask) = with_umask(0o022, || ());
+        let listener = UnixSocket::new(path.clone()).mode(0o660).bind().unwrap();
+        assert_eq!(fs::metadata(&path).unwrap().mode() & 0o777, 0o660);
+        // the umask of the process is restored after the bind
+        let (_, restored) = with_umask(umask, || ());
+        assert_eq!(restored, 0o022);
+        drop(listener);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restore_umask_permissions_when_only_the_group_changes() {
+        let _lock = UMASK.lock().unwrap();
+        let path = socket_path("group");
+        // SAFETY: getegid can't fail
+        let gid = unsafe { libc::getegid() };
+        let (listener, _) = with_umask(0o007, || UnixSocket::new(path.clone()).group(gid).bind().unwrap());
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.mode() & 0o777, 0o770);
+        assert_eq!(metadata.gid(), gid);
+        drop(listener);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn remove_socket_when_permissions_fail() {
+        // SAFETY: geteuid can't fail
+        if unsafe { libc::geteuid() } == 0 {
+            // root can give the socket to any user
+            return;
+        }
+        let _lock = UMASK.lock().unwrap();
+        let path = socket_path("chown");
+        assert!(UnixSocket::new(path.clone()).owner(0).bind().is_err());
+        assert!(!path.exists());
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn remove_only_stale_sockets() {
+        let path = socket_path("stale");
+        let listener = UnixSocket::new(path.clone()).bind().unwrap();
+        assert_eq!(UnixSocket::new(path.clone()).bind().unwrap_err().kind(), io::ErrorKind::AddrInUse);
+        drop(listener);
+        // the socket file is left behind, nobody listens on it anymore
+        assert!(UnixSocket::new(path.clone()).remove_stale(false).bind().is_err());
+        drop(UnixSocket::new(path.clone()).bind().unwrap());
+
+        fs::remove_file(&path).unwrap();
+        fs::write(&path, "not a socket").unwrap();
+        assert_eq!(UnixSocket::new(path.clone()).bind().unwrap_err().kind(), io::ErrorKind::AlreadyExists);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "not a socket");
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}